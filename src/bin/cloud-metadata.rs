@@ -1,13 +1,14 @@
 //! CLI binary for cloud-metadata crate.
 
 use std::io::{self, Write};
+use std::path::PathBuf;
 use std::process::ExitCode;
 
 use clap::{Parser, Subcommand};
 use cloud_metadata::{CloudMetadata, CloudProvider, MetadataError};
 
 /// Default metadata key for GCP instance attributes.
-/// AWS and Azure ignore this key.
+/// Other providers default to an empty key, which selects their user-data.
 const DEFAULT_METADATA_KEY: &str = "user-data-json";
 
 #[derive(Parser)]
@@ -29,9 +30,9 @@ enum Commands {
 
     /// Fetch custom metadata
     Fetch {
-        /// The metadata key (used by GCP, ignored by AWS/Azure)
-        #[arg(default_value = DEFAULT_METADATA_KEY)]
-        key: String,
+        /// The metadata key (GCP attribute, OpenStack `meta` entry, or
+        /// multipart part). Defaults to user-data-json on GCP
+        key: Option<String>,
 
         /// Explicitly specify the cloud provider instead of auto-detecting
         #[arg(short, long, value_parser = parse_provider)]
@@ -44,6 +45,61 @@ enum Commands {
         /// Maximum size in bytes to accept (fails if exceeded)
         #[arg(short, long)]
         max_size: Option<usize>,
+
+        /// Decode gzip, zstd and base64 encoded data
+        #[arg(short, long)]
+        decode: bool,
+
+        /// Local source for file-based providers (NoCloud seed directory,
+        /// OpenStack config drive mount point or image, Azure OVF file)
+        #[arg(long)]
+        path: Option<PathBuf>,
+    },
+
+    /// Fetch a raw metadata path with the provider's authentication applied
+    Get {
+        /// The metadata path, e.g. /latest/meta-data/placement/partition-number
+        metadata_path: String,
+
+        /// Explicitly specify the cloud provider instead of auto-detecting
+        #[arg(short, long, value_parser = parse_provider)]
+        provider: Option<CloudProvider>,
+
+        /// Maximum size in bytes to accept (fails if exceeded)
+        #[arg(short, long)]
+        max_size: Option<usize>,
+
+        /// Local source for file-based providers (NoCloud seed directory,
+        /// OpenStack config drive mount point or image)
+        #[arg(long)]
+        path: Option<PathBuf>,
+    },
+
+    /// List available metadata keys
+    List {
+        /// Explicitly specify the cloud provider instead of auto-detecting
+        #[arg(short, long, value_parser = parse_provider)]
+        provider: Option<CloudProvider>,
+
+        /// List GCP project attributes instead of instance attributes
+        #[arg(long)]
+        project: bool,
+
+        /// Local source for file-based providers (NoCloud seed directory,
+        /// OpenStack config drive mount point or image)
+        #[arg(long)]
+        path: Option<PathBuf>,
+    },
+
+    /// Fetch a GCP metadata directory recursively as JSON
+    Tree {
+        /// The directory below /computeMetadata/v1/, e.g. instance or project
+        #[arg(default_value = "instance")]
+        path: String,
+
+        /// Explicitly specify the cloud provider instead of auto-detecting
+        #[arg(short, long, value_parser = parse_provider)]
+        provider: Option<CloudProvider>,
     },
 }
 
@@ -73,8 +129,10 @@ fn parse_provider(s: &str) -> Result<CloudProvider, String> {
         "aws" => Ok(CloudProvider::Aws),
         "gcp" => Ok(CloudProvider::Gcp),
         "azure" => Ok(CloudProvider::Azure),
+        "nocloud" => Ok(CloudProvider::NoCloud),
+        "openstack" => Ok(CloudProvider::OpenStack),
         _ => Err(format!(
-            "unknown provider: {} (expected aws, gcp, or azure)",
+            "unknown provider: {} (expected aws, gcp, azure, nocloud, or openstack)",
             s
         )),
     }
@@ -93,6 +151,24 @@ async fn main() -> ExitCode {
     }
 }
 
+/// Build a metadata client for the given provider, or detect it.
+async fn metadata(
+    provider: Option<CloudProvider>,
+    path: Option<PathBuf>,
+) -> Result<CloudMetadata, MetadataError> {
+    Ok(match (provider, path) {
+        (Some(CloudProvider::Aws), _) => CloudMetadata::aws(),
+        (Some(CloudProvider::Gcp), _) => CloudMetadata::gcp(),
+        (Some(CloudProvider::Azure), Some(path)) => CloudMetadata::azure().with_ovf_path(path),
+        (Some(CloudProvider::Azure), None) => CloudMetadata::azure(),
+        (Some(CloudProvider::NoCloud), Some(path)) => CloudMetadata::nocloud_with_path(path),
+        (Some(CloudProvider::NoCloud), None) => CloudMetadata::nocloud(),
+        (Some(CloudProvider::OpenStack), Some(path)) => CloudMetadata::openstack_with_path(path),
+        (Some(CloudProvider::OpenStack), None) => CloudMetadata::openstack(),
+        (None, _) => CloudMetadata::detect().await?,
+    })
+}
+
 async fn run(cli: Cli) -> Result<(), MetadataError> {
     match cli.command {
         Commands::Detect => {
@@ -106,19 +182,23 @@ async fn run(cli: Cli) -> Result<(), MetadataError> {
             provider,
             format,
             max_size,
+            decode,
+            path,
         } => {
-            let metadata = match provider {
-                Some(CloudProvider::Aws) => CloudMetadata::aws(),
-                Some(CloudProvider::Gcp) => CloudMetadata::gcp(),
-                Some(CloudProvider::Azure) => CloudMetadata::azure(),
-                None => CloudMetadata::detect().await?,
-            };
+            let metadata = metadata(provider, path)
+                .await?
+                .with_user_data_decoding(decode);
 
             let metadata = match max_size {
                 Some(size) => metadata.with_max_size(size),
                 None => metadata,
             };
 
+            let key = key.unwrap_or_else(|| match metadata.provider() {
+                CloudProvider::Gcp => DEFAULT_METADATA_KEY.to_string(),
+                _ => String::new(),
+            });
+
             match format {
                 OutputFormat::Text => {
                     let text = metadata.custom_text(&key).await?;
@@ -135,5 +215,45 @@ async fn run(cli: Cli) -> Result<(), MetadataError> {
             }
             Ok(())
         }
+
+        Commands::Get {
+            metadata_path,
+            provider,
+            max_size,
+            path,
+        } => {
+            let metadata = metadata(provider, path).await?;
+            let metadata = match max_size {
+                Some(size) => metadata.with_max_size(size),
+                None => metadata,
+            };
+            let data = metadata.get_path(&metadata_path).await?;
+            io::stdout().write_all(&data)?;
+            Ok(())
+        }
+
+        Commands::List {
+            provider,
+            project,
+            path,
+        } => {
+            let metadata = metadata(provider, path).await?;
+            let keys = if project {
+                metadata.list_project_keys().await?
+            } else {
+                metadata.list_keys().await?
+            };
+            for key in keys {
+                println!("{}", key);
+            }
+            Ok(())
+        }
+
+        Commands::Tree { path, provider } => {
+            let metadata = metadata(provider, None).await?;
+            let tree: serde_json::Value = metadata.tree(&path).await?;
+            println!("{}", serde_json::to_string_pretty(&tree)?);
+            Ok(())
+        }
     }
 }
@@ -20,6 +20,7 @@ pub const DEFAULT_BASE_URL: &str = "http://169.254.169.254";
 pub struct MetadataClient {
     inner: Client,
     base_url: String,
+    timeout: Duration,
 }
 
 impl MetadataClient {
@@ -32,6 +33,7 @@ impl MetadataClient {
         Ok(Self {
             inner,
             base_url: base_url.trim_end_matches('/').to_string(),
+            timeout,
         })
     }
 
@@ -55,12 +57,46 @@ impl MetadataClient {
         &self.inner
     }
 
+    /// Get the request timeout.
+    pub fn timeout(&self) -> Duration {
+        self.timeout
+    }
+
     /// Get the base URL.
     pub fn base_url(&self) -> &str {
         &self.base_url
     }
 }
 
+/// Percent-encode a value for use as a single URL path segment.
+///
+/// Only unreserved characters are kept, so `/`, `?` and `#` cannot change
+/// the request path.
+pub fn encode_path_segment(value: &str) -> String {
+    let mut encoded = String::with_capacity(value.len());
+    for byte in value.bytes() {
+        if byte.is_ascii_alphanumeric() || matches!(byte, b'-' | b'.' | b'_' | b'~') {
+            encoded.push(byte as char);
+        } else {
+            encoded.push_str(&format!("%{:02X}", byte));
+        }
+    }
+    encoded
+}
+
+/// Check that a raw metadata path is absolute.
+///
+/// Paths are appended to the base URL, so one that does not start with `/`,
+/// such as `@other.host/`, could send the request and its credentials to a
+/// different host.
+pub fn check_absolute_path(path: &str) -> Result<(), MetadataError> {
+    if path.starts_with('/') {
+        Ok(())
+    } else {
+        Err(MetadataError::InvalidKey(path.to_string()))
+    }
+}
+
 /// Read response body with an optional size limit.
 ///
 /// If `max_size` is `Some`, this will:
@@ -129,6 +165,25 @@ mod tests {
         assert_eq!(DETECTION_TIMEOUT, Duration::from_millis(500));
     }
 
+    #[test]
+    fn test_encode_path_segment() {
+        assert_eq!(encode_path_segment("Name"), "Name");
+        assert_eq!(encode_path_segment("Cost Center"), "Cost%20Center");
+        assert_eq!(encode_path_segment("a/b?c#d"), "a%2Fb%3Fc%23d");
+        assert_eq!(encode_path_segment("a+b=c:d@e"), "a%2Bb%3Dc%3Ad%40e");
+    }
+
+    #[test]
+    fn test_check_absolute_path() {
+        assert!(check_absolute_path("/latest/meta-data/").is_ok());
+        for path in ["", "latest", "@evil.example/", ".evil.example/", ":8080/"] {
+            assert!(matches!(
+                check_absolute_path(path),
+                Err(MetadataError::InvalidKey(_))
+            ));
+        }
+    }
+
     #[test]
     fn test_default_base_url() {
         assert_eq!(DEFAULT_BASE_URL, "http://169.254.169.254");
@@ -138,6 +193,7 @@ mod tests {
     fn test_client_creation() {
         let client = MetadataClient::with_default_timeout().unwrap();
         assert_eq!(client.base_url(), DEFAULT_BASE_URL);
+        assert_eq!(client.timeout(), DEFAULT_TIMEOUT);
     }
 
     #[test]
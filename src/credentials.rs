@@ -0,0 +1,130 @@
+//! Caching of expiring credentials for the token providers.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::future::Future;
+use std::hash::Hash;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+use tokio::sync::Mutex;
+
+use crate::error::MetadataError;
+
+/// Credentials that stop being valid at a known time.
+pub(crate) trait Expiring {
+    /// When the credentials expire.
+    fn expires_at(&self) -> SystemTime;
+
+    /// Whether the credentials expire within `margin` of `now`.
+    fn expires_within(&self, margin: Duration, now: SystemTime) -> bool {
+        self.expires_at()
+            .duration_since(now)
+            .map_or(true, |remaining| remaining <= margin)
+    }
+}
+
+/// Credentials cached per key until they are within the refresh margin of
+/// their expiry.
+///
+/// Clones share the cache. The cache stays locked while fetching, so
+/// concurrent callers wait for a single request instead of racing.
+#[derive(Clone)]
+pub(crate) struct CredentialCache<K, V> {
+    refresh_margin: Duration,
+    entries: Arc<Mutex<HashMap<K, V>>>,
+}
+
+impl<K, V> fmt::Debug for CredentialCache<K, V> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CredentialCache")
+            .field("refresh_margin", &self.refresh_margin)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<K: Eq + Hash, V: Expiring + Clone> CredentialCache<K, V> {
+    /// Create an empty cache refreshing `refresh_margin` before expiry.
+    pub(crate) fn new(refresh_margin: Duration) -> Self {
+        Self {
+            refresh_margin,
+            entries: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    /// Set how long before expiry entries are refreshed.
+    pub(crate) fn with_refresh_margin(mut self, refresh_margin: Duration) -> Self {
+        self.refresh_margin = refresh_margin;
+        self
+    }
+
+    /// Return the cached credentials for `key`, or fetch and cache new ones.
+    ///
+    /// `fetch` gets the expiring entry being replaced, if any.
+    pub(crate) async fn get<F, Fut>(&self, key: K, fetch: F) -> Result<V, MetadataError>
+    where
+        F: FnOnce(Option<V>) -> Fut,
+        Fut: Future<Output = Result<V, MetadataError>>,
+    {
+        let mut entries = self.entries.lock().await;
+        let stale = match entries.get(&key) {
+            Some(value) if !value.expires_within(self.refresh_margin, SystemTime::now()) => {
+                return Ok(value.clone());
+            }
+            stale => stale.cloned(),
+        };
+
+        let value = fetch(stale).await?;
+        entries.insert(key, value.clone());
+        Ok(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Clone)]
+    struct Token(SystemTime);
+
+    impl Expiring for Token {
+        fn expires_at(&self) -> SystemTime {
+            self.0
+        }
+    }
+
+    #[test]
+    fn test_expires_within() {
+        let now = SystemTime::UNIX_EPOCH + Duration::from_secs(1_000);
+        let token = Token(now + Duration::from_secs(600));
+        assert!(!token.expires_within(Duration::from_secs(300), now));
+        assert!(token.expires_within(Duration::from_secs(600), now));
+        assert!(token.expires_within(Duration::ZERO, now + Duration::from_secs(700)));
+    }
+
+    #[tokio::test]
+    async fn test_cache_refreshes_expiring_entries() {
+        let now = SystemTime::now();
+        let cache = CredentialCache::new(Duration::from_secs(60));
+
+        let fresh = Token(now + Duration::from_secs(3_600));
+        let token = cache.get("a", |_| async { Ok(fresh) }).await.unwrap();
+        let cached = cache
+            .get("a", |_| async { panic!("fetched a cached entry") })
+            .await
+            .unwrap();
+        assert_eq!(cached.0, token.0);
+
+        let expiring = Token(now + Duration::from_secs(30));
+        cache.get("b", |_| async { Ok(expiring) }).await.unwrap();
+        let refreshed = cache
+            .clone()
+            .get("b", |stale| async move {
+                assert!(stale.is_some());
+                Ok(Token(now + Duration::from_secs(3_600)))
+            })
+            .await
+            .unwrap();
+        assert_eq!(refreshed.0, now + Duration::from_secs(3_600));
+    }
+}
@@ -45,11 +45,45 @@ pub enum MetadataError {
     #[error("operation not supported for this provider")]
     NotSupported,
 
+    /// Credentials or tokens could not be obtained.
+    #[error("credentials unavailable: {0}")]
+    Credentials(String),
+
+    /// A signed document or token failed verification.
+    #[error("verification failed: {0}")]
+    Verification(String),
+
+    /// The IMDSv2 token request timed out, but the metadata service is
+    /// reachable. Usually the response was dropped by the hop limit.
+    #[error(
+        "IMDSv2 token request timed out but the metadata service is reachable; \
+         the PUT response hop limit is probably too low for containers \
+         (set HttpPutResponseHopLimit to 2)"
+    )]
+    HopLimit,
+
+    /// Compressed user-data could not be decompressed.
+    #[error("decompression failed: {0}")]
+    Decompress(String),
+
+    /// A key or name contains characters the metadata service does not accept.
+    #[error("invalid key: {0}")]
+    InvalidKey(String),
+
     /// Response exceeds maximum allowed size.
     #[error("response too large: {0} bytes exceeds limit of {1} bytes")]
     TooLarge(usize, usize),
 }
 
+/// Treat `MetadataError::NotFound` as an absent value.
+pub(crate) fn optional<T>(result: Result<T, MetadataError>) -> Result<Option<T>, MetadataError> {
+    match result {
+        Ok(value) => Ok(Some(value)),
+        Err(MetadataError::NotFound) => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -65,9 +99,20 @@ mod tests {
         assert_eq!(MetadataError::Http(404).to_string(), "http 404");
         assert_eq!(MetadataError::Base64.to_string(), "base64 decode failed");
         assert_eq!(MetadataError::Utf8.to_string(), "invalid utf-8");
+        assert_eq!(
+            MetadataError::Decompress("corrupt deflate stream".to_string()).to_string(),
+            "decompression failed: corrupt deflate stream"
+        );
         assert_eq!(
             MetadataError::NotSupported.to_string(),
             "operation not supported for this provider"
         );
     }
+
+    #[test]
+    fn test_optional() {
+        assert_eq!(optional(Ok::<_, MetadataError>(1)).unwrap(), Some(1));
+        assert_eq!(optional::<u8>(Err(MetadataError::NotFound)).unwrap(), None);
+        assert!(optional::<u8>(Err(MetadataError::Timeout)).is_err());
+    }
 }
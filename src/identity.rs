@@ -0,0 +1,88 @@
+//! Provider-neutral instance identity.
+
+use serde::Serialize;
+
+/// Identity of the running instance, normalized across providers.
+///
+/// Fields a provider does not expose are `None`.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct InstanceIdentity {
+    /// Instance id (AWS instance id, GCP numeric id, Azure vmId).
+    pub instance_id: Option<String>,
+    /// Region name, e.g. `us-east-1`, `us-central1` or `eastus`.
+    pub region: Option<String>,
+    /// Zone name, e.g. `us-east-1a`, `us-central1-a` or `eastus-1`.
+    pub zone: Option<String>,
+    /// Machine type (AWS instance type, GCP machine type, Azure VM size).
+    pub machine_type: Option<String>,
+    /// Hostname of the instance.
+    pub hostname: Option<String>,
+    /// Account id (AWS account, GCP project id, Azure subscription id).
+    pub account_id: Option<String>,
+    /// Image the instance was created from.
+    pub image_id: Option<String>,
+}
+
+/// Return the last segment of a slash-separated resource name.
+///
+/// GCP reports zones and machine types as full resource names such as
+/// `projects/123/zones/us-central1-a`.
+pub(crate) fn last_segment(name: &str) -> &str {
+    name.rsplit('/').next().unwrap_or(name)
+}
+
+/// Derive a GCP region from a zone name (`us-central1-a` -> `us-central1`).
+pub(crate) fn gcp_region_from_zone(zone: &str) -> Option<&str> {
+    zone.rsplit_once('-').map(|(region, _)| region)
+}
+
+/// Normalize an Azure availability zone (`1`) to `<location>-<zone>`.
+///
+/// This matches the zone names used by Kubernetes topology labels. Regions
+/// without zones report an empty zone, which maps to `None`.
+pub(crate) fn azure_zone(location: &str, zone: &str) -> Option<String> {
+    if location.is_empty() || zone.is_empty() {
+        None
+    } else {
+        Some(format!("{}-{}", location, zone))
+    }
+}
+
+/// Convert an empty string to `None`.
+pub(crate) fn non_empty(value: &str) -> Option<String> {
+    let value = value.trim();
+    (!value.is_empty()).then(|| value.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_last_segment() {
+        assert_eq!(
+            last_segment("projects/123/zones/us-central1-a"),
+            "us-central1-a"
+        );
+        assert_eq!(last_segment("e2-medium"), "e2-medium");
+    }
+
+    #[test]
+    fn test_gcp_region_from_zone() {
+        assert_eq!(gcp_region_from_zone("us-central1-a"), Some("us-central1"));
+        assert_eq!(gcp_region_from_zone("europe-west4-b"), Some("europe-west4"));
+        assert_eq!(gcp_region_from_zone("nozone"), None);
+    }
+
+    #[test]
+    fn test_azure_zone() {
+        assert_eq!(azure_zone("eastus", "1").as_deref(), Some("eastus-1"));
+        assert_eq!(azure_zone("eastus", ""), None);
+    }
+
+    #[test]
+    fn test_non_empty() {
+        assert_eq!(non_empty(" a ").as_deref(), Some("a"));
+        assert_eq!(non_empty(""), None);
+    }
+}
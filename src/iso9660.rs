@@ -0,0 +1,509 @@
+//! Minimal read-only ISO9660 reader.
+//!
+//! Supports looking up files by path in an image file or block device, so
+//! config drives can be read without mounting them. Long file names are taken
+//! from the Joliet supplementary volume descriptor when present, otherwise
+//! from Rock Ridge `NM` entries, falling back to plain ISO9660 names.
+
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::Path;
+
+use crate::error::MetadataError;
+use crate::local::map_io_error;
+
+/// Logical sector size.
+const SECTOR_SIZE: u64 = 2048;
+
+/// First sector of the volume descriptor set.
+const VOLUME_DESCRIPTOR_START: u64 = 16;
+
+/// Upper bound on volume descriptors scanned before giving up.
+const MAX_VOLUME_DESCRIPTORS: u64 = 32;
+
+/// Standard identifier present in every volume descriptor.
+const STANDARD_IDENTIFIER: &[u8] = b"CD001";
+
+/// Primary volume descriptor type.
+const PRIMARY_DESCRIPTOR: u8 = 1;
+
+/// Supplementary volume descriptor type (used by Joliet).
+const SUPPLEMENTARY_DESCRIPTOR: u8 = 2;
+
+/// Volume descriptor set terminator type.
+const TERMINATOR_DESCRIPTOR: u8 = 255;
+
+/// Offset of the root directory record within a volume descriptor.
+const ROOT_RECORD_OFFSET: usize = 156;
+
+/// Upper bound on the size of a directory read from an image.
+///
+/// Config drive directories hold a handful of entries; the limit keeps a
+/// corrupt size field from allocating up to 4 GiB.
+const MAX_DIRECTORY_SIZE: u32 = 1024 * 1024;
+
+/// Upper bound on the size of a file read without an explicit limit.
+///
+/// Config drive files are small; like `MAX_DIRECTORY_SIZE`, this keeps a
+/// corrupt size field from allocating up to 4 GiB.
+const MAX_FILE_SIZE: usize = 16 * 1024 * 1024;
+
+/// Directory flag in a directory record.
+const FLAG_DIRECTORY: u8 = 0x02;
+
+/// How file names are encoded in a directory hierarchy.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Names {
+    /// UCS-2 big endian names from a Joliet descriptor.
+    Joliet,
+    /// ISO9660 names, possibly overridden by Rock Ridge `NM` entries.
+    Iso,
+}
+
+/// A directory record reduced to the fields needed for lookups.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Entry {
+    name: String,
+    extent: u32,
+    size: u32,
+    is_dir: bool,
+}
+
+/// Read the file at `path` (e.g. `openstack/latest/user_data`) from an image.
+///
+/// The lookup runs on the blocking thread pool since image files and block
+/// devices are read with synchronous I/O.
+pub async fn read_file(
+    image: &Path,
+    path: &str,
+    max_size: Option<usize>,
+) -> Result<Vec<u8>, MetadataError> {
+    let image = image.to_path_buf();
+    let path = path.to_string();
+    tokio::task::spawn_blocking(move || {
+        let mut file = File::open(&image).map_err(map_io_error)?;
+        read_file_from(&mut file, &path, max_size)
+    })
+    .await
+    .map_err(|e| MetadataError::Io(std::io::Error::other(e)))?
+}
+
+/// Read the file at `path` from an ISO9660 image.
+fn read_file_from<R: Read + Seek>(
+    reader: &mut R,
+    path: &str,
+    max_size: Option<usize>,
+) -> Result<Vec<u8>, MetadataError> {
+    let (root, names) = find_root(reader)?;
+
+    let mut current = root;
+    for component in path.split('/').filter(|c| !c.is_empty()) {
+        if !current.is_dir {
+            return Err(MetadataError::NotFound);
+        }
+        current = read_dir(reader, &current, names)?
+            .into_iter()
+            .find(|entry| name_matches(&entry.name, component, names))
+            .ok_or(MetadataError::NotFound)?;
+    }
+    if current.is_dir {
+        return Err(MetadataError::NotFound);
+    }
+
+    let size = current.size as usize;
+    let max_size = max_size.unwrap_or(MAX_FILE_SIZE);
+    if size > max_size {
+        return Err(MetadataError::TooLarge(size, max_size));
+    }
+
+    let start = u64::from(current.extent) * SECTOR_SIZE;
+    let image_size = reader.seek(SeekFrom::End(0))?;
+    if start + size as u64 > image_size {
+        return Err(invalid("file extends past the end of the image"));
+    }
+
+    let mut data = vec![0u8; size];
+    reader.seek(SeekFrom::Start(start))?;
+    reader.read_exact(&mut data)?;
+    Ok(data)
+}
+
+/// Locate the root directory, preferring the Joliet hierarchy.
+fn find_root<R: Read + Seek>(reader: &mut R) -> Result<(Entry, Names), MetadataError> {
+    let mut primary = None;
+    let mut sector = [0u8; SECTOR_SIZE as usize];
+
+    for index in 0..MAX_VOLUME_DESCRIPTORS {
+        reader.seek(SeekFrom::Start(
+            (VOLUME_DESCRIPTOR_START + index) * SECTOR_SIZE,
+        ))?;
+        reader.read_exact(&mut sector)?;
+        if &sector[1..6] != STANDARD_IDENTIFIER {
+            if index == 0 {
+                return Err(invalid("unsupported filesystem, not an ISO9660 image"));
+            }
+            return Err(invalid("missing volume descriptor"));
+        }
+
+        let root = || {
+            parse_record(&sector[ROOT_RECORD_OFFSET..], Names::Iso)
+                .map(|(entry, _)| entry)
+                .ok_or_else(|| invalid("invalid root directory record"))
+        };
+        match sector[0] {
+            PRIMARY_DESCRIPTOR => primary = Some(root()?),
+            SUPPLEMENTARY_DESCRIPTOR if is_joliet(&sector) => return Ok((root()?, Names::Joliet)),
+            TERMINATOR_DESCRIPTOR => break,
+            _ => {}
+        }
+    }
+
+    primary
+        .map(|root| (root, Names::Iso))
+        .ok_or_else(|| invalid("no primary volume descriptor"))
+}
+
+/// Check the escape sequences of a supplementary descriptor for Joliet.
+fn is_joliet(sector: &[u8]) -> bool {
+    matches!(&sector[88..91], b"%/@" | b"%/C" | b"%/E")
+}
+
+/// Read all entries of a directory, skipping `.` and `..`.
+fn read_dir<R: Read + Seek>(
+    reader: &mut R,
+    dir: &Entry,
+    names: Names,
+) -> Result<Vec<Entry>, MetadataError> {
+    if dir.size > MAX_DIRECTORY_SIZE {
+        return Err(invalid("directory too large"));
+    }
+    let mut data = vec![0u8; dir.size as usize];
+    reader.seek(SeekFrom::Start(u64::from(dir.extent) * SECTOR_SIZE))?;
+    reader.read_exact(&mut data)?;
+
+    let mut entries = Vec::new();
+    let mut offset = 0;
+    while offset < data.len() {
+        // Records never span sectors; a zero length pads to the next sector
+        if data[offset] == 0 {
+            offset = (offset / SECTOR_SIZE as usize + 1) * SECTOR_SIZE as usize;
+            continue;
+        }
+        let (entry, len) = parse_record(&data[offset..], names)
+            .ok_or_else(|| invalid("invalid directory record"))?;
+        if !entry.name.is_empty() {
+            entries.push(entry);
+        }
+        offset += len;
+    }
+
+    Ok(entries)
+}
+
+/// Parse a directory record, returning the entry and the record length.
+///
+/// The `.` and `..` records are returned with an empty name.
+fn parse_record(data: &[u8], names: Names) -> Option<(Entry, usize)> {
+    let len = *data.first()? as usize;
+    let name_len = *data.get(32)? as usize;
+    // An even-length name is followed by a padding byte
+    let pad = 1 - name_len % 2;
+    if len < 33 + name_len + pad || data.len() < len {
+        return None;
+    }
+    let extent = u32::from_le_bytes(data[2..6].try_into().ok()?);
+    let size = u32::from_le_bytes(data[10..14].try_into().ok()?);
+    let is_dir = data[25] & FLAG_DIRECTORY != 0;
+    let raw_name = &data[33..33 + name_len];
+
+    let name = if raw_name == [0] || raw_name == [1] {
+        String::new()
+    } else {
+        match names {
+            Names::Joliet => {
+                let units: Vec<u16> = raw_name
+                    .chunks_exact(2)
+                    .map(|c| u16::from_be_bytes([c[0], c[1]]))
+                    .collect();
+                String::from_utf16_lossy(&units)
+            }
+            Names::Iso => {
+                // System use area starts after the name and its padding byte
+                let system_use = &data[33 + name_len + pad..len];
+                rock_ridge_name(system_use)
+                    .unwrap_or_else(|| String::from_utf8_lossy(raw_name).into_owned())
+            }
+        }
+    };
+
+    Some((
+        Entry {
+            name,
+            extent,
+            size,
+            is_dir,
+        },
+        len,
+    ))
+}
+
+/// Extract the alternate name from Rock Ridge `NM` entries.
+fn rock_ridge_name(mut system_use: &[u8]) -> Option<String> {
+    let mut name = Vec::new();
+    while system_use.len() >= 4 {
+        let len = system_use[2] as usize;
+        if len < 4 || len > system_use.len() {
+            break;
+        }
+        if &system_use[..2] == b"NM" && len >= 5 {
+            name.extend_from_slice(&system_use[5..len]);
+        }
+        system_use = &system_use[len..];
+    }
+    (!name.is_empty()).then(|| String::from_utf8_lossy(&name).into_owned())
+}
+
+/// Compare a directory entry name with a path component.
+///
+/// Plain ISO9660 names are upper case and may carry a `;1` version suffix and
+/// a trailing dot, so they are compared case-insensitively without those.
+fn name_matches(entry: &str, component: &str, names: Names) -> bool {
+    let entry = entry.split(';').next().unwrap_or(entry);
+    if entry == component {
+        return true;
+    }
+    names == Names::Iso && entry.trim_end_matches('.').eq_ignore_ascii_case(component)
+}
+
+/// Build an error for a malformed image.
+fn invalid(msg: &str) -> MetadataError {
+    MetadataError::Io(std::io::Error::new(
+        std::io::ErrorKind::InvalidData,
+        format!("iso9660: {}", msg),
+    ))
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use std::collections::BTreeMap;
+    use std::io::Cursor;
+
+    use super::*;
+
+    /// A node in a test image.
+    enum Node {
+        Dir(BTreeMap<String, Node>),
+        File(Vec<u8>),
+    }
+
+    /// Build an ISO9660 image containing `files`, either with a Joliet
+    /// descriptor or with Rock Ridge names in the primary hierarchy.
+    pub(crate) fn build_image(files: &[(&str, &[u8])], joliet: bool) -> Vec<u8> {
+        let mut root = BTreeMap::new();
+        for (path, data) in files {
+            let mut dir = &mut root;
+            let mut parts: Vec<&str> = path.split('/').collect();
+            let file = parts.pop().unwrap();
+            for part in parts {
+                let node = dir
+                    .entry(part.to_string())
+                    .or_insert_with(|| Node::Dir(BTreeMap::new()));
+                let Node::Dir(children) = node else {
+                    panic!("file used as directory");
+                };
+                dir = children;
+            }
+            dir.insert(file.to_string(), Node::File(data.to_vec()));
+        }
+
+        // Sectors 16..=18 hold the descriptors; contents start at 19
+        let mut image = vec![0u8; 19 * SECTOR_SIZE as usize];
+        let root_extent = write_dir(&mut image, &root, joliet);
+        let root_size = SECTOR_SIZE as u32;
+
+        let descriptor = |kind: u8| {
+            let mut sector = vec![0u8; SECTOR_SIZE as usize];
+            sector[0] = kind;
+            sector[1..6].copy_from_slice(STANDARD_IDENTIFIER);
+            sector[6] = 1;
+            sector
+        };
+        let mut pvd = descriptor(PRIMARY_DESCRIPTOR);
+        let mut svd = descriptor(if joliet { SUPPLEMENTARY_DESCRIPTOR } else { 0 });
+        let record = record(&[0], root_extent, root_size, true, &[]);
+        pvd[ROOT_RECORD_OFFSET..ROOT_RECORD_OFFSET + record.len()].copy_from_slice(&record);
+        if joliet {
+            svd[88..91].copy_from_slice(b"%/E");
+            svd[ROOT_RECORD_OFFSET..ROOT_RECORD_OFFSET + record.len()].copy_from_slice(&record);
+        }
+        let terminator = descriptor(TERMINATOR_DESCRIPTOR);
+
+        let start = (VOLUME_DESCRIPTOR_START * SECTOR_SIZE) as usize;
+        let len = SECTOR_SIZE as usize;
+        image[start..start + len].copy_from_slice(&pvd);
+        image[start + len..start + 2 * len].copy_from_slice(&svd);
+        image[start + 2 * len..start + 3 * len].copy_from_slice(&terminator);
+        image
+    }
+
+    /// Append a directory (one sector) and its children, returning its extent.
+    fn write_dir(image: &mut Vec<u8>, dir: &BTreeMap<String, Node>, joliet: bool) -> u32 {
+        let extent = (image.len() as u64 / SECTOR_SIZE) as u32;
+        image.resize(image.len() + SECTOR_SIZE as usize, 0);
+
+        let mut records = record(&[0], extent, SECTOR_SIZE as u32, true, &[]);
+        records.extend(record(&[1], extent, SECTOR_SIZE as u32, true, &[]));
+        for (name, node) in dir {
+            let (child, size, is_dir) = match node {
+                Node::Dir(children) => {
+                    (write_dir(image, children, joliet), SECTOR_SIZE as u32, true)
+                }
+                Node::File(data) => {
+                    let child = (image.len() as u64 / SECTOR_SIZE) as u32;
+                    image.extend_from_slice(data);
+                    let padded = image.len().div_ceil(SECTOR_SIZE as usize) * SECTOR_SIZE as usize;
+                    image.resize(padded, 0);
+                    (child, data.len() as u32, false)
+                }
+            };
+            if joliet {
+                let encoded: Vec<u8> = name.encode_utf16().flat_map(|u| u.to_be_bytes()).collect();
+                records.extend(record(&encoded, child, size, is_dir, &[]));
+            } else {
+                let mut nm = vec![b'N', b'M', (5 + name.len()) as u8, 1, 0];
+                nm.extend_from_slice(name.as_bytes());
+                let iso_name = format!("{};1", name.to_uppercase().replace('-', "_"));
+                records.extend(record(iso_name.as_bytes(), child, size, is_dir, &nm));
+            }
+        }
+
+        let start = extent as usize * SECTOR_SIZE as usize;
+        image[start..start + records.len()].copy_from_slice(&records);
+        extent
+    }
+
+    /// Encode a directory record.
+    fn record(name: &[u8], extent: u32, size: u32, is_dir: bool, system_use: &[u8]) -> Vec<u8> {
+        let mut rec = vec![0u8; 33];
+        rec[2..6].copy_from_slice(&extent.to_le_bytes());
+        rec[6..10].copy_from_slice(&extent.to_be_bytes());
+        rec[10..14].copy_from_slice(&size.to_le_bytes());
+        rec[14..18].copy_from_slice(&size.to_be_bytes());
+        rec[25] = if is_dir { FLAG_DIRECTORY } else { 0 };
+        rec[32] = name.len() as u8;
+        rec.extend_from_slice(name);
+        if name.len().is_multiple_of(2) {
+            rec.push(0);
+        }
+        rec.extend_from_slice(system_use);
+        if rec.len() % 2 == 1 {
+            rec.push(0);
+        }
+        rec[0] = rec.len() as u8;
+        rec
+    }
+
+    #[test]
+    fn test_read_joliet() {
+        let image = build_image(&[("openstack/latest/user_data", b"hello")], true);
+        let data =
+            read_file_from(&mut Cursor::new(image), "openstack/latest/user_data", None).unwrap();
+        assert_eq!(data, b"hello");
+    }
+
+    #[test]
+    fn test_read_rock_ridge() {
+        let image = build_image(&[("openstack/latest/meta_data.json", b"{}")], false);
+        let data = read_file_from(
+            &mut Cursor::new(image),
+            "openstack/latest/meta_data.json",
+            None,
+        )
+        .unwrap();
+        assert_eq!(data, b"{}");
+    }
+
+    #[test]
+    fn test_read_missing_file() {
+        let image = build_image(&[("openstack/latest/user_data", b"hello")], true);
+        let result = read_file_from(
+            &mut Cursor::new(image),
+            "openstack/latest/vendor_data2.json",
+            None,
+        );
+        assert!(matches!(result, Err(MetadataError::NotFound)));
+    }
+
+    #[test]
+    fn test_read_directory_is_not_found() {
+        let image = build_image(&[("openstack/latest/user_data", b"hello")], true);
+        let result = read_file_from(&mut Cursor::new(image), "openstack/latest", None);
+        assert!(matches!(result, Err(MetadataError::NotFound)));
+    }
+
+    #[test]
+    fn test_read_max_size() {
+        let image = build_image(&[("user_data", b"this is too large")], true);
+        let result = read_file_from(&mut Cursor::new(image), "user_data", Some(4));
+        assert!(matches!(result, Err(MetadataError::TooLarge(17, 4))));
+    }
+
+    #[test]
+    fn test_not_an_image() {
+        let result = read_file_from(&mut Cursor::new(vec![0u8; 64 * 1024]), "user_data", None);
+        match result {
+            Err(MetadataError::Io(e)) => {
+                assert_eq!(e.kind(), std::io::ErrorKind::InvalidData);
+                assert!(e.to_string().contains("unsupported filesystem"));
+            }
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_parse_record_without_padding() {
+        let mut rec = record(b"AB", 20, 0, false, &[]);
+        // Drop the padding byte that must follow an even-length name
+        rec.pop();
+        rec[0] = rec.len() as u8;
+        assert_eq!(parse_record(&rec, Names::Iso), None);
+    }
+
+    #[test]
+    fn test_read_oversized_directory() {
+        let mut image = build_image(&[("user_data", b"hello")], false);
+        let size_offset =
+            VOLUME_DESCRIPTOR_START as usize * SECTOR_SIZE as usize + ROOT_RECORD_OFFSET + 10;
+        image[size_offset..size_offset + 4].copy_from_slice(&u32::MAX.to_le_bytes());
+        let result = read_file_from(&mut Cursor::new(image), "user_data", None);
+        assert!(matches!(result, Err(MetadataError::Io(_))));
+    }
+
+    #[test]
+    fn test_read_oversized_file() {
+        let image = build_image(&[("user_data", b"hello")], false);
+        let size = [5, 0, 0, 0, 0, 0, 0, 5];
+        let offset = image.windows(8).position(|w| w == size).unwrap();
+
+        let mut corrupt = image.clone();
+        corrupt[offset..offset + 8].copy_from_slice(&[0xff; 8]);
+        let result = read_file_from(&mut Cursor::new(corrupt), "user_data", None);
+        assert!(matches!(
+            result,
+            Err(MetadataError::TooLarge(_, MAX_FILE_SIZE))
+        ));
+
+        // Within the limit, but past the end of the image
+        let mut corrupt = image;
+        corrupt[offset..offset + 4].copy_from_slice(&(1024 * 1024u32).to_le_bytes());
+        let result = read_file_from(&mut Cursor::new(corrupt), "user_data", None);
+        assert!(matches!(result, Err(MetadataError::Io(_))));
+    }
+
+    #[test]
+    fn test_name_matches_plain_iso() {
+        assert!(name_matches("USER_DATA.;1", "user_data", Names::Iso));
+        assert!(!name_matches("USER_DATA.;1", "user_data", Names::Joliet));
+        assert!(name_matches("user_data", "user_data", Names::Joliet));
+    }
+}
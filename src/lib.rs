@@ -1,14 +1,31 @@
 //! Minimal Rust crate for fetching custom instance metadata from AWS, GCP, and Azure VMs.
 //!
 //! This crate provides a simple API for fetching custom metadata (user-data, instance
-//! attributes, or customData) from cloud provider metadata services.
+//! attributes, or customData) from cloud provider metadata services, and from
+//! cloud-init NoCloud seeds and OpenStack config drives on local disk.
 //!
 //! # Features
 //!
 //! - Auto-detect cloud provider
 //! - Fetch custom metadata as bytes, string, or JSON
+//! - Watch custom metadata for changes, with change notifications on GCP
+//! - Lifecycle event stream for spot interruptions, preemptions and maintenance
+//! - Provider-neutral instance identity (id, region, zone, machine type, ...)
+//! - Instance tags and labels as a key/value map
+//! - Network interface information (MAC, addresses, subnet and VPC ids)
+//! - List the available metadata keys
+//! - Fetch GCP metadata directories recursively as JSON
+//! - Raw access to any metadata path with provider authentication applied
+//! - AWS IAM role credentials with expiry-aware caching
+//! - GCP service-account access and identity tokens with caching
+//! - GCP guest attributes, the only write access, for reporting instance status
+//! - Azure managed identity tokens with caching
+//! - Offline verification of signed identity documents (`verify` feature)
 //! - Support for AWS IMDSv2, GCP Metadata Server, and Azure IMDS
-//! - Automatic base64 decoding for Azure customData
+//! - Support for cloud-init NoCloud seed directories
+//! - Support for OpenStack config drives (mounted or as ISO9660 image)
+//! - Automatic base64 decoding for Azure userData and customData, with fallback
+//!   to the OVF provisioning file
 //!
 //! # Example
 //!
@@ -44,16 +61,43 @@
 //!
 //! | Provider | Metadata Source | Key Parameter |
 //! |----------|-----------------|---------------|
-//! | AWS | User-data | Ignored |
+//! | AWS | User-data | Selects a MIME multipart part, if any |
 //! | GCP | Instance attribute | Used as attribute name |
-//! | Azure | customData (base64 decoded) | Ignored |
+//! | Azure | userData, then customData (base64 decoded, IMDS then ovf-env.xml) | Selects a MIME multipart part, if any |
+//! | NoCloud | `user-data` seed file | Selects a MIME multipart part, if any |
+//! | OpenStack | meta_data.json `meta` entry, user_data for an empty key | Used as `meta` key |
 
 mod client;
+mod credentials;
 mod error;
+mod identity;
+mod iso9660;
+mod lifecycle;
+mod local;
 mod metadata;
+mod network;
 mod provider;
 mod providers;
+mod time;
+mod userdata;
+#[cfg(feature = "verify")]
+pub mod verify;
 
 pub use error::MetadataError;
+pub use identity::InstanceIdentity;
+pub use lifecycle::{LifecycleEvent, LifecycleEventKind};
 pub use metadata::CloudMetadata;
+pub use network::NetworkInterface;
 pub use provider::CloudProvider;
+pub use providers::aws::{
+    AwsCredentialProvider, AwsCredentials, AwsIdentityDocument, AwsSignedIdentityDocument,
+};
+pub use providers::azure::{
+    AzureAttestedDocument, AzureDataSource, AzureIdentity, AzureToken, AzureTokenProvider,
+};
+pub use providers::gcp::{
+    GcpAttributeScope, GcpGuestAttributes, GcpServiceAccount, GcpToken, GcpTokenProvider,
+};
+pub use providers::nocloud::NoCloudMetaData;
+pub use providers::openstack::OpenStackMetaData;
+pub use userdata::{UserDataKind, UserDataPart};
@@ -0,0 +1,49 @@
+//! Provider-neutral lifecycle events.
+
+use std::time::{Duration, SystemTime};
+
+/// How often AWS and Azure are polled for lifecycle events.
+///
+/// AWS gives two minutes notice of spot interruptions and recommends
+/// checking every five seconds.
+pub const DEFAULT_LIFECYCLE_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Size limit for lifecycle event responses.
+pub(crate) const MAX_EVENT_SIZE: usize = 64 * 1024;
+
+/// Kind of a lifecycle event, normalized across providers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum LifecycleEventKind {
+    /// The instance is reclaimed (AWS spot interruption, GCP preemption,
+    /// Azure `Preempt`).
+    Preemption,
+    /// The instance is at elevated risk of interruption (AWS rebalance
+    /// recommendation).
+    Rebalance,
+    /// The instance is paused or live-migrated for host maintenance (AWS
+    /// `system-maintenance`, GCP `MIGRATE_ON_HOST_MAINTENANCE`, Azure `Freeze`).
+    Maintenance,
+    /// The instance is rebooted (AWS `instance-reboot` and `system-reboot`,
+    /// Azure `Reboot`).
+    Reboot,
+    /// The instance moves to another host and loses its local disks (Azure
+    /// `Redeploy`).
+    Redeploy,
+    /// The instance is stopped or terminated (AWS `instance-stop` and
+    /// `instance-retirement`, Auto Scaling `Terminated` target state, GCP
+    /// `TERMINATE_ON_HOST_MAINTENANCE`, Azure `Terminate`).
+    Termination,
+}
+
+/// An upcoming event that affects the running instance.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LifecycleEvent {
+    /// What happens to the instance.
+    pub kind: LifecycleEventKind,
+    /// Provider event id (AWS maintenance `EventId`, Azure `EventId`).
+    pub id: Option<String>,
+    /// When the event starts at the earliest, if the provider reports it.
+    pub deadline: Option<SystemTime>,
+    /// The event as served by the metadata service.
+    pub raw: String,
+}
@@ -0,0 +1,90 @@
+//! Helpers for reading metadata from local files.
+
+use std::io::ErrorKind;
+use std::path::{Component, Path};
+
+use crate::error::MetadataError;
+
+/// Read a file with an optional size limit.
+///
+/// A missing file is reported as `MetadataError::NotFound`. If `max_size` is
+/// `Some`, the file length is checked before reading so oversized files are
+/// rejected without loading them into memory.
+pub async fn read_file_limited(
+    path: &Path,
+    max_size: Option<usize>,
+) -> Result<Vec<u8>, MetadataError> {
+    if let Some(max_size) = max_size {
+        let len = tokio::fs::metadata(path).await.map_err(map_io_error)?.len() as usize;
+        if len > max_size {
+            return Err(MetadataError::TooLarge(len, max_size));
+        }
+    }
+
+    let data = tokio::fs::read(path).await.map_err(map_io_error)?;
+
+    // The file may have grown between the metadata check and the read
+    if let Some(max_size) = max_size {
+        if data.len() > max_size {
+            return Err(MetadataError::TooLarge(data.len(), max_size));
+        }
+    }
+
+    Ok(data)
+}
+
+/// Map a missing file to `MetadataError::NotFound`, keeping other I/O errors.
+pub fn map_io_error(err: std::io::Error) -> MetadataError {
+    if err.kind() == ErrorKind::NotFound {
+        MetadataError::NotFound
+    } else {
+        MetadataError::Io(err)
+    }
+}
+
+/// Strip leading slashes from a path below a local source.
+///
+/// Paths with `..` components would leave the source and are reported as
+/// `MetadataError::NotFound`.
+pub fn relative_path(path: &str) -> Result<&str, MetadataError> {
+    let path = path.trim_start_matches('/');
+    if Path::new(path)
+        .components()
+        .any(|c| c == Component::ParentDir)
+    {
+        return Err(MetadataError::NotFound);
+    }
+    Ok(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_map_io_error_not_found() {
+        let err = std::io::Error::from(ErrorKind::NotFound);
+        assert!(matches!(map_io_error(err), MetadataError::NotFound));
+    }
+
+    #[test]
+    fn test_map_io_error_other() {
+        let err = std::io::Error::from(ErrorKind::PermissionDenied);
+        assert!(matches!(map_io_error(err), MetadataError::Io(_)));
+    }
+
+    #[test]
+    fn test_relative_path() {
+        assert_eq!(relative_path("/user-data").unwrap(), "user-data");
+        assert_eq!(relative_path("a/./b").unwrap(), "a/./b");
+        assert_eq!(relative_path("a..b").unwrap(), "a..b");
+        assert!(matches!(
+            relative_path("../etc/passwd"),
+            Err(MetadataError::NotFound)
+        ));
+        assert!(matches!(
+            relative_path("/a/../../b"),
+            Err(MetadataError::NotFound)
+        ));
+    }
+}
@@ -1,11 +1,32 @@
 //! CloudMetadata struct and core implementation.
 
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{BTreeMap, HashSet};
+use std::hash::{Hash, Hasher};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
 use serde::de::DeserializeOwned;
+use tokio::sync::{mpsc, watch};
 
 use crate::client::MetadataClient;
-use crate::error::MetadataError;
+use crate::error::{optional, MetadataError};
+use crate::identity::InstanceIdentity;
+use crate::lifecycle::{LifecycleEvent, DEFAULT_LIFECYCLE_INTERVAL, MAX_EVENT_SIZE};
+use crate::network::NetworkInterface;
 use crate::provider::CloudProvider;
-use crate::providers::{aws, azure, gcp};
+use crate::providers::aws::{AwsCredentialProvider, AwsSignedIdentityDocument};
+use crate::providers::azure::{AzureAttestedDocument, AzureDataSource, AzureTokenProvider};
+use crate::providers::gcp::{
+    GcpAttributeScope, GcpGuestAttributes, GcpServiceAccount, GcpTokenProvider,
+};
+use crate::providers::nocloud::NoCloudMetaData;
+use crate::providers::openstack::OpenStackMetaData;
+use crate::providers::{aws, azure, gcp, nocloud, openstack};
+use crate::userdata::{self, UserDataKind, UserDataPart};
+
+/// Default interval between polls in `CloudMetadata::watch`.
+pub const DEFAULT_WATCH_INTERVAL: Duration = Duration::from_secs(30);
 
 /// Main interface for fetching cloud instance metadata.
 ///
@@ -27,113 +48,307 @@ use crate::providers::{aws, azure, gcp};
 ///     Ok(())
 /// }
 /// ```
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct CloudMetadata {
     provider: CloudProvider,
     client: MetadataClient,
     max_size: Option<usize>,
+    seed_path: Option<PathBuf>,
+    ovf_path: Option<PathBuf>,
+    azure_sources: Vec<AzureDataSource>,
+    azure_versions: azure::ApiVersions,
+    gcp_tag_prefix: Option<String>,
+    gcp_attribute_scope: GcpAttributeScope,
+    gcp_guest_attributes: bool,
+    decode_user_data: bool,
+    aws_session: aws::Session,
+    watch_interval: Duration,
+    lifecycle_interval: Duration,
 }
 
 impl CloudMetadata {
     /// Auto-detect the cloud provider by probing metadata endpoints.
     ///
     /// Performs parallel probes to AWS, GCP, and Azure metadata services
-    /// with a 500ms timeout. Returns the first provider that responds.
+    /// with a 500ms timeout and returns the first provider that responds.
+    /// Only if none responds, checks for a local cloud-init NoCloud seed or
+    /// OpenStack config drive, so a leftover seed on a cloud VM does not win.
     ///
     /// # Errors
     ///
     /// Returns `MetadataError::NotDetected` if no cloud provider is detected.
     pub async fn detect() -> Result<Self, MetadataError> {
-        Self::detect_with_base_url(crate::client::DEFAULT_BASE_URL).await
+        if let Some(metadata) = Self::detect_network(crate::client::DEFAULT_BASE_URL).await? {
+            return Ok(metadata);
+        }
+        if let Ok(seed_path) = nocloud::probe().await {
+            return Ok(Self::nocloud_with_path(seed_path));
+        }
+        if let Ok(drive) = openstack::probe().await {
+            return Ok(Self::openstack_with_path(drive));
+        }
+        Err(MetadataError::NotDetected)
     }
 
     /// Auto-detect the cloud provider using a custom base URL.
     ///
-    /// This is primarily useful for testing with mock servers.
+    /// This is primarily useful for testing with mock servers. Only the
+    /// metadata services are probed, not local NoCloud seeds or OpenStack
+    /// config drives.
+    ///
+    /// # Errors
+    ///
+    /// Returns `MetadataError::NotDetected` if no metadata service responds.
     pub async fn detect_with_base_url(base_url: &str) -> Result<Self, MetadataError> {
+        Self::detect_network(base_url)
+            .await?
+            .ok_or(MetadataError::NotDetected)
+    }
+
+    /// Probe the AWS, GCP and Azure metadata services concurrently and
+    /// return the first that responds.
+    async fn detect_network(base_url: &str) -> Result<Option<Self>, MetadataError> {
         let client = MetadataClient::for_detection_with_base_url(base_url)?;
 
-        // Run all probes concurrently and return the first successful one
-        tokio::select! {
-            Ok(()) = gcp::probe(&client) => {
-                return Ok(Self {
-                    provider: CloudProvider::Gcp,
-                    client: MetadataClient::with_base_url(base_url)?,
-                    max_size: None,
-                });
-            }
-            Ok(()) = aws::probe(&client) => {
-                return Ok(Self {
-                    provider: CloudProvider::Aws,
-                    client: MetadataClient::with_base_url(base_url)?,
-                    max_size: None,
-                });
-            }
-            Ok(()) = azure::probe(&client) => {
-                return Ok(Self {
-                    provider: CloudProvider::Azure,
-                    client: MetadataClient::with_base_url(base_url)?,
-                    max_size: None,
-                });
-            }
-            else => {}
-        }
+        let provider = tokio::select! {
+            Ok(()) = gcp::probe(&client) => CloudProvider::Gcp,
+            Ok(()) = aws::probe(&client) => CloudProvider::Aws,
+            Ok(()) = azure::probe(&client) => CloudProvider::Azure,
+            else => return Ok(None),
+        };
 
-        Err(MetadataError::NotDetected)
+        Ok(Some(Self::new(
+            provider,
+            MetadataClient::with_base_url(base_url)?,
+            None,
+        )))
     }
 
-    /// Create a CloudMetadata instance for AWS.
-    pub fn aws() -> Self {
+    /// Create an instance with default settings.
+    fn new(provider: CloudProvider, client: MetadataClient, seed_path: Option<PathBuf>) -> Self {
         Self {
-            provider: CloudProvider::Aws,
-            client: MetadataClient::default(),
+            provider,
+            client,
             max_size: None,
+            seed_path,
+            ovf_path: None,
+            azure_sources: azure::DEFAULT_SOURCES.to_vec(),
+            azure_versions: azure::ApiVersions::default(),
+            gcp_tag_prefix: None,
+            gcp_attribute_scope: GcpAttributeScope::default(),
+            gcp_guest_attributes: false,
+            decode_user_data: false,
+            aws_session: aws::Session::default(),
+            watch_interval: DEFAULT_WATCH_INTERVAL,
+            lifecycle_interval: DEFAULT_LIFECYCLE_INTERVAL,
         }
     }
 
+    /// Create a CloudMetadata instance for AWS.
+    pub fn aws() -> Self {
+        Self::new(CloudProvider::Aws, MetadataClient::default(), None)
+    }
+
     /// Create a CloudMetadata instance for AWS with a custom base URL.
     pub fn aws_with_base_url(base_url: &str) -> Self {
-        Self {
-            provider: CloudProvider::Aws,
-            client: MetadataClient::with_base_url(base_url).expect("failed to create HTTP client"),
-            max_size: None,
-        }
+        Self::new(
+            CloudProvider::Aws,
+            MetadataClient::with_base_url(base_url).expect("failed to create HTTP client"),
+            None,
+        )
     }
 
     /// Create a CloudMetadata instance for GCP.
     pub fn gcp() -> Self {
-        Self {
-            provider: CloudProvider::Gcp,
-            client: MetadataClient::default(),
-            max_size: None,
-        }
+        Self::new(CloudProvider::Gcp, MetadataClient::default(), None)
     }
 
     /// Create a CloudMetadata instance for GCP with a custom base URL.
     pub fn gcp_with_base_url(base_url: &str) -> Self {
-        Self {
-            provider: CloudProvider::Gcp,
-            client: MetadataClient::with_base_url(base_url).expect("failed to create HTTP client"),
-            max_size: None,
-        }
+        Self::new(
+            CloudProvider::Gcp,
+            MetadataClient::with_base_url(base_url).expect("failed to create HTTP client"),
+            None,
+        )
     }
 
     /// Create a CloudMetadata instance for Azure.
     pub fn azure() -> Self {
-        Self {
-            provider: CloudProvider::Azure,
-            client: MetadataClient::default(),
-            max_size: None,
-        }
+        Self::new(CloudProvider::Azure, MetadataClient::default(), None)
     }
 
     /// Create a CloudMetadata instance for Azure with a custom base URL.
     pub fn azure_with_base_url(base_url: &str) -> Self {
-        Self {
-            provider: CloudProvider::Azure,
-            client: MetadataClient::with_base_url(base_url).expect("failed to create HTTP client"),
-            max_size: None,
-        }
+        Self::new(
+            CloudProvider::Azure,
+            MetadataClient::with_base_url(base_url).expect("failed to create HTTP client"),
+            None,
+        )
+    }
+
+    /// Create a CloudMetadata instance for a cloud-init NoCloud seed in the
+    /// default directory (`/var/lib/cloud/seed/nocloud`).
+    pub fn nocloud() -> Self {
+        Self::nocloud_with_path(nocloud::DEFAULT_SEED_PATH)
+    }
+
+    /// Create a CloudMetadata instance for a cloud-init NoCloud seed directory.
+    ///
+    /// Use this for the mount point of a `CIDATA`-labelled volume.
+    pub fn nocloud_with_path(path: impl Into<PathBuf>) -> Self {
+        Self::new(
+            CloudProvider::NoCloud,
+            MetadataClient::default(),
+            Some(path.into()),
+        )
+    }
+
+    /// Create a CloudMetadata instance for an OpenStack config drive, read
+    /// directly from the `config-2` labelled device.
+    pub fn openstack() -> Self {
+        Self::openstack_with_path(openstack::DEFAULT_CONFIG_DRIVE_PATH)
+    }
+
+    /// Create a CloudMetadata instance for an OpenStack config drive.
+    ///
+    /// The path may be the mount point of the config drive, or an ISO9660
+    /// image file or block device which is read without mounting. A vfat
+    /// config drive has to be mounted.
+    pub fn openstack_with_path(path: impl Into<PathBuf>) -> Self {
+        Self::new(
+            CloudProvider::OpenStack,
+            MetadataClient::default(),
+            Some(path.into()),
+        )
+    }
+
+    /// Set the location of the Azure OVF provisioning file.
+    ///
+    /// The path may point to `ovf-env.xml`, a directory containing it, or the
+    /// provisioning ISO image or device. By default `/var/lib/waagent/ovf-env.xml`
+    /// and then `/dev/sr0` are tried. Only used by Azure.
+    pub fn with_ovf_path(mut self, path: impl Into<PathBuf>) -> Self {
+        self.ovf_path = Some(path.into());
+        self
+    }
+
+    /// Set which Azure sources `custom_data` reads, in order.
+    ///
+    /// Each source is tried in turn until one has data. The default is
+    /// `userData`, then `customData`. Only used by Azure.
+    pub fn with_azure_sources(mut self, sources: &[AzureDataSource]) -> Self {
+        self.azure_sources = sources.to_vec();
+        self
+    }
+
+    /// Use a fixed Azure IMDS api-version instead of negotiating one.
+    ///
+    /// By default the newest version offered by IMDS and supported by this
+    /// crate is used for each endpoint. Only used by Azure.
+    pub fn with_azure_api_version(mut self, version: &str) -> Self {
+        self.azure_versions = azure::ApiVersions::with_override(version);
+        self
+    }
+
+    /// Set the lifetime of IMDSv2 session tokens.
+    ///
+    /// The token is cached and reused until shortly before it expires. The
+    /// default and maximum is six hours; values are rounded down to whole
+    /// seconds, with a minimum of one second. Only used by AWS.
+    pub fn with_aws_token_ttl(mut self, ttl: Duration) -> Self {
+        self.aws_session = self.aws_session.with_ttl(ttl);
+        self
+    }
+
+    /// Fall back to IMDSv1 if no IMDSv2 session token can be obtained.
+    ///
+    /// Only use this for accounts that still allow IMDSv1. The fallback is
+    /// taken when the token request times out, for example because the hop
+    /// limit drops the response inside a container, or is refused with any
+    /// status but 403. The token is tried again once its TTL has passed.
+    /// With the fallback the token request times out after one second unless
+    /// `with_aws_token_timeout` says otherwise. Disabled by default. Only used
+    /// by AWS.
+    pub fn with_aws_imdsv1_fallback(mut self, enabled: bool) -> Self {
+        self.aws_session = self.aws_session.with_imdsv1_fallback(enabled);
+        self
+    }
+
+    /// Set how long to wait for an IMDSv2 session token.
+    ///
+    /// Defaults to the client timeout. A token request that times out while
+    /// IMDS still answers a plain GET fails with `MetadataError::HopLimit`.
+    /// Only used by AWS.
+    pub fn with_aws_token_timeout(mut self, timeout: Duration) -> Self {
+        self.aws_session = self.aws_session.with_token_timeout(timeout);
+        self
+    }
+
+    /// Set the attribute name prefix that marks GCP instance attributes as tags.
+    ///
+    /// `tags()` returns only attributes starting with the prefix, with the
+    /// prefix stripped. GCP has no instance tags, and attributes such as
+    /// `ssh-keys` or `startup-script` must not show up as tags, so without a
+    /// prefix `tags()` returns `MetadataError::NotSupported`. An empty prefix
+    /// returns all instance attributes. Only used by GCP.
+    pub fn with_gcp_tag_prefix(mut self, prefix: &str) -> Self {
+        self.gcp_tag_prefix = Some(prefix.to_string());
+        self
+    }
+
+    /// Set where `custom_data` looks up GCP attributes.
+    ///
+    /// With `InstanceThenProject`, a key missing on the instance falls back
+    /// to the project-wide value, as GCP does for `ssh-keys`. The default is
+    /// instance attributes only. Only used by GCP.
+    ///
+    /// The scope is a setting rather than a per-call argument so that
+    /// `custom_text`, `custom_json` and `watch` keep their provider-neutral
+    /// signatures. To read from several scopes, use a clone per scope; clones
+    /// share the HTTP client.
+    pub fn with_gcp_attribute_scope(mut self, scope: GcpAttributeScope) -> Self {
+        self.gcp_attribute_scope = scope;
+        self
+    }
+
+    /// Allow access to GCP guest attributes through `gcp_guest_attributes`.
+    ///
+    /// Guest attributes are the only metadata the crate writes, so access
+    /// has to be enabled explicitly. Disabled by default. Only used by GCP.
+    pub fn with_gcp_guest_attributes(mut self, enabled: bool) -> Self {
+        self.gcp_guest_attributes = enabled;
+        self
+    }
+
+    /// Set how often `watch` polls for changes.
+    ///
+    /// Defaults to 30 seconds. GCP is notified of changes
+    /// directly and only polls while the watched key does not exist.
+    pub fn with_watch_interval(mut self, interval: Duration) -> Self {
+        self.watch_interval = interval;
+        self
+    }
+
+    /// Set how often AWS and Azure are polled for lifecycle events.
+    ///
+    /// Defaults to 5 seconds. GCP is notified of events directly and only
+    /// uses the interval to retry failed requests.
+    pub fn with_lifecycle_interval(mut self, interval: Duration) -> Self {
+        self.lifecycle_interval = interval;
+        self
+    }
+
+    /// Decode compressed and base64-encoded custom data.
+    ///
+    /// gzip, base64 (standard or URL-safe, optionally line-wrapped) and, with
+    /// the `zstd` feature, zstd are detected by content and removed, also
+    /// when nested. Base64 is only decoded if it yields compressed data or a
+    /// known kind of user-data, such as a JSON object or array but not a JSON
+    /// scalar. `max_size` also limits the decompressed size. Disabled by
+    /// default.
+    pub fn with_user_data_decoding(mut self, enabled: bool) -> Self {
+        self.decode_user_data = enabled;
+        self
     }
 
     /// Set the maximum size limit for fetched data.
@@ -151,21 +366,111 @@ impl CloudMetadata {
 
     /// Fetch custom data as raw bytes.
     ///
-    /// - **AWS**: Returns user-data (key parameter is ignored)
-    /// - **GCP**: Returns the instance attribute with the given key
-    /// - **Azure**: Returns decoded customData (key parameter is ignored)
+    /// - **AWS**: Returns user-data
+    /// - **GCP**: Returns the attribute with the given key, from the instance
+    ///   or project as set by `with_gcp_attribute_scope`
+    /// - **Azure**: Returns decoded userData, or customData if there is no
+    ///   userData. If IMDS has no customData, it is read from the OVF
+    ///   provisioning file instead. See `with_azure_sources`
+    /// - **NoCloud**: Returns the seed's user-data
+    /// - **OpenStack**: Returns user_data for an empty key, otherwise the
+    ///   `meta` entry with the given key from meta_data.json
+    ///
+    /// Except on GCP, if the data is MIME multipart, the key selects the
+    /// first part with that filename or content type. If no part matches,
+    /// or the data is not multipart, the whole data is returned. See `parts`.
+    /// On OpenStack a non-empty key that names neither a `meta` entry nor a
+    /// part of user_data returns `MetadataError::NotFound` instead.
     ///
     /// # Errors
     ///
     /// Returns an error if the metadata cannot be fetched or decoded.
     /// Returns `MetadataError::TooLarge` if the data exceeds the configured `max_size`.
     pub async fn custom_data(&self, key: &str) -> Result<Vec<u8>, MetadataError> {
+        let named = self.provider == CloudProvider::OpenStack && !key.is_empty();
+        if named {
+            let entry = openstack::fetch_meta_entry(self.seed_path(), key, self.max_size).await?;
+            if let Some(value) = entry {
+                return self.decode_custom_data(value);
+            }
+        }
+
+        let data = self.fetch_custom_data(key).await?;
+        let data = self.decode_custom_data(data)?;
+        if self.provider == CloudProvider::Gcp {
+            return Ok(data);
+        }
+        match userdata::select_part(&data, key)? {
+            Some(part) => self.decode_custom_data(part),
+            None if named => Err(MetadataError::NotFound),
+            None => Ok(data),
+        }
+    }
+
+    /// List the MIME parts of custom data.
+    ///
+    /// The key is used as by `custom_data` to fetch the data, but does not
+    /// select a part. Nested multipart parts are flattened. Data that is not
+    /// multipart is returned as a single part without filename, with the
+    /// content type cloud-init uses for its kind, e.g. `text/cloud-config`.
+    /// Decoding applies to the data and to each part as configured with
+    /// `with_user_data_decoding`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the metadata cannot be fetched or decoded.
+    pub async fn parts(&self, key: &str) -> Result<Vec<UserDataPart>, MetadataError> {
+        let data = self.fetch_custom_data(key).await?;
+        let data = self.decode_custom_data(data)?;
+        userdata::parts(&data)?
+            .into_iter()
+            .map(|part| {
+                Ok(UserDataPart {
+                    data: self.decode_custom_data(part.data)?,
+                    ..part
+                })
+            })
+            .collect()
+    }
+
+    /// Fetch custom data and classify its content.
+    ///
+    /// Decoding applies as configured with `with_user_data_decoding`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the metadata cannot be fetched or decoded.
+    pub async fn custom_data_kind(&self, key: &str) -> Result<UserDataKind, MetadataError> {
+        let data = self.custom_data(key).await?;
+        Ok(UserDataKind::detect(&data))
+    }
+
+    /// Decode custom data if enabled with `with_user_data_decoding`.
+    fn decode_custom_data(&self, data: Vec<u8>) -> Result<Vec<u8>, MetadataError> {
+        if self.decode_user_data {
+            userdata::decode(data, self.max_size)
+        } else {
+            Ok(data)
+        }
+    }
+
+    /// Fetch custom data as served by the provider.
+    async fn fetch_custom_data(&self, key: &str) -> Result<Vec<u8>, MetadataError> {
         match self.provider {
-            CloudProvider::Aws => aws::fetch_user_data(&self.client, self.max_size).await,
+            CloudProvider::Aws => {
+                aws::fetch_user_data(&self.client, &self.aws_session, self.max_size).await
+            }
             CloudProvider::Gcp => {
-                gcp::fetch_instance_attribute(&self.client, key, self.max_size).await
+                gcp::fetch_attribute(&self.client, self.gcp_attribute_scope, key, self.max_size)
+                    .await
+            }
+            CloudProvider::Azure => self.azure_custom_data().await,
+            CloudProvider::NoCloud => {
+                nocloud::fetch_user_data(self.seed_path(), self.max_size).await
+            }
+            CloudProvider::OpenStack => {
+                openstack::fetch_user_data(self.seed_path(), self.max_size).await
             }
-            CloudProvider::Azure => azure::fetch_custom_data(&self.client, self.max_size).await,
         }
     }
 
@@ -189,21 +494,741 @@ impl CloudMetadata {
         serde_json::from_slice(&data).map_err(MetadataError::from)
     }
 
-    /// Fetch a GCP project-level attribute.
+    /// Watch custom data and deserialize it as JSON whenever it changes.
+    ///
+    /// The returned receiver holds the current value and is notified when
+    /// the value changes, until it is dropped. Values that fail to
+    /// deserialize, `MetadataError::NotFound` while the key does not exist,
+    /// and failed requests are delivered as errors without ending the watch.
+    /// Failed requests are retried at the watch interval, and the current
+    /// value is delivered again once they succeed.
+    ///
+    /// - **GCP**: Waits for changes of the attribute with
+    ///   `wait_for_change=true`, using the ETag of the last value. With
+    ///   `GcpAttributeScope::InstanceThenProject` it polls like other providers
+    /// - **Others**: Polls `custom_data` at the watch interval and compares
+    ///   content hashes. See `with_watch_interval`
+    ///
+    /// Must be called within a Tokio runtime.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the initial request fails.
+    pub async fn watch<T>(
+        &self,
+        key: &str,
+    ) -> Result<watch::Receiver<Result<T, MetadataError>>, MetadataError>
+    where
+        T: DeserializeOwned + Send + Sync + 'static,
+    {
+        let metadata = self.clone();
+        let key = key.to_string();
+        let (data, mut version) = metadata.next_change(&key, None).await?;
+        let (tx, rx) = watch::channel(decode_json(data));
+
+        tokio::spawn(async move {
+            let mut failed = false;
+            loop {
+                // After an error the receiver holds the error, so the next
+                // value is delivered even if it did not change
+                let last = (!failed).then_some(&version);
+                let change = tokio::select! {
+                    _ = tx.closed() => break,
+                    change = metadata.next_change(&key, last) => change,
+                };
+                match change {
+                    Ok((data, next)) => {
+                        failed = false;
+                        version = next;
+                        if tx.send(decode_json(data)).is_err() {
+                            break;
+                        }
+                    }
+                    Err(e) => {
+                        failed = true;
+                        if tx.send(Err(e)).is_err() {
+                            break;
+                        }
+                        tokio::select! {
+                            _ = tx.closed() => break,
+                            _ = tokio::time::sleep(metadata.watch_interval) => {}
+                        }
+                    }
+                }
+            }
+        });
+
+        Ok(rx)
+    }
+
+    /// Wait until custom data differs from the `last` seen version.
+    ///
+    /// Returns the new value, `None` if the key does not exist, and its
+    /// version. Without `last`, the current value is returned immediately.
+    async fn next_change(
+        &self,
+        key: &str,
+        last: Option<&Version>,
+    ) -> Result<(Option<Vec<u8>>, Version), MetadataError> {
+        loop {
+            let gcp_path = match self.provider {
+                CloudProvider::Gcp => gcp::attribute_path(self.gcp_attribute_scope, key),
+                _ => None,
+            };
+            if let Some(path) = gcp_path {
+                let last_etag = match last {
+                    Some(Version::Etag(etag)) => Some(etag.as_str()),
+                    Some(_) => {
+                        tokio::time::sleep(self.watch_interval).await;
+                        None
+                    }
+                    None => None,
+                };
+                let fetched =
+                    gcp::wait_for_change(&self.client, &path, last_etag, self.max_size).await;
+                let (data, version) = match optional(fetched)? {
+                    Some((data, etag)) => {
+                        (Some(self.decode_custom_data(data)?), Version::Etag(etag))
+                    }
+                    None => (None, Version::Missing),
+                };
+                if last != Some(&version) {
+                    return Ok((data, version));
+                }
+            } else {
+                if last.is_some() {
+                    tokio::time::sleep(self.watch_interval).await;
+                }
+                let data = optional(self.custom_data(key).await)?;
+                let version = match &data {
+                    Some(data) => {
+                        let mut hasher = DefaultHasher::new();
+                        data.hash(&mut hasher);
+                        Version::Hash(hasher.finish())
+                    }
+                    None => Version::Missing,
+                };
+                if last != Some(&version) {
+                    return Ok((data, version));
+                }
+            }
+        }
+    }
+
+    /// Stream lifecycle events such as spot interruptions, preemptions and
+    /// scheduled maintenance, so workloads can be drained in time.
+    ///
+    /// Each event is delivered once. Failed requests and events that fail to
+    /// parse are delivered as errors next to the events of the other sources.
+    /// Failed requests are retried, and the stream ends when the receiver is
+    /// dropped.
+    ///
+    /// - **AWS**: Polls `spot/instance-action`,
+    ///   `events/recommendations/rebalance`, `events/maintenance/scheduled`
+    ///   and `autoscaling/target-lifecycle-state`
+    /// - **GCP**: Waits for changes of `instance/preempted` and
+    ///   `instance/maintenance-event`
+    /// - **Azure**: Polls `/metadata/scheduledevents`. Events may concern
+    ///   other VMs of the same availability set, see the `raw` payload.
+    ///   Acknowledge handled events with `azure_ack_event`
+    ///
+    /// AWS and Azure are polled every 5 seconds by default, see
+    /// `with_lifecycle_interval`. Must be called within a Tokio runtime.
+    ///
+    /// # Errors
+    ///
+    /// Returns `MetadataError::NotSupported` for NoCloud and OpenStack.
+    pub fn lifecycle_events(
+        &self,
+    ) -> Result<mpsc::Receiver<Result<LifecycleEvent, MetadataError>>, MetadataError> {
+        let (tx, rx) = mpsc::channel(16);
+        match self.provider {
+            CloudProvider::Aws | CloudProvider::Azure => {
+                tokio::spawn(self.clone().poll_lifecycle_events(tx));
+            }
+            CloudProvider::Gcp => {
+                for path in gcp::LIFECYCLE_PATHS {
+                    tokio::spawn(self.clone().wait_for_lifecycle_events(path, tx.clone()));
+                }
+            }
+            CloudProvider::NoCloud | CloudProvider::OpenStack => {
+                return Err(MetadataError::NotSupported)
+            }
+        }
+        Ok(rx)
+    }
+
+    /// Acknowledge an Azure scheduled event from `lifecycle_events`.
+    ///
+    /// This tells Azure the VM is ready, so the event may start before its
+    /// deadline.
+    ///
+    /// # Errors
+    ///
+    /// Returns `MetadataError::NotSupported` for providers other than Azure
+    /// and for events without an id.
+    pub async fn azure_ack_event(&self, event: &LifecycleEvent) -> Result<(), MetadataError> {
+        match (self.provider, &event.id) {
+            (CloudProvider::Azure, Some(id)) => azure::ack_scheduled_event(&self.client, id).await,
+            _ => Err(MetadataError::NotSupported),
+        }
+    }
+
+    /// Poll for lifecycle events and send each new one to `tx`.
+    async fn poll_lifecycle_events(self, tx: mpsc::Sender<Result<LifecycleEvent, MetadataError>>) {
+        let mut seen = HashSet::new();
+        loop {
+            let events = match self.provider {
+                CloudProvider::Aws => {
+                    aws::fetch_lifecycle_events(&self.client, &self.aws_session).await
+                }
+                _ => azure::fetch_scheduled_events(&self.client)
+                    .await
+                    .unwrap_or_else(|e| vec![Err(e)]),
+            };
+            // Only events still pending are remembered, so `seen` stays as
+            // small as the last response. After a failure the previous events
+            // are kept, so whatever the failed request would have repeated is
+            // not sent again. Failed polls are retried at the next interval.
+            let mut current = HashSet::new();
+            let mut failed = false;
+            for event in events {
+                match &event {
+                    Ok(event) => {
+                        let key = event.id.clone().unwrap_or_else(|| event.raw.clone());
+                        let key = (event.kind, key);
+                        let new = !seen.contains(&key);
+                        current.insert(key);
+                        if !new {
+                            continue;
+                        }
+                    }
+                    Err(_) => failed = true,
+                }
+                if tx.send(event).await.is_err() {
+                    return;
+                }
+            }
+            if failed {
+                seen.extend(current);
+            } else {
+                seen = current;
+            }
+
+            tokio::select! {
+                _ = tx.closed() => return,
+                _ = tokio::time::sleep(self.lifecycle_interval) => {}
+            }
+        }
+    }
+
+    /// Wait for changes of a GCP lifecycle path and send new events to `tx`.
+    async fn wait_for_lifecycle_events(
+        self,
+        path: &str,
+        tx: mpsc::Sender<Result<LifecycleEvent, MetadataError>>,
+    ) {
+        let mut last_etag: Option<String> = None;
+        loop {
+            let change = tokio::select! {
+                _ = tx.closed() => return,
+                change = gcp::wait_for_change(
+                    &self.client,
+                    path,
+                    last_etag.as_deref(),
+                    Some(MAX_EVENT_SIZE),
+                ) => change,
+            };
+            match change {
+                Ok((value, etag)) => {
+                    if last_etag.as_deref() == Some(etag.as_str()) {
+                        continue;
+                    }
+                    last_etag = Some(etag);
+                    if let Some(event) = gcp::lifecycle_event(path, &value) {
+                        if tx.send(Ok(event)).await.is_err() {
+                            return;
+                        }
+                    }
+                }
+                Err(e) => {
+                    if tx.send(Err(e)).await.is_err() {
+                        return;
+                    }
+                    tokio::select! {
+                        _ = tx.closed() => return,
+                        _ = tokio::time::sleep(self.lifecycle_interval) => {}
+                    }
+                }
+            }
+        }
+    }
+
+    /// Fetch the identity of the running instance.
+    ///
+    /// - **AWS**: From the instance identity document and `meta-data/local-hostname`
+    /// - **GCP**: From `instance/*` and `project/project-id`
+    /// - **Azure**: From `/metadata/instance/compute`
+    /// - **NoCloud**: From meta-data (instance id and hostname only)
+    /// - **OpenStack**: From meta_data.json (no region or machine type)
+    ///
+    /// Zone names are normalized to the provider's full zone name, e.g.
+    /// `us-central1-a` on GCP and `eastus-1` on Azure.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the metadata cannot be fetched or parsed.
+    pub async fn identity(&self) -> Result<InstanceIdentity, MetadataError> {
+        match self.provider {
+            CloudProvider::Aws => {
+                aws::fetch_identity(&self.client, &self.aws_session, self.max_size).await
+            }
+            CloudProvider::Gcp => gcp::fetch_identity(&self.client, self.max_size).await,
+            CloudProvider::Azure => {
+                azure::fetch_identity(&self.client, &self.azure_versions, self.max_size).await
+            }
+            CloudProvider::NoCloud => {
+                nocloud::fetch_identity(self.seed_path(), self.max_size).await
+            }
+            CloudProvider::OpenStack => {
+                openstack::fetch_identity(self.seed_path(), self.max_size).await
+            }
+        }
+    }
+
+    /// Fetch the instance tags or labels.
+    ///
+    /// - **AWS**: From `meta-data/tags/instance/*`. Returns `MetadataError::NotFound`
+    ///   if instance metadata tags are not enabled
+    /// - **GCP**: Instance attributes starting with the configured prefix.
+    ///   Returns `MetadataError::NotSupported` until a prefix is set with
+    ///   `with_gcp_tag_prefix`
+    /// - **Azure**: From `compute/tagsList`
+    /// - **OpenStack**: The `meta` entries of meta_data.json
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the tags cannot be fetched. Returns
+    /// `MetadataError::NotSupported` on NoCloud, which has no tags.
+    pub async fn tags(&self) -> Result<BTreeMap<String, String>, MetadataError> {
+        match self.provider {
+            CloudProvider::Aws => {
+                aws::fetch_tags(&self.client, &self.aws_session, self.max_size).await
+            }
+            CloudProvider::Gcp => match &self.gcp_tag_prefix {
+                Some(prefix) => gcp::fetch_tags(&self.client, prefix, self.max_size).await,
+                None => Err(MetadataError::NotSupported),
+            },
+            CloudProvider::Azure => {
+                azure::fetch_tags(&self.client, &self.azure_versions, self.max_size).await
+            }
+            CloudProvider::OpenStack => {
+                openstack::fetch_tags(self.seed_path(), self.max_size).await
+            }
+            CloudProvider::NoCloud => Err(MetadataError::NotSupported),
+        }
+    }
+
+    /// Fetch the network interfaces of the instance.
+    ///
+    /// - **AWS**: From `meta-data/network/interfaces/macs/*`, ordered by device number
+    /// - **GCP**: From `instance/network-interfaces/?recursive=true`
+    /// - **Azure**: From `/metadata/instance/network`
+    /// - **OpenStack**: From network_data.json
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the interfaces cannot be fetched. Returns
+    /// `MetadataError::NotSupported` on NoCloud.
+    pub async fn network(&self) -> Result<Vec<NetworkInterface>, MetadataError> {
+        match self.provider {
+            CloudProvider::Aws => {
+                aws::fetch_network(&self.client, &self.aws_session, self.max_size).await
+            }
+            CloudProvider::Gcp => gcp::fetch_network(&self.client, self.max_size).await,
+            CloudProvider::Azure => {
+                azure::fetch_network(&self.client, &self.azure_versions, self.max_size).await
+            }
+            CloudProvider::OpenStack => {
+                openstack::fetch_network(self.seed_path(), self.max_size).await
+            }
+            CloudProvider::NoCloud => Err(MetadataError::NotSupported),
+        }
+    }
+
+    /// Fetch a raw metadata path the crate does not model.
+    ///
+    /// The provider's authentication is applied and `max_size` is enforced:
+    ///
+    /// - **AWS**: IMDSv2 token, e.g. `/latest/meta-data/placement/partition-number`
+    /// - **GCP**: `Metadata-Flavor: Google`, e.g. `/computeMetadata/v1/instance/id`
+    /// - **Azure**: `Metadata: true` and the negotiated `api-version` (unless the
+    ///   path has one), e.g. `/metadata/instance/compute/vmId?format=text`
+    /// - **NoCloud**: File relative to the seed directory
+    /// - **OpenStack**: File relative to the config drive root
+    ///
+    /// # Errors
+    ///
+    /// Returns `MetadataError::InvalidKey` if a path for AWS, GCP or Azure
+    /// does not start with `/`, `MetadataError::NotFound` if the path does not
+    /// exist, or an error if the request fails.
+    pub async fn get_path(&self, path: &str) -> Result<Vec<u8>, MetadataError> {
+        match self.provider {
+            CloudProvider::Aws => {
+                aws::fetch_path(&self.client, &self.aws_session, path, self.max_size).await
+            }
+            CloudProvider::Gcp => gcp::fetch_path(&self.client, path, self.max_size).await,
+            CloudProvider::Azure => {
+                azure::fetch_path(&self.client, &self.azure_versions, path, self.max_size).await
+            }
+            CloudProvider::NoCloud => {
+                nocloud::fetch_path(self.seed_path(), path, self.max_size).await
+            }
+            CloudProvider::OpenStack => {
+                openstack::fetch_path(self.seed_path(), path, self.max_size).await
+            }
+        }
+    }
+
+    /// List the available metadata keys.
+    ///
+    /// - **AWS**: The `meta-data/` category listing (directories end with `/`)
+    /// - **GCP**: The instance attribute names
+    /// - **Azure**: The available `/metadata/instance/compute` fields
+    /// - **NoCloud**: The seed files present
+    /// - **OpenStack**: The `meta` keys of meta_data.json
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the listing cannot be fetched.
+    pub async fn list_keys(&self) -> Result<Vec<String>, MetadataError> {
+        match self.provider {
+            CloudProvider::Aws => {
+                aws::list_meta_data(&self.client, &self.aws_session, self.max_size).await
+            }
+            CloudProvider::Gcp => gcp::list_instance_attributes(&self.client, self.max_size).await,
+            CloudProvider::Azure => {
+                azure::list_compute_fields(&self.client, &self.azure_versions, self.max_size).await
+            }
+            CloudProvider::NoCloud => nocloud::list_files(self.seed_path()).await,
+            CloudProvider::OpenStack => {
+                openstack::list_meta_keys(self.seed_path(), self.max_size).await
+            }
+        }
+    }
+
+    /// List the GCP project attribute names.
+    ///
+    /// This method is only supported on GCP. On other providers, it returns
+    /// `MetadataError::NotSupported`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the listing cannot be fetched or if called on
+    /// a non-GCP provider.
+    pub async fn list_project_keys(&self) -> Result<Vec<String>, MetadataError> {
+        match self.provider {
+            CloudProvider::Gcp => gcp::list_project_attributes(&self.client, self.max_size).await,
+            _ => Err(MetadataError::NotSupported),
+        }
+    }
+
+    /// Fetch a GCP metadata directory recursively in a single request.
+    ///
+    /// `path` is relative to `/computeMetadata/v1/`, e.g. `instance` or
+    /// `project`; an empty path fetches everything. The result is
+    /// deserialized into `T`, which may be `serde_json::Value`.
+    ///
+    /// This method is only supported on GCP. On other providers, it returns
+    /// `MetadataError::NotSupported`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the tree cannot be fetched or deserialized, or if
+    /// called on a non-GCP provider.
+    pub async fn tree<T: DeserializeOwned>(&self, path: &str) -> Result<T, MetadataError> {
+        match self.provider {
+            CloudProvider::Gcp => {
+                let tree = gcp::fetch_tree(&self.client, path, self.max_size).await?;
+                serde_json::from_value(tree).map_err(MetadataError::from)
+            }
+            _ => Err(MetadataError::NotSupported),
+        }
+    }
+
+    /// Fetch the AWS instance identity document with its RSA-2048 signature.
+    ///
+    /// With the `verify` feature, the signature can be checked offline with
+    /// `verify::aws_identity_document`.
+    /// This method is only supported on AWS. On other providers, it returns
+    /// `MetadataError::NotSupported`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the document cannot be fetched or if called on
+    /// a non-AWS provider.
+    pub async fn aws_identity_document(&self) -> Result<AwsSignedIdentityDocument, MetadataError> {
+        match self.provider {
+            CloudProvider::Aws => {
+                aws::fetch_identity_document(&self.client, &self.aws_session, self.max_size).await
+            }
+            _ => Err(MetadataError::NotSupported),
+        }
+    }
+
+    /// Create a caching provider for the instance's IAM role credentials.
+    ///
+    /// The role is discovered from the instance profile unless set with
+    /// [`AwsCredentialProvider::with_role`]. This method is only supported on
+    /// AWS. On other providers, it returns `MetadataError::NotSupported`.
+    ///
+    /// # Errors
+    ///
+    /// Returns `MetadataError::NotSupported` if called on a non-AWS provider.
+    pub fn aws_credential_provider(&self) -> Result<AwsCredentialProvider, MetadataError> {
+        match self.provider {
+            CloudProvider::Aws => Ok(AwsCredentialProvider::new(
+                self.client.clone(),
+                self.aws_session.clone(),
+            )),
+            _ => Err(MetadataError::NotSupported),
+        }
+    }
+
+    /// Create a caching provider for GCP service-account tokens.
+    ///
+    /// The default service account is used unless another is set with
+    /// [`GcpTokenProvider::with_account`]. This method is only supported on
+    /// GCP. On other providers, it returns `MetadataError::NotSupported`.
+    ///
+    /// # Errors
+    ///
+    /// Returns `MetadataError::NotSupported` if called on a non-GCP provider.
+    pub fn gcp_token_provider(&self) -> Result<GcpTokenProvider, MetadataError> {
+        match self.provider {
+            CloudProvider::Gcp => Ok(GcpTokenProvider::new(self.client.clone())),
+            _ => Err(MetadataError::NotSupported),
+        }
+    }
+
+    /// Get read and write access to the GCP guest attributes of the instance.
+    ///
+    /// Writing is opt-in: access must be enabled with
+    /// `with_gcp_guest_attributes`, and the crate only writes to the metadata
+    /// server through the returned handle. Reads honor the maximum size. This
+    /// method is only supported on GCP. On other providers, it returns
+    /// `MetadataError::NotSupported`.
+    ///
+    /// # Errors
+    ///
+    /// Returns `MetadataError::NotSupported` if called on a non-GCP provider
+    /// or if guest attribute access is not enabled.
+    pub fn gcp_guest_attributes(&self) -> Result<GcpGuestAttributes, MetadataError> {
+        match self.provider {
+            CloudProvider::Gcp if self.gcp_guest_attributes => {
+                Ok(GcpGuestAttributes::new(self.client.clone(), self.max_size))
+            }
+            _ => Err(MetadataError::NotSupported),
+        }
+    }
+
+    /// List the service accounts attached to the GCP instance with their scopes.
     ///
     /// This method is only supported on GCP. On other providers, it returns
     /// `MetadataError::NotSupported`.
     ///
     /// # Errors
     ///
+    /// Returns an error if the listing cannot be fetched or if called on
+    /// a non-GCP provider.
+    pub async fn gcp_service_accounts(&self) -> Result<Vec<GcpServiceAccount>, MetadataError> {
+        match self.provider {
+            CloudProvider::Gcp => gcp::fetch_service_accounts(&self.client).await,
+            _ => Err(MetadataError::NotSupported),
+        }
+    }
+
+    /// Fetch the Azure attested data document for a caller-chosen `nonce`.
+    ///
+    /// With the `verify` feature, the document can be checked offline with
+    /// `verify::azure_attested_document`. This method is only supported on
+    /// Azure. On other providers, it returns `MetadataError::NotSupported`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the document cannot be fetched or if called on
+    /// a non-Azure provider.
+    pub async fn azure_attested_document(
+        &self,
+        nonce: &str,
+    ) -> Result<AzureAttestedDocument, MetadataError> {
+        match self.provider {
+            CloudProvider::Azure => {
+                azure::fetch_attested_document(
+                    &self.client,
+                    &self.azure_versions,
+                    nonce,
+                    self.max_size,
+                )
+                .await
+            }
+            _ => Err(MetadataError::NotSupported),
+        }
+    }
+
+    /// Create a caching provider for Azure managed identity tokens.
+    ///
+    /// The system-assigned identity is used unless a user-assigned identity
+    /// is set with [`AzureTokenProvider::with_identity`]. This method is only
+    /// supported on Azure. On other providers, it returns
+    /// `MetadataError::NotSupported`.
+    ///
+    /// # Errors
+    ///
+    /// Returns `MetadataError::NotSupported` if called on a non-Azure provider.
+    pub fn azure_token_provider(&self) -> Result<AzureTokenProvider, MetadataError> {
+        match self.provider {
+            CloudProvider::Azure => Ok(AzureTokenProvider::new(self.client.clone())),
+            _ => Err(MetadataError::NotSupported),
+        }
+    }
+
+    /// Fetch a GCP project-level attribute.
+    ///
+    /// The value is limited by `max_size` like `custom_data`. For the raw
+    /// bytes or JSON, use `with_gcp_attribute_scope` with
+    /// `GcpAttributeScope::Project`. This method is only supported on GCP. On
+    /// other providers, it returns `MetadataError::NotSupported`.
+    ///
+    /// # Errors
+    ///
     /// Returns an error if the attribute cannot be fetched or if called on
     /// a non-GCP provider.
     pub async fn project_attribute(&self, key: &str) -> Result<String, MetadataError> {
         match self.provider {
-            CloudProvider::Gcp => gcp::fetch_project_attribute(&self.client, key).await,
+            CloudProvider::Gcp => {
+                let data = gcp::fetch_project_attribute(&self.client, key, self.max_size).await?;
+                String::from_utf8(data).map_err(|_| MetadataError::Utf8)
+            }
             _ => Err(MetadataError::NotSupported),
         }
     }
+
+    /// Fetch vendor-data from a NoCloud seed or OpenStack config drive.
+    ///
+    /// On OpenStack this returns `vendor_data2.json`. This method is only
+    /// supported on NoCloud and OpenStack. On other providers, it returns
+    /// `MetadataError::NotSupported`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the vendor-data cannot be read or if called on
+    /// an unsupported provider.
+    pub async fn vendor_data(&self) -> Result<Vec<u8>, MetadataError> {
+        match self.provider {
+            CloudProvider::NoCloud => {
+                nocloud::fetch_vendor_data(self.seed_path(), self.max_size).await
+            }
+            CloudProvider::OpenStack => {
+                openstack::fetch_vendor_data(self.seed_path(), self.max_size).await
+            }
+            _ => Err(MetadataError::NotSupported),
+        }
+    }
+
+    /// Fetch and parse the `meta-data` file of a NoCloud seed.
+    ///
+    /// This method is only supported on NoCloud. On other providers, it returns
+    /// `MetadataError::NotSupported`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the meta-data cannot be read or parsed, or if called
+    /// on a non-NoCloud provider.
+    pub async fn nocloud_meta_data(&self) -> Result<NoCloudMetaData, MetadataError> {
+        match self.provider {
+            CloudProvider::NoCloud => {
+                nocloud::fetch_meta_data(self.seed_path(), self.max_size).await
+            }
+            _ => Err(MetadataError::NotSupported),
+        }
+    }
+
+    /// Fetch and parse `meta_data.json` from an OpenStack config drive.
+    ///
+    /// This method is only supported on OpenStack. On other providers, it
+    /// returns `MetadataError::NotSupported`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the meta-data cannot be read or parsed, or if called
+    /// on a non-OpenStack provider.
+    pub async fn openstack_meta_data(&self) -> Result<OpenStackMetaData, MetadataError> {
+        match self.provider {
+            CloudProvider::OpenStack => {
+                openstack::fetch_meta_data(self.seed_path(), self.max_size).await
+            }
+            _ => Err(MetadataError::NotSupported),
+        }
+    }
+
+    /// Fetch Azure custom data from the configured sources in order.
+    async fn azure_custom_data(&self) -> Result<Vec<u8>, MetadataError> {
+        for source in &self.azure_sources {
+            let result = match source {
+                AzureDataSource::UserData => {
+                    azure::fetch_user_data(&self.client, &self.azure_versions, self.max_size).await
+                }
+                AzureDataSource::CustomData => {
+                    match azure::fetch_custom_data(
+                        &self.client,
+                        &self.azure_versions,
+                        self.max_size,
+                    )
+                    .await
+                    {
+                        // IMDS returns empty customData on most VM types
+                        Err(MetadataError::NotFound) => {
+                            azure::fetch_ovf_custom_data(self.ovf_path.as_deref(), self.max_size)
+                                .await
+                        }
+                        result => result,
+                    }
+                }
+            };
+            match result {
+                Err(MetadataError::NotFound) => continue,
+                result => return result,
+            }
+        }
+        Err(MetadataError::NotFound)
+    }
+
+    /// Get the local seed path for file-based providers.
+    fn seed_path(&self) -> &Path {
+        let default = match self.provider {
+            CloudProvider::OpenStack => openstack::DEFAULT_CONFIG_DRIVE_PATH,
+            _ => nocloud::DEFAULT_SEED_PATH,
+        };
+        self.seed_path.as_deref().unwrap_or(Path::new(default))
+    }
+}
+
+/// Version of a watched value, used to detect changes.
+#[derive(Debug, PartialEq, Eq)]
+enum Version {
+    /// ETag of a GCP attribute.
+    Etag(String),
+    /// Hash of a polled value.
+    Hash(u64),
+    /// The value does not exist.
+    Missing,
+}
+
+/// Deserialize a watched value, reporting a missing value as `NotFound`.
+fn decode_json<T: DeserializeOwned>(data: Option<Vec<u8>>) -> Result<T, MetadataError> {
+    let data = data.ok_or(MetadataError::NotFound)?;
+    serde_json::from_slice(&data).map_err(MetadataError::from)
 }
 
 #[cfg(test)]
@@ -228,6 +1253,37 @@ mod tests {
         assert_eq!(metadata.provider(), CloudProvider::Azure);
     }
 
+    #[test]
+    fn test_nocloud_constructor() {
+        let metadata = CloudMetadata::nocloud();
+        assert_eq!(metadata.provider(), CloudProvider::NoCloud);
+        assert_eq!(metadata.seed_path(), Path::new(nocloud::DEFAULT_SEED_PATH));
+    }
+
+    #[test]
+    fn test_nocloud_with_path() {
+        let metadata = CloudMetadata::nocloud_with_path("/mnt/cidata");
+        assert_eq!(metadata.provider(), CloudProvider::NoCloud);
+        assert_eq!(metadata.seed_path(), Path::new("/mnt/cidata"));
+    }
+
+    #[test]
+    fn test_ovf_path_keeps_seed_path() {
+        let metadata = CloudMetadata::nocloud_with_path("/mnt/cidata").with_ovf_path("/mnt/ovf");
+        assert_eq!(metadata.seed_path(), Path::new("/mnt/cidata"));
+        assert_eq!(metadata.ovf_path.as_deref(), Some(Path::new("/mnt/ovf")));
+    }
+
+    #[test]
+    fn test_openstack_constructor() {
+        let metadata = CloudMetadata::openstack();
+        assert_eq!(metadata.provider(), CloudProvider::OpenStack);
+        assert_eq!(
+            metadata.seed_path(),
+            Path::new(openstack::DEFAULT_CONFIG_DRIVE_PATH)
+        );
+    }
+
     #[test]
     fn test_aws_with_base_url() {
         let metadata = CloudMetadata::aws_with_base_url("http://localhost:8080");
@@ -0,0 +1,54 @@
+//! Provider-neutral network interface information.
+
+use serde::Serialize;
+
+/// A network interface attached to the instance.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct NetworkInterface {
+    /// MAC address, lower case and colon separated.
+    pub mac: String,
+    /// Whether this is the primary interface.
+    pub primary: bool,
+    /// Private IPv4 addresses.
+    pub private_ipv4: Vec<String>,
+    /// IPv6 addresses.
+    pub ipv6: Vec<String>,
+    /// Public IPv4 addresses.
+    pub public_ipv4: Vec<String>,
+    /// Subnet id (AWS subnet id, Azure subnet prefix).
+    pub subnet_id: Option<String>,
+    /// Network id (AWS VPC id, GCP network name, OpenStack network id).
+    pub vpc_id: Option<String>,
+}
+
+/// Normalize a MAC address to lower case, colon-separated form.
+///
+/// Azure reports MACs as bare hex digits (`000D3AF806EC`).
+pub(crate) fn normalize_mac(mac: &str) -> String {
+    let hex: String = mac
+        .chars()
+        .filter(|c| c.is_ascii_hexdigit())
+        .map(|c| c.to_ascii_lowercase())
+        .collect();
+    if hex.len() != 12 {
+        return mac.to_ascii_lowercase();
+    }
+    hex.as_bytes()
+        .chunks(2)
+        .map(|pair| std::str::from_utf8(pair).unwrap_or_default())
+        .collect::<Vec<_>>()
+        .join(":")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_normalize_mac() {
+        assert_eq!(normalize_mac("000D3AF806EC"), "00:0d:3a:f8:06:ec");
+        assert_eq!(normalize_mac("0E:49:61:0F:C3:11"), "0e:49:61:0f:c3:11");
+        assert_eq!(normalize_mac("42:01:0a:80:00:02"), "42:01:0a:80:00:02");
+        assert_eq!(normalize_mac("bogus"), "bogus");
+    }
+}
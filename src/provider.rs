@@ -11,6 +11,10 @@ pub enum CloudProvider {
     Gcp,
     /// Microsoft Azure
     Azure,
+    /// cloud-init NoCloud seed (local files)
+    NoCloud,
+    /// OpenStack config drive (local files)
+    OpenStack,
 }
 
 impl fmt::Display for CloudProvider {
@@ -19,6 +23,8 @@ impl fmt::Display for CloudProvider {
             CloudProvider::Aws => write!(f, "AWS"),
             CloudProvider::Gcp => write!(f, "GCP"),
             CloudProvider::Azure => write!(f, "Azure"),
+            CloudProvider::NoCloud => write!(f, "NoCloud"),
+            CloudProvider::OpenStack => write!(f, "OpenStack"),
         }
     }
 }
@@ -32,6 +38,8 @@ mod tests {
         assert_eq!(CloudProvider::Aws.to_string(), "AWS");
         assert_eq!(CloudProvider::Gcp.to_string(), "GCP");
         assert_eq!(CloudProvider::Azure.to_string(), "Azure");
+        assert_eq!(CloudProvider::NoCloud.to_string(), "NoCloud");
+        assert_eq!(CloudProvider::OpenStack.to_string(), "OpenStack");
     }
 
     #[test]
@@ -1,7 +1,20 @@
 //! AWS IMDSv2 metadata implementation.
 
-use crate::client::{read_body_limited, MetadataClient};
-use crate::error::MetadataError;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime};
+
+use serde::Deserialize;
+use tokio::sync::Mutex;
+
+use crate::client::{check_absolute_path, encode_path_segment, read_body_limited, MetadataClient};
+use crate::credentials::{CredentialCache, Expiring};
+use crate::error::{optional, MetadataError};
+use crate::identity::{non_empty, InstanceIdentity};
+use crate::lifecycle::{LifecycleEvent, LifecycleEventKind, MAX_EVENT_SIZE};
+use crate::network::{normalize_mac, NetworkInterface};
+use crate::time::{parse_http_date, parse_timestamp};
 
 /// AWS IMDSv2 token endpoint path.
 const TOKEN_PATH: &str = "/latest/api/token";
@@ -9,6 +22,53 @@ const TOKEN_PATH: &str = "/latest/api/token";
 /// AWS user-data endpoint path.
 const USER_DATA_PATH: &str = "/latest/user-data";
 
+/// AWS meta-data root path.
+const META_DATA_PATH: &str = "/latest/meta-data/";
+
+/// AWS instance identity document path.
+const IDENTITY_DOCUMENT_PATH: &str = "/latest/dynamic/instance-identity/document";
+
+/// AWS identity document RSA-2048 PKCS7 signature path.
+const IDENTITY_SIGNATURE_PATH: &str = "/latest/dynamic/instance-identity/rsa2048";
+
+/// AWS local hostname path.
+const LOCAL_HOSTNAME_PATH: &str = "/latest/meta-data/local-hostname";
+
+/// AWS instance tags path (requires instance metadata tags to be enabled).
+const TAGS_PATH: &str = "/latest/meta-data/tags/instance";
+
+/// AWS network interfaces path, keyed by MAC address.
+const MACS_PATH: &str = "/latest/meta-data/network/interfaces/macs";
+
+/// AWS IAM role credentials path, keyed by role name.
+const SECURITY_CREDENTIALS_PATH: &str = "/latest/meta-data/iam/security-credentials/";
+
+/// Spot interruption notice path.
+const SPOT_ACTION_PATH: &str = "/latest/meta-data/spot/instance-action";
+
+/// Rebalance recommendation path.
+const REBALANCE_PATH: &str = "/latest/meta-data/events/recommendations/rebalance";
+
+/// Scheduled maintenance events path.
+const MAINTENANCE_PATH: &str = "/latest/meta-data/events/maintenance/scheduled";
+
+/// Auto Scaling target lifecycle state path.
+const TARGET_LIFECYCLE_STATE_PATH: &str = "/latest/meta-data/autoscaling/target-lifecycle-state";
+
+/// Refresh credentials this long before they expire.
+///
+/// IMDS rotates role credentials at least five minutes before expiry.
+const DEFAULT_REFRESH_MARGIN: Duration = Duration::from_secs(5 * 60);
+
+/// Default lifetime of IMDSv2 session tokens, the longest IMDS allows.
+pub const DEFAULT_TOKEN_TTL: Duration = Duration::from_secs(6 * 60 * 60);
+
+/// Refresh the session token this long before it expires.
+const TOKEN_REFRESH_MARGIN: Duration = Duration::from_secs(60);
+
+/// How long to wait for an IMDSv2 token when the IMDSv1 fallback is enabled.
+const TOKEN_TIMEOUT: Duration = Duration::from_secs(1);
+
 /// Token TTL header name.
 const TOKEN_TTL_HEADER: &str = "X-aws-ec2-metadata-token-ttl-seconds";
 
@@ -33,16 +93,30 @@ pub async fn probe(client: &MetadataClient) -> Result<(), MetadataError> {
     }
 }
 
-/// Get an IMDSv2 token.
-async fn get_token(client: &MetadataClient) -> Result<String, MetadataError> {
+/// Get an IMDSv2 token valid for `ttl`, waiting at most `timeout` if set and
+/// the client timeout otherwise.
+///
+/// Returns `MetadataError::HopLimit` if the token request times out while a
+/// plain GET to the metadata service gets a response.
+async fn get_token(
+    client: &MetadataClient,
+    ttl: Duration,
+    timeout: Option<Duration>,
+) -> Result<String, MetadataError> {
     let url = format!("{}{}", client.base_url(), TOKEN_PATH);
 
-    let response = client
+    let mut request = client
         .inner()
         .put(&url)
-        .header(TOKEN_TTL_HEADER, "60")
-        .send()
-        .await?;
+        .header(TOKEN_TTL_HEADER, ttl.as_secs().to_string());
+    if let Some(timeout) = timeout {
+        request = request.timeout(timeout);
+    }
+    let response = match request.send().await {
+        Ok(response) => response,
+        Err(e) if e.is_timeout() && reachable(client).await => return Err(MetadataError::HopLimit),
+        Err(e) => return Err(e.into()),
+    };
 
     if !response.status().is_success() {
         return Err(MetadataError::Http(response.status().as_u16()));
@@ -51,20 +125,360 @@ async fn get_token(client: &MetadataClient) -> Result<String, MetadataError> {
     response.text().await.map_err(MetadataError::from)
 }
 
+/// Whether the metadata service answers a plain GET.
+///
+/// The hop limit only applies to `PUT` responses, so a GET still gets
+/// through from a container when the token response is dropped.
+async fn reachable(client: &MetadataClient) -> bool {
+    let url = format!("{}{}", client.base_url(), META_DATA_PATH);
+    client
+        .inner()
+        .get(&url)
+        .timeout(TOKEN_TIMEOUT)
+        .send()
+        .await
+        .is_ok()
+}
+
+/// Whether IMDSv1 may be tried after the token request failed with `error`.
+///
+/// IMDS answers 403 when it is disabled for the instance.
+fn allows_imdsv1(error: &MetadataError) -> bool {
+    match error {
+        MetadataError::HopLimit => true,
+        MetadataError::Http(status) => *status != 403,
+        _ => false,
+    }
+}
+
+/// A cached IMDSv2 session token, or `None` when falling back to IMDSv1.
+struct SessionToken {
+    value: Option<String>,
+    refresh_at: Instant,
+}
+
+/// IMDSv2 session shared by all requests of a `CloudMetadata`.
+///
+/// The session token is fetched on first use, reused until shortly before
+/// it expires, and fetched again once if IMDS rejects it. Clones share the
+/// token.
+#[derive(Clone)]
+pub struct Session {
+    ttl: Duration,
+    imdsv1_fallback: bool,
+    token_timeout: Option<Duration>,
+    token: Arc<Mutex<Option<SessionToken>>>,
+}
+
+impl fmt::Debug for Session {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Session")
+            .field("ttl", &self.ttl)
+            .field("imdsv1_fallback", &self.imdsv1_fallback)
+            .field("token_timeout", &self.token_timeout)
+            .finish()
+    }
+}
+
+impl Default for Session {
+    fn default() -> Self {
+        Self {
+            ttl: DEFAULT_TOKEN_TTL,
+            imdsv1_fallback: false,
+            token_timeout: None,
+            token: Arc::new(Mutex::new(None)),
+        }
+    }
+}
+
+impl Session {
+    /// Use session tokens valid for `ttl`, clamped to 1 to 21600 seconds.
+    pub fn with_ttl(mut self, ttl: Duration) -> Self {
+        self.ttl = Duration::from_secs(ttl.as_secs().clamp(1, DEFAULT_TOKEN_TTL.as_secs()));
+        self
+    }
+
+    /// Fall back to IMDSv1 requests without a token if no token can be had.
+    ///
+    /// Unless a token timeout is set, the token request then gives up after
+    /// one second instead of the client timeout.
+    pub fn with_imdsv1_fallback(mut self, enabled: bool) -> Self {
+        self.imdsv1_fallback = enabled;
+        self
+    }
+
+    /// Wait at most `timeout` for a session token.
+    pub fn with_token_timeout(mut self, timeout: Duration) -> Self {
+        self.token_timeout = Some(timeout);
+        self
+    }
+
+    /// The timeout for token requests, `None` for the client timeout.
+    fn token_timeout(&self) -> Option<Duration> {
+        self.token_timeout
+            .or(self.imdsv1_fallback.then_some(TOKEN_TIMEOUT))
+    }
+
+    /// Return the cached token, fetching a new one if it is about to expire.
+    ///
+    /// With the IMDSv1 fallback, `None` means requests go without a token
+    /// until the next refresh.
+    async fn token(&self, client: &MetadataClient) -> Result<Option<String>, MetadataError> {
+        let mut cached = self.token.lock().await;
+        if let Some(token) = cached.as_ref() {
+            if Instant::now() < token.refresh_at {
+                return Ok(token.value.clone());
+            }
+        }
+
+        let requested = Instant::now();
+        let value = match get_token(client, self.ttl, self.token_timeout()).await {
+            Ok(value) => Some(value),
+            Err(e) if self.imdsv1_fallback && allows_imdsv1(&e) => None,
+            Err(e) => return Err(e),
+        };
+        *cached = Some(SessionToken {
+            value: value.clone(),
+            refresh_at: requested + self.ttl - TOKEN_REFRESH_MARGIN.min(self.ttl / 2),
+        });
+        Ok(value)
+    }
+
+    /// Drop `stale` from the cache unless it was already replaced.
+    async fn invalidate(&self, stale: Option<&str>) {
+        let mut cached = self.token.lock().await;
+        if cached
+            .as_ref()
+            .is_some_and(|token| token.value.as_deref() == stale)
+        {
+            *cached = None;
+        }
+    }
+
+    /// Fetch a metadata path, re-acquiring the token once if it is rejected.
+    async fn get(
+        &self,
+        client: &MetadataClient,
+        path: &str,
+        max_size: Option<usize>,
+    ) -> Result<Vec<u8>, MetadataError> {
+        let token = self.token(client).await?;
+        match get(client, token.as_deref(), path, max_size).await {
+            Err(MetadataError::Http(401)) => {
+                self.invalidate(token.as_deref()).await;
+                let token = self.token(client).await?;
+                get(client, token.as_deref(), path, max_size).await
+            }
+            result => result,
+        }
+    }
+}
+
+/// The instance identity document.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct AwsIdentityDocument {
+    /// The instance id.
+    pub instance_id: String,
+    /// The region.
+    pub region: String,
+    /// The availability zone.
+    pub availability_zone: String,
+    /// The instance type.
+    pub instance_type: String,
+    /// The AWS account id.
+    pub account_id: String,
+    /// The AMI id.
+    pub image_id: String,
+    /// The primary private IPv4 address.
+    pub private_ip: Option<String>,
+    /// The CPU architecture.
+    pub architecture: Option<String>,
+    /// When the instance was launched.
+    pub pending_time: Option<String>,
+}
+
+/// The instance identity document with its RSA-2048 PKCS7 signature.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AwsSignedIdentityDocument {
+    /// The parsed document.
+    pub document: AwsIdentityDocument,
+    /// The document as served by IMDS.
+    pub raw: String,
+    /// The base64-encoded PKCS7 signature from `instance-identity/rsa2048`.
+    pub signature: String,
+}
+
 /// Fetch user-data from AWS metadata service.
 pub async fn fetch_user_data(
     client: &MetadataClient,
+    session: &Session,
     max_size: Option<usize>,
 ) -> Result<Vec<u8>, MetadataError> {
-    let token = get_token(client).await?;
-    let url = format!("{}{}", client.base_url(), USER_DATA_PATH);
+    session.get(client, USER_DATA_PATH, max_size).await
+}
 
-    let response = client
-        .inner()
-        .get(&url)
-        .header(TOKEN_HEADER, &token)
-        .send()
+/// Fetch an arbitrary metadata path, e.g. `/latest/meta-data/placement/partition-number`.
+pub async fn fetch_path(
+    client: &MetadataClient,
+    session: &Session,
+    path: &str,
+    max_size: Option<usize>,
+) -> Result<Vec<u8>, MetadataError> {
+    check_absolute_path(path)?;
+    session.get(client, path, max_size).await
+}
+
+/// Fetch the instance identity document together with its signature.
+pub async fn fetch_identity_document(
+    client: &MetadataClient,
+    session: &Session,
+    max_size: Option<usize>,
+) -> Result<AwsSignedIdentityDocument, MetadataError> {
+    let (raw, signature) = tokio::try_join!(
+        session.get(client, IDENTITY_DOCUMENT_PATH, max_size),
+        session.get(client, IDENTITY_SIGNATURE_PATH, max_size),
+    )?;
+    let raw = String::from_utf8(raw).map_err(|_| MetadataError::Utf8)?;
+    let signature = String::from_utf8(signature).map_err(|_| MetadataError::Utf8)?;
+
+    Ok(AwsSignedIdentityDocument {
+        document: serde_json::from_str(&raw)?,
+        raw,
+        signature,
+    })
+}
+
+/// Fetch the instance identity from the identity document and meta-data.
+pub async fn fetch_identity(
+    client: &MetadataClient,
+    session: &Session,
+    max_size: Option<usize>,
+) -> Result<InstanceIdentity, MetadataError> {
+    let document = session
+        .get(client, IDENTITY_DOCUMENT_PATH, max_size)
         .await?;
+    let document: AwsIdentityDocument = serde_json::from_slice(&document)?;
+    let hostname = optional(session.get(client, LOCAL_HOSTNAME_PATH, max_size).await)?;
+
+    Ok(InstanceIdentity {
+        instance_id: non_empty(&document.instance_id),
+        region: non_empty(&document.region),
+        zone: non_empty(&document.availability_zone),
+        machine_type: non_empty(&document.instance_type),
+        hostname: hostname.and_then(|h| non_empty(&String::from_utf8_lossy(&h))),
+        account_id: non_empty(&document.account_id),
+        image_id: non_empty(&document.image_id),
+    })
+}
+
+/// List the meta-data categories. Directories end with `/`.
+pub async fn list_meta_data(
+    client: &MetadataClient,
+    session: &Session,
+    max_size: Option<usize>,
+) -> Result<Vec<String>, MetadataError> {
+    let body = session.get(client, META_DATA_PATH, max_size).await?;
+    let body = String::from_utf8(body).map_err(|_| MetadataError::Utf8)?;
+    Ok(body
+        .lines()
+        .filter(|l| !l.is_empty())
+        .map(str::to_string)
+        .collect())
+}
+
+/// Fetch instance tags.
+///
+/// Returns `MetadataError::NotFound` if instance metadata tags are not
+/// enabled for the instance.
+pub async fn fetch_tags(
+    client: &MetadataClient,
+    session: &Session,
+    max_size: Option<usize>,
+) -> Result<BTreeMap<String, String>, MetadataError> {
+    let keys = session.get(client, TAGS_PATH, max_size).await?;
+    let keys = String::from_utf8(keys).map_err(|_| MetadataError::Utf8)?;
+
+    let mut tags = BTreeMap::new();
+    // AWS rejects `.` and `..` as tag keys; they would escape the tags path
+    for key in keys
+        .lines()
+        .filter(|k| !k.is_empty() && *k != "." && *k != "..")
+    {
+        let path = format!("{}/{}", TAGS_PATH, encode_path_segment(key));
+        let value = session.get(client, &path, max_size).await?;
+        let value = String::from_utf8(value).map_err(|_| MetadataError::Utf8)?;
+        tags.insert(key.to_string(), value);
+    }
+    Ok(tags)
+}
+
+/// Fetch network interfaces, ordered by device number.
+pub async fn fetch_network(
+    client: &MetadataClient,
+    session: &Session,
+    max_size: Option<usize>,
+) -> Result<Vec<NetworkInterface>, MetadataError> {
+    let list = |path: String| async move {
+        let value = optional(session.get(client, &path, max_size).await)?.unwrap_or_default();
+        let value = String::from_utf8(value).map_err(|_| MetadataError::Utf8)?;
+        Ok::<_, MetadataError>(
+            value
+                .lines()
+                .map(|l| l.trim().trim_end_matches('/').to_string())
+                .filter(|l| !l.is_empty())
+                .collect::<Vec<_>>(),
+        )
+    };
+
+    let mut interfaces = Vec::new();
+    for mac in list(format!("{}/", MACS_PATH)).await? {
+        let field = |name: &str| format!("{}/{}/{}", MACS_PATH, mac, name);
+        let (device_number, private_ipv4, ipv6, public_ipv4, subnet_id, vpc_id) = tokio::try_join!(
+            list(field("device-number")),
+            list(field("local-ipv4s")),
+            list(field("ipv6s")),
+            list(field("public-ipv4s")),
+            list(field("subnet-id")),
+            list(field("vpc-id")),
+        )?;
+        let device_number = device_number
+            .first()
+            .and_then(|n| n.parse::<u32>().ok())
+            .unwrap_or(u32::MAX);
+
+        interfaces.push((
+            device_number,
+            NetworkInterface {
+                mac: normalize_mac(&mac),
+                primary: device_number == 0,
+                private_ipv4,
+                ipv6,
+                public_ipv4,
+                subnet_id: subnet_id.into_iter().next(),
+                vpc_id: vpc_id.into_iter().next(),
+            },
+        ));
+    }
+
+    interfaces.sort_by_key(|(device_number, _)| *device_number);
+    Ok(interfaces.into_iter().map(|(_, i)| i).collect())
+}
+
+/// Fetch a metadata path using an IMDSv2 token, or IMDSv1 without one.
+async fn get(
+    client: &MetadataClient,
+    token: Option<&str>,
+    path: &str,
+    max_size: Option<usize>,
+) -> Result<Vec<u8>, MetadataError> {
+    let url = format!("{}{}", client.base_url(), path);
+
+    let mut request = client.inner().get(&url);
+    if let Some(token) = token {
+        request = request.header(TOKEN_HEADER, token);
+    }
+    let response = request.send().await?;
 
     let status = response.status();
     if status.as_u16() == 404 {
@@ -77,6 +491,284 @@ pub async fn fetch_user_data(
     read_body_limited(response, max_size).await
 }
 
+/// Spot interruption notice.
+#[derive(Deserialize)]
+struct SpotAction {
+    time: String,
+}
+
+/// Scheduled maintenance event.
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct MaintenanceEvent {
+    code: String,
+    #[serde(default)]
+    event_id: Option<String>,
+    #[serde(default)]
+    not_before: String,
+    #[serde(default)]
+    state: String,
+}
+
+/// Fetch the pending lifecycle events.
+///
+/// Spot interruptions, rebalance recommendations, active scheduled
+/// maintenance and an Auto Scaling `Terminated` target state are reported.
+/// Each source is fetched and parsed on its own, so a failing source shows
+/// up as an error next to the events of the others.
+pub async fn fetch_lifecycle_events(
+    client: &MetadataClient,
+    session: &Session,
+) -> Vec<Result<LifecycleEvent, MetadataError>> {
+    let text = |path| async move {
+        optional(session.get(client, path, Some(MAX_EVENT_SIZE)).await)?
+            .map(|body| String::from_utf8(body).map_err(|_| MetadataError::Utf8))
+            .transpose()
+    };
+    let (spot, rebalance, maintenance, target_state) = tokio::join!(
+        text(SPOT_ACTION_PATH),
+        text(REBALANCE_PATH),
+        text(MAINTENANCE_PATH),
+        text(TARGET_LIFECYCLE_STATE_PATH),
+    );
+
+    let mut events = parse_source(spot, |raw| vec![spot_event(raw)]);
+    events.extend(parse_source(rebalance, |raw| {
+        vec![Ok(LifecycleEvent {
+            kind: LifecycleEventKind::Rebalance,
+            id: None,
+            deadline: None,
+            raw,
+        })]
+    }));
+    events.extend(parse_source(maintenance, |raw| maintenance_events(&raw)));
+    events.extend(parse_source(target_state, |raw| {
+        if raw.trim() != "Terminated" {
+            return Vec::new();
+        }
+        vec![Ok(LifecycleEvent {
+            kind: LifecycleEventKind::Termination,
+            id: None,
+            deadline: None,
+            raw,
+        })]
+    }));
+    events
+}
+
+/// Parse the response of one lifecycle event source with `parse`, keeping a
+/// failed request as an error.
+fn parse_source(
+    source: Result<Option<String>, MetadataError>,
+    parse: impl FnOnce(String) -> Vec<Result<LifecycleEvent, MetadataError>>,
+) -> Vec<Result<LifecycleEvent, MetadataError>> {
+    match source {
+        Ok(Some(raw)) => parse(raw),
+        Ok(None) => Vec::new(),
+        Err(e) => vec![Err(e)],
+    }
+}
+
+/// Parse a spot interruption notice. The action time is the deadline.
+fn spot_event(raw: String) -> Result<LifecycleEvent, MetadataError> {
+    let action: SpotAction = serde_json::from_str(&raw)?;
+    Ok(LifecycleEvent {
+        kind: LifecycleEventKind::Preemption,
+        id: None,
+        deadline: parse_timestamp(&action.time),
+        raw,
+    })
+}
+
+/// Parse the scheduled maintenance events that are still active.
+///
+/// Events that fail to parse are reported as errors without dropping the
+/// others.
+fn maintenance_events(raw: &str) -> Vec<Result<LifecycleEvent, MetadataError>> {
+    if raw.trim().is_empty() {
+        return Vec::new();
+    }
+    let values: Vec<serde_json::Value> = match serde_json::from_str(raw) {
+        Ok(values) => values,
+        Err(e) => return vec![Err(e.into())],
+    };
+
+    let mut events = Vec::new();
+    for value in values {
+        let event = match MaintenanceEvent::deserialize(&value) {
+            Ok(event) => event,
+            Err(e) => {
+                events.push(Err(e.into()));
+                continue;
+            }
+        };
+        if matches!(event.state.as_str(), "completed" | "canceled") {
+            continue;
+        }
+        let kind = match event.code.as_str() {
+            "instance-reboot" | "system-reboot" => LifecycleEventKind::Reboot,
+            "instance-stop" | "instance-retirement" => LifecycleEventKind::Termination,
+            _ => LifecycleEventKind::Maintenance,
+        };
+        events.push(Ok(LifecycleEvent {
+            kind,
+            id: event.event_id,
+            deadline: parse_http_date(&event.not_before),
+            raw: value.to_string(),
+        }));
+    }
+    events
+}
+
+/// Temporary IAM role credentials served by IMDS.
+#[derive(Clone, PartialEq, Eq)]
+pub struct AwsCredentials {
+    /// The role the credentials belong to.
+    pub role: String,
+    /// The access key id.
+    pub access_key_id: String,
+    /// The secret access key.
+    pub secret_access_key: String,
+    /// The session token.
+    pub token: String,
+    /// When the credentials expire.
+    pub expiration: SystemTime,
+}
+
+impl fmt::Debug for AwsCredentials {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AwsCredentials")
+            .field("role", &self.role)
+            .field("access_key_id", &self.access_key_id)
+            .field("secret_access_key", &"<redacted>")
+            .field("token", &"<redacted>")
+            .field("expiration", &self.expiration)
+            .finish()
+    }
+}
+
+impl Expiring for AwsCredentials {
+    fn expires_at(&self) -> SystemTime {
+        self.expiration
+    }
+}
+
+/// IAM role credentials document.
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct CredentialsDocument {
+    code: String,
+    #[serde(default)]
+    message: Option<String>,
+    #[serde(default)]
+    access_key_id: String,
+    #[serde(default)]
+    secret_access_key: String,
+    #[serde(default)]
+    token: String,
+    #[serde(default)]
+    expiration: String,
+}
+
+/// Discover the IAM role attached to the instance profile.
+///
+/// Returns `MetadataError::NotFound` if the instance has no instance profile.
+pub async fn fetch_role_name(
+    client: &MetadataClient,
+    session: &Session,
+) -> Result<String, MetadataError> {
+    let body = session.get(client, SECURITY_CREDENTIALS_PATH, None).await?;
+    let body = String::from_utf8(body).map_err(|_| MetadataError::Utf8)?;
+    body.lines()
+        .map(str::trim)
+        .find(|l| !l.is_empty())
+        .map(str::to_string)
+        .ok_or(MetadataError::NotFound)
+}
+
+/// Fetch the IAM role credentials for `role`.
+pub async fn fetch_credentials(
+    client: &MetadataClient,
+    session: &Session,
+    role: &str,
+) -> Result<AwsCredentials, MetadataError> {
+    let path = format!("{}{}", SECURITY_CREDENTIALS_PATH, encode_path_segment(role));
+    let body = session.get(client, &path, None).await?;
+    let doc: CredentialsDocument = serde_json::from_slice(&body)?;
+
+    if doc.code != "Success" {
+        return Err(MetadataError::Credentials(doc.message.unwrap_or(doc.code)));
+    }
+    let expiration = parse_timestamp(&doc.expiration).ok_or_else(|| {
+        MetadataError::Credentials(format!("invalid expiration: {}", doc.expiration))
+    })?;
+
+    Ok(AwsCredentials {
+        role: role.to_string(),
+        access_key_id: doc.access_key_id,
+        secret_access_key: doc.secret_access_key,
+        token: doc.token,
+        expiration,
+    })
+}
+
+/// Caching provider for IAM role credentials.
+///
+/// Credentials are fetched on first use and refreshed once they are within
+/// the refresh margin of their expiration. Clones share the cache.
+#[derive(Debug, Clone)]
+pub struct AwsCredentialProvider {
+    client: MetadataClient,
+    session: Session,
+    role: Option<String>,
+    cache: CredentialCache<(), AwsCredentials>,
+}
+
+impl AwsCredentialProvider {
+    pub(crate) fn new(client: MetadataClient, session: Session) -> Self {
+        Self {
+            client,
+            session,
+            role: None,
+            cache: CredentialCache::new(DEFAULT_REFRESH_MARGIN),
+        }
+    }
+
+    /// Use the credentials of `role` instead of discovering the role name.
+    pub fn with_role(mut self, role: &str) -> Self {
+        self.role = Some(role.to_string());
+        self
+    }
+
+    /// Set how long before expiration the credentials are refreshed.
+    ///
+    /// Defaults to five minutes.
+    pub fn with_refresh_margin(mut self, margin: Duration) -> Self {
+        self.cache = self.cache.with_refresh_margin(margin);
+        self
+    }
+
+    /// Return valid credentials, fetching new ones if needed.
+    ///
+    /// # Errors
+    ///
+    /// Returns `MetadataError::NotFound` if the instance has no instance
+    /// profile, `MetadataError::Credentials` if IMDS reports a failure, or an
+    /// error if the request fails.
+    pub async fn credentials(&self) -> Result<AwsCredentials, MetadataError> {
+        self.cache
+            .get((), |stale| async move {
+                let role = match (&self.role, stale) {
+                    (Some(role), _) => role.clone(),
+                    (None, Some(credentials)) => credentials.role,
+                    (None, None) => fetch_role_name(&self.client, &self.session).await?,
+                };
+                fetch_credentials(&self.client, &self.session, &role).await
+            })
+            .await
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -85,5 +777,128 @@ mod tests {
     fn test_paths() {
         assert_eq!(TOKEN_PATH, "/latest/api/token");
         assert_eq!(USER_DATA_PATH, "/latest/user-data");
+        assert!(IDENTITY_DOCUMENT_PATH.starts_with("/latest/dynamic/"));
+        assert!(IDENTITY_SIGNATURE_PATH.starts_with("/latest/dynamic/"));
+        assert!(LOCAL_HOSTNAME_PATH.starts_with(META_DATA_PATH));
+        assert!(TAGS_PATH.starts_with(META_DATA_PATH));
+        assert!(MACS_PATH.starts_with(META_DATA_PATH));
+        assert!(SECURITY_CREDENTIALS_PATH.starts_with(META_DATA_PATH));
+    }
+
+    #[test]
+    fn test_session_ttl_clamped() {
+        assert_eq!(Session::default().ttl, DEFAULT_TOKEN_TTL);
+        assert_eq!(
+            Session::default().with_ttl(Duration::from_secs(300)).ttl,
+            Duration::from_secs(300)
+        );
+        assert_eq!(
+            Session::default().with_ttl(Duration::ZERO).ttl,
+            Duration::from_secs(1)
+        );
+        assert_eq!(
+            Session::default().with_ttl(Duration::from_secs(86_400)).ttl,
+            DEFAULT_TOKEN_TTL
+        );
+    }
+
+    #[test]
+    fn test_token_timeout() {
+        assert_eq!(Session::default().token_timeout(), None);
+        assert_eq!(
+            Session::default()
+                .with_imdsv1_fallback(true)
+                .token_timeout(),
+            Some(TOKEN_TIMEOUT)
+        );
+        assert_eq!(
+            Session::default()
+                .with_imdsv1_fallback(true)
+                .with_token_timeout(Duration::from_secs(3))
+                .token_timeout(),
+            Some(Duration::from_secs(3))
+        );
+    }
+
+    #[test]
+    fn test_allows_imdsv1() {
+        assert!(allows_imdsv1(&MetadataError::HopLimit));
+        assert!(allows_imdsv1(&MetadataError::Http(405)));
+        assert!(!allows_imdsv1(&MetadataError::Http(403)));
+        assert!(!allows_imdsv1(&MetadataError::NotFound));
+    }
+
+    #[test]
+    fn test_spot_event() {
+        let event = spot_event(r#"{"action": "terminate", "time": "2017-09-18T08:22:00Z"}"#.into())
+            .unwrap();
+        assert_eq!(event.kind, LifecycleEventKind::Preemption);
+        assert_eq!(event.deadline, parse_timestamp("2017-09-18T08:22:00Z"));
+        assert!(spot_event("terminate".into()).is_err());
+    }
+
+    #[test]
+    fn test_maintenance_events() {
+        let raw = r#"[
+            {"Code": "system-reboot", "Description": "scheduled reboot",
+             "EventId": "instance-event-0d59937288b749b32", "NotBefore": "21 Jan 2019 09:00:43 GMT",
+             "NotAfter": "21 Jan 2019 09:17:23 GMT", "State": "active"},
+            {"Code": "instance-retirement", "EventId": "instance-event-1",
+             "NotBefore": "22 Jan 2019 09:00:43 GMT", "State": "active"},
+            {"Code": "system-maintenance", "EventId": "instance-event-2",
+             "NotBefore": "20 Jan 2019 09:00:43 GMT", "State": "completed"},
+            {"Code": 42}
+        ]"#;
+        let events = maintenance_events(raw);
+        assert_eq!(events.len(), 3);
+        assert!(matches!(events[2], Err(MetadataError::Json(_))));
+        let events: Vec<_> = events.into_iter().filter_map(Result::ok).collect();
+        assert_eq!(events[0].kind, LifecycleEventKind::Reboot);
+        assert_eq!(
+            events[0].id.as_deref(),
+            Some("instance-event-0d59937288b749b32")
+        );
+        assert_eq!(
+            events[0].deadline,
+            parse_http_date("21 Jan 2019 09:00:43 GMT")
+        );
+        assert_eq!(events[1].kind, LifecycleEventKind::Termination);
+        assert!(maintenance_events("").is_empty());
+        assert!(matches!(
+            maintenance_events("not json")[..],
+            [Err(MetadataError::Json(_))]
+        ));
+    }
+
+    #[test]
+    fn test_credentials_debug() {
+        let now = SystemTime::UNIX_EPOCH + Duration::from_secs(1_000);
+        let credentials = AwsCredentials {
+            role: "role".to_string(),
+            access_key_id: "AKID".to_string(),
+            secret_access_key: "wJalrXUtnFEMI".to_string(),
+            token: "token".to_string(),
+            expiration: now + Duration::from_secs(600),
+        };
+        assert_eq!(credentials.expires_at(), now + Duration::from_secs(600));
+        assert!(!format!("{:?}", credentials).contains("wJalrXUtnFEMI"));
+    }
+
+    #[test]
+    fn test_parse_identity_document() {
+        let json = r#"{
+            "accountId": "123456789012",
+            "architecture": "x86_64",
+            "availabilityZone": "us-east-1a",
+            "imageId": "ami-0abcdef1234567890",
+            "instanceId": "i-1234567890abcdef0",
+            "instanceType": "t3.micro",
+            "privateIp": "10.0.0.1",
+            "region": "us-east-1"
+        }"#;
+        let doc: AwsIdentityDocument = serde_json::from_str(json).unwrap();
+        assert_eq!(doc.instance_id, "i-1234567890abcdef0");
+        assert_eq!(doc.availability_zone, "us-east-1a");
+        assert_eq!(doc.private_ip.as_deref(), Some("10.0.0.1"));
     }
 }
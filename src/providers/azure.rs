@@ -1,26 +1,233 @@
 //! Azure metadata implementation with base64 decoding.
 
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
 use base64::engine::general_purpose::STANDARD;
 use base64::Engine;
+use serde::Deserialize;
+use tokio::sync::OnceCell;
 
-use crate::client::{read_body_limited, MetadataClient};
+use crate::client::{check_absolute_path, read_body_limited, MetadataClient};
+use crate::credentials::{CredentialCache, Expiring};
 use crate::error::MetadataError;
+use crate::identity::{azure_zone, non_empty, InstanceIdentity};
+use crate::iso9660;
+use crate::lifecycle::{LifecycleEvent, LifecycleEventKind, MAX_EVENT_SIZE};
+use crate::local::{map_io_error, read_file_limited};
+use crate::network::{normalize_mac, NetworkInterface};
+use crate::time::parse_http_date;
 
 /// Azure metadata service base path.
 const METADATA_PATH: &str = "/metadata";
 
+/// Azure compute metadata endpoint path.
+const COMPUTE_PATH: &str = "/metadata/instance/compute";
+
+/// Azure network metadata endpoint path.
+const NETWORK_PATH: &str = "/metadata/instance/network";
+
+/// Azure tags endpoint path.
+const TAGS_LIST_PATH: &str = "/metadata/instance/compute/tagsList";
+
 /// Azure customData endpoint path.
 const CUSTOM_DATA_PATH: &str = "/metadata/instance/compute/customData";
 
-/// API version query parameter.
+/// Azure userData endpoint path (requires api-version 2021-01-01 or newer).
+const USER_DATA_PATH: &str = "/metadata/instance/compute/userData";
+
+/// Attested data document path.
+const ATTESTED_PATH: &str = "/metadata/attested/document";
+
+/// Attested data endpoint path.
+const ATTESTED_PREFIX: &str = "/metadata/attested";
+
+/// Managed identity endpoint path.
+const IDENTITY_PREFIX: &str = "/metadata/identity";
+
+/// Managed identity token path.
+const IDENTITY_TOKEN_PATH: &str = "/metadata/identity/oauth2/token";
+
+/// API version of the managed identity endpoint.
+///
+/// The identity endpoint is versioned separately from `/metadata/instance`
+/// and is not covered by the `/metadata/versions` listing.
+const IDENTITY_API_VERSION: &str = "2018-02-01";
+
+/// Scheduled events path.
+const SCHEDULED_EVENTS_PATH: &str = "/metadata/scheduledevents";
+
+/// API version of the scheduled events endpoint, which is versioned
+/// separately from `/metadata/instance`.
+const SCHEDULED_EVENTS_API_VERSION: &str = "2020-07-01";
+
+/// Timeout for fetching scheduled events. The first request enables the
+/// service and can take up to two minutes.
+const SCHEDULED_EVENTS_TIMEOUT: Duration = Duration::from_secs(120);
+
+/// Refresh tokens this long before they expire.
+const DEFAULT_REFRESH_MARGIN: Duration = Duration::from_secs(5 * 60);
+
+/// Azure api-version listing endpoint path.
+const VERSIONS_PATH: &str = "/metadata/versions";
+
+/// API version used for detection, and when negotiation is not possible.
 const API_VERSION: &str = "2021-02-01";
 
+/// Newest API version this crate has been tested against. Negotiation never
+/// picks a newer one, so response formats stay stable.
+const MAX_API_VERSION: &str = "2023-07-01";
+
 /// Required header for Azure metadata requests.
 const METADATA_HEADER: &str = "Metadata";
 
 /// Required header value for Azure metadata requests.
 const METADATA_VALUE: &str = "true";
 
+/// Maximum accepted size of the api-version listing.
+const MAX_VERSIONS_SIZE: usize = 64 * 1024;
+
+/// Size limit for managed identity token responses.
+const MAX_TOKEN_SIZE: usize = 64 * 1024;
+
+/// OVF provisioning file name.
+const OVF_FILE_NAME: &str = "ovf-env.xml";
+
+/// Default OVF locations: the copy kept by the Azure Linux agent, then the
+/// provisioning ISO itself.
+const DEFAULT_OVF_PATHS: &[&str] = &["/var/lib/waagent/ovf-env.xml", "/dev/sr0"];
+
+/// OVF element holding base64-encoded customData.
+const CUSTOM_DATA_ELEMENT: &str = "CustomData";
+
+/// Azure sources for custom data.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum AzureDataSource {
+    /// The IMDS `userData` field.
+    UserData,
+    /// The `customData` field, from IMDS or the OVF provisioning file.
+    CustomData,
+}
+
+/// Default order in which Azure sources are tried.
+pub const DEFAULT_SOURCES: &[AzureDataSource] =
+    &[AzureDataSource::UserData, AzureDataSource::CustomData];
+
+/// Azure IMDS endpoints with distinct api-version requirements.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Endpoint {
+    /// Compute metadata (JSON format).
+    Compute,
+    /// `customData` (text format).
+    CustomData,
+    /// `userData`.
+    UserData,
+    /// `tagsList`.
+    TagsList,
+    /// Network metadata (JSON format).
+    Network,
+    /// Attested data document.
+    Attested,
+}
+
+impl Endpoint {
+    /// Oldest API version serving this endpoint.
+    fn min_version(self) -> &'static str {
+        match self {
+            Endpoint::Compute => "2019-06-01",
+            Endpoint::CustomData => "2019-02-01",
+            Endpoint::UserData => "2021-01-01",
+            Endpoint::TagsList => "2019-06-04",
+            Endpoint::Network => "2019-06-01",
+            Endpoint::Attested => "2018-10-01",
+        }
+    }
+}
+
+/// Response of the api-version listing endpoint.
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Versions {
+    api_versions: Vec<String>,
+}
+
+/// API versions available on the IMDS endpoint.
+///
+/// The listing is fetched once and cached for the lifetime of the owning
+/// `CloudMetadata` instance. Clones share the cache.
+#[derive(Debug, Default, Clone)]
+pub struct ApiVersions {
+    override_version: Option<String>,
+    available: Arc<OnceCell<Vec<String>>>,
+}
+
+impl ApiVersions {
+    /// Use a fixed API version for all endpoints instead of negotiating.
+    pub fn with_override(version: &str) -> Self {
+        Self {
+            override_version: Some(version.to_string()),
+            available: Arc::default(),
+        }
+    }
+
+    /// Resolve the API version to use for an endpoint.
+    ///
+    /// Picks the newest version offered by IMDS that the crate supports for
+    /// the endpoint. Negotiation is best-effort: if IMDS does not list its
+    /// versions, a pinned version is used.
+    pub async fn resolve(&self, client: &MetadataClient, endpoint: Endpoint) -> String {
+        if let Some(version) = &self.override_version {
+            return version.clone();
+        }
+
+        let available = self
+            .available
+            .get_or_init(|| async { fetch_versions(client).await.unwrap_or_default() })
+            .await;
+
+        select_version(available, endpoint)
+            .unwrap_or_else(|| API_VERSION.max(endpoint.min_version()))
+            .to_string()
+    }
+}
+
+/// Fetch the API versions offered by IMDS.
+///
+/// An error response or unparsable listing yields an empty listing.
+async fn fetch_versions(client: &MetadataClient) -> Result<Vec<String>, MetadataError> {
+    let url = format!("{}{}", client.base_url(), VERSIONS_PATH);
+
+    let response = client
+        .inner()
+        .get(&url)
+        .header(METADATA_HEADER, METADATA_VALUE)
+        .send()
+        .await?;
+
+    if !response.status().is_success() {
+        return Ok(Vec::new());
+    }
+
+    let body = read_body_limited(response, Some(MAX_VERSIONS_SIZE)).await?;
+    Ok(serde_json::from_slice::<Versions>(&body)
+        .map(|v| v.api_versions)
+        .unwrap_or_default())
+}
+
+/// Pick the newest available version supported for an endpoint.
+///
+/// Versions are `YYYY-MM-DD` dates, so they order lexicographically.
+fn select_version(available: &[String], endpoint: Endpoint) -> Option<&str> {
+    available
+        .iter()
+        .map(String::as_str)
+        .filter(|v| *v >= endpoint.min_version() && *v <= MAX_API_VERSION)
+        .max()
+}
+
 /// Probe Azure metadata service to check if we're running on Azure.
 pub async fn probe(client: &MetadataClient) -> Result<(), MetadataError> {
     let url = format!(
@@ -49,14 +256,416 @@ pub async fn probe(client: &MetadataClient) -> Result<(), MetadataError> {
 /// The max_size limit applies to the decoded data size.
 pub async fn fetch_custom_data(
     client: &MetadataClient,
+    versions: &ApiVersions,
+    max_size: Option<usize>,
+) -> Result<Vec<u8>, MetadataError> {
+    let api_version = versions.resolve(client, Endpoint::CustomData).await;
+    fetch_base64(client, CUSTOM_DATA_PATH, &api_version, max_size).await
+}
+
+/// Fetch userData from Azure metadata service.
+/// Azure returns base64-encoded data, which is automatically decoded.
+/// The max_size limit applies to the decoded data size.
+pub async fn fetch_user_data(
+    client: &MetadataClient,
+    versions: &ApiVersions,
     max_size: Option<usize>,
 ) -> Result<Vec<u8>, MetadataError> {
-    let url = format!("{}{}", client.base_url(), CUSTOM_DATA_PATH);
+    let api_version = versions.resolve(client, Endpoint::UserData).await;
+    fetch_base64(client, USER_DATA_PATH, &api_version, max_size).await
+}
+
+/// Fetch the instance identity from compute metadata.
+pub async fn fetch_identity(
+    client: &MetadataClient,
+    versions: &ApiVersions,
+    max_size: Option<usize>,
+) -> Result<InstanceIdentity, MetadataError> {
+    let compute = fetch_json(client, versions, Endpoint::Compute, COMPUTE_PATH, max_size).await?;
+    let field = |name: &str| compute.get(name).and_then(|v| v.as_str()).unwrap_or("");
+
+    let hostname = compute
+        .pointer("/osProfile/computerName")
+        .and_then(|v| v.as_str())
+        .and_then(non_empty)
+        .or_else(|| non_empty(field("name")));
+
+    Ok(InstanceIdentity {
+        instance_id: non_empty(field("vmId")),
+        region: non_empty(field("location")),
+        zone: azure_zone(field("location"), field("zone")),
+        machine_type: non_empty(field("vmSize")),
+        hostname,
+        account_id: non_empty(field("subscriptionId")),
+        image_id: compute
+            .pointer("/storageProfile/imageReference")
+            .and_then(image_id),
+    })
+}
+
+/// List the available compute metadata fields.
+pub async fn list_compute_fields(
+    client: &MetadataClient,
+    versions: &ApiVersions,
+    max_size: Option<usize>,
+) -> Result<Vec<String>, MetadataError> {
+    let compute = fetch_json(client, versions, Endpoint::Compute, COMPUTE_PATH, max_size).await?;
+    let compute = compute.as_object().ok_or(MetadataError::NotFound)?;
+    Ok(compute.keys().cloned().collect())
+}
+
+/// A single entry of `tagsList`.
+#[derive(Deserialize)]
+struct Tag {
+    name: String,
+    value: String,
+}
+
+/// Fetch instance tags from `tagsList`.
+pub async fn fetch_tags(
+    client: &MetadataClient,
+    versions: &ApiVersions,
+    max_size: Option<usize>,
+) -> Result<BTreeMap<String, String>, MetadataError> {
+    let tags = fetch_json(
+        client,
+        versions,
+        Endpoint::TagsList,
+        TAGS_LIST_PATH,
+        max_size,
+    )
+    .await?;
+    let tags: Vec<Tag> = serde_json::from_value(tags)?;
+    Ok(tags.into_iter().map(|t| (t.name, t.value)).collect())
+}
+
+/// Network metadata document.
+#[derive(Deserialize)]
+struct Network {
+    #[serde(default)]
+    interface: Vec<Interface>,
+}
+
+/// A network interface from network metadata.
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Interface {
+    mac_address: String,
+    #[serde(default)]
+    ipv4: IpConfig,
+    #[serde(default)]
+    ipv6: IpConfig,
+}
+
+/// IPv4 or IPv6 configuration of an interface.
+#[derive(Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct IpConfig {
+    #[serde(default)]
+    ip_address: Vec<IpAddress>,
+    #[serde(default)]
+    subnet: Vec<Subnet>,
+}
+
+/// An address assigned to an interface.
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct IpAddress {
+    #[serde(default)]
+    private_ip_address: String,
+    #[serde(default)]
+    public_ip_address: String,
+}
+
+/// A subnet of an interface.
+#[derive(Deserialize)]
+struct Subnet {
+    address: String,
+    prefix: String,
+}
+
+/// Fetch network interfaces. The first interface is the primary one.
+pub async fn fetch_network(
+    client: &MetadataClient,
+    versions: &ApiVersions,
+    max_size: Option<usize>,
+) -> Result<Vec<NetworkInterface>, MetadataError> {
+    let network = fetch_json(client, versions, Endpoint::Network, NETWORK_PATH, max_size).await?;
+    let network: Network = serde_json::from_value(network)?;
+
+    Ok(network
+        .interface
+        .into_iter()
+        .enumerate()
+        .map(|(index, interface)| {
+            let addresses = |config: &IpConfig, public: bool| {
+                config
+                    .ip_address
+                    .iter()
+                    .map(|a| {
+                        if public {
+                            a.public_ip_address.clone()
+                        } else {
+                            a.private_ip_address.clone()
+                        }
+                    })
+                    .filter(|ip| !ip.is_empty())
+                    .collect()
+            };
+            NetworkInterface {
+                mac: normalize_mac(&interface.mac_address),
+                primary: index == 0,
+                private_ipv4: addresses(&interface.ipv4, false),
+                ipv6: addresses(&interface.ipv6, false),
+                public_ipv4: addresses(&interface.ipv4, true),
+                subnet_id: interface
+                    .ipv4
+                    .subnet
+                    .first()
+                    .map(|s| format!("{}/{}", s.address, s.prefix)),
+                vpc_id: None,
+            }
+        })
+        .collect())
+}
+
+/// Format an image reference as its resource id, or as a
+/// `publisher:offer:sku:version` URN for marketplace images.
+fn image_id(reference: &serde_json::Value) -> Option<String> {
+    let field = |name: &str| reference.get(name).and_then(|v| v.as_str()).unwrap_or("");
+    if let Some(id) = non_empty(field("id")) {
+        return Some(id);
+    }
+    let parts = [
+        field("publisher"),
+        field("offer"),
+        field("sku"),
+        field("version"),
+    ];
+    if parts.iter().all(|p| p.is_empty()) {
+        return None;
+    }
+    Some(parts.join(":"))
+}
+
+/// Fetch an arbitrary IMDS path, e.g. `/metadata/instance/compute/vmId?format=text`.
+///
+/// Unless the path already carries an `api-version`, the one for the endpoint
+/// the path belongs to is appended: the fixed versions for scheduled events and
+/// managed identity, and the negotiated one for everything else.
+pub async fn fetch_path(
+    client: &MetadataClient,
+    versions: &ApiVersions,
+    path: &str,
+    max_size: Option<usize>,
+) -> Result<Vec<u8>, MetadataError> {
+    check_absolute_path(path)?;
+    let mut url = format!("{}{}", client.base_url(), path);
+    if !path.contains("api-version=") {
+        let api_version = path_api_version(client, versions, path).await;
+        let separator = if path.contains('?') { '&' } else { '?' };
+        url = format!("{}{}api-version={}", url, separator, api_version);
+    }
+
+    let response = client
+        .inner()
+        .get(&url)
+        .header(METADATA_HEADER, METADATA_VALUE)
+        .send()
+        .await?;
+
+    let status = response.status();
+    if status.as_u16() == 404 {
+        return Err(MetadataError::NotFound);
+    }
+    if !status.is_success() {
+        return Err(MetadataError::Http(status.as_u16()));
+    }
+
+    read_body_limited(response, max_size).await
+}
+
+/// Pick the `api-version` for a raw IMDS path from the endpoint it belongs to.
+async fn path_api_version(client: &MetadataClient, versions: &ApiVersions, path: &str) -> String {
+    let route = path.split('?').next().unwrap_or(path);
+    if route.starts_with(SCHEDULED_EVENTS_PATH) {
+        return SCHEDULED_EVENTS_API_VERSION.to_string();
+    }
+    if route.starts_with(IDENTITY_PREFIX) {
+        return IDENTITY_API_VERSION.to_string();
+    }
+
+    let endpoint = if route.starts_with(ATTESTED_PREFIX) {
+        Endpoint::Attested
+    } else if route.starts_with(USER_DATA_PATH) {
+        Endpoint::UserData
+    } else if route.starts_with(CUSTOM_DATA_PATH) {
+        Endpoint::CustomData
+    } else if route.starts_with(TAGS_LIST_PATH) {
+        Endpoint::TagsList
+    } else if route.starts_with(NETWORK_PATH) {
+        Endpoint::Network
+    } else {
+        Endpoint::Compute
+    };
+    versions.resolve(client, endpoint).await
+}
+
+/// Scheduled events document.
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct ScheduledEvents {
+    #[serde(default)]
+    events: Vec<serde_json::Value>,
+}
+
+/// A scheduled event.
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct ScheduledEvent {
+    event_id: String,
+    event_type: String,
+    #[serde(default)]
+    not_before: String,
+}
+
+/// Fetch the pending scheduled events.
+///
+/// The first request after boot enables scheduled events for the VM and
+/// can take up to two minutes to return, so the client timeout is replaced
+/// by a longer one.
+pub async fn fetch_scheduled_events(
+    client: &MetadataClient,
+) -> Result<Vec<Result<LifecycleEvent, MetadataError>>, MetadataError> {
+    let url = format!("{}{}", client.base_url(), SCHEDULED_EVENTS_PATH);
 
     let response = client
         .inner()
         .get(&url)
-        .query(&[("api-version", API_VERSION), ("format", "text")])
+        .query(&[("api-version", SCHEDULED_EVENTS_API_VERSION)])
+        .header(METADATA_HEADER, METADATA_VALUE)
+        .timeout(SCHEDULED_EVENTS_TIMEOUT)
+        .send()
+        .await?;
+
+    let status = response.status();
+    if status.as_u16() == 404 {
+        return Err(MetadataError::NotFound);
+    }
+    if !status.is_success() {
+        return Err(MetadataError::Http(status.as_u16()));
+    }
+
+    let body = read_body_limited(response, Some(MAX_EVENT_SIZE)).await?;
+    scheduled_events(&body)
+}
+
+/// Parse a scheduled events document. Unknown event types are skipped, and
+/// events that fail to parse are reported as errors without dropping the
+/// others.
+fn scheduled_events(
+    body: &[u8],
+) -> Result<Vec<Result<LifecycleEvent, MetadataError>>, MetadataError> {
+    let document: ScheduledEvents = serde_json::from_slice(body)?;
+
+    let mut events = Vec::new();
+    for value in document.events {
+        let event = match ScheduledEvent::deserialize(&value) {
+            Ok(event) => event,
+            Err(e) => {
+                events.push(Err(e.into()));
+                continue;
+            }
+        };
+        let kind = match event.event_type.as_str() {
+            "Freeze" => LifecycleEventKind::Maintenance,
+            "Reboot" => LifecycleEventKind::Reboot,
+            "Redeploy" => LifecycleEventKind::Redeploy,
+            "Preempt" => LifecycleEventKind::Preemption,
+            "Terminate" => LifecycleEventKind::Termination,
+            _ => continue,
+        };
+        events.push(Ok(LifecycleEvent {
+            kind,
+            id: Some(event.event_id),
+            // Empty once the event has started
+            deadline: parse_http_date(&event.not_before),
+            raw: value.to_string(),
+        }));
+    }
+    Ok(events)
+}
+
+/// Acknowledge a scheduled event, allowing it to start right away.
+pub async fn ack_scheduled_event(
+    client: &MetadataClient,
+    event_id: &str,
+) -> Result<(), MetadataError> {
+    let url = format!("{}{}", client.base_url(), SCHEDULED_EVENTS_PATH);
+    let body = serde_json::json!({ "StartRequests": [{ "EventId": event_id }] });
+
+    let response = client
+        .inner()
+        .post(&url)
+        .query(&[("api-version", SCHEDULED_EVENTS_API_VERSION)])
+        .header(METADATA_HEADER, METADATA_VALUE)
+        .header(reqwest::header::CONTENT_TYPE, "application/json")
+        .body(body.to_string())
+        .send()
+        .await?;
+
+    let status = response.status();
+    if !status.is_success() {
+        return Err(MetadataError::Http(status.as_u16()));
+    }
+    Ok(())
+}
+
+/// Fetch a JSON document from an IMDS endpoint.
+async fn fetch_json(
+    client: &MetadataClient,
+    versions: &ApiVersions,
+    endpoint: Endpoint,
+    path: &str,
+    max_size: Option<usize>,
+) -> Result<serde_json::Value, MetadataError> {
+    let api_version = versions.resolve(client, endpoint).await;
+    let url = format!("{}{}", client.base_url(), path);
+
+    let response = client
+        .inner()
+        .get(&url)
+        .query(&[("api-version", api_version.as_str()), ("format", "json")])
+        .header(METADATA_HEADER, METADATA_VALUE)
+        .send()
+        .await?;
+
+    let status = response.status();
+    if status.as_u16() == 404 {
+        return Err(MetadataError::NotFound);
+    }
+    if !status.is_success() {
+        return Err(MetadataError::Http(status.as_u16()));
+    }
+
+    let body = read_body_limited(response, max_size).await?;
+    serde_json::from_slice(&body).map_err(MetadataError::from)
+}
+
+/// Fetch a base64-encoded text field and decode it.
+/// An empty field is reported as `MetadataError::NotFound`.
+async fn fetch_base64(
+    client: &MetadataClient,
+    path: &str,
+    api_version: &str,
+    max_size: Option<usize>,
+) -> Result<Vec<u8>, MetadataError> {
+    let url = format!("{}{}", client.base_url(), path);
+
+    let response = client
+        .inner()
+        .get(&url)
+        .query(&[("api-version", api_version), ("format", "text")])
         .header(METADATA_HEADER, METADATA_VALUE)
         .send()
         .await?;
@@ -82,10 +691,110 @@ pub async fn fetch_custom_data(
         return Err(MetadataError::NotFound);
     }
 
+    decode_base64(&b64_bytes, max_size)
+}
+
+/// Fetch customData from the OVF provisioning file.
+///
+/// `path` may point to `ovf-env.xml`, a directory containing it, or the
+/// provisioning ISO image or device. Without a path, the default locations
+/// are tried in order.
+pub async fn fetch_ovf_custom_data(
+    path: Option<&Path>,
+    max_size: Option<usize>,
+) -> Result<Vec<u8>, MetadataError> {
+    match path {
+        Some(path) => read_ovf_custom_data(path, max_size).await,
+        None => {
+            let paths: Vec<&Path> = DEFAULT_OVF_PATHS.iter().map(Path::new).collect();
+            read_first_ovf_custom_data(&paths, max_size).await
+        }
+    }
+}
+
+/// Read customData from the first of `paths` that has it.
+///
+/// The default locations are often unreadable: `ovf-env.xml` is only
+/// readable by root and the drive may hold no disc. Such I/O errors are
+/// treated as `MetadataError::NotFound`, so callers without access see "no
+/// customData" rather than an error.
+async fn read_first_ovf_custom_data(
+    paths: &[&Path],
+    max_size: Option<usize>,
+) -> Result<Vec<u8>, MetadataError> {
+    for path in paths {
+        match read_ovf_custom_data(path, max_size).await {
+            Err(MetadataError::NotFound | MetadataError::Io(_)) => {}
+            result => return result,
+        }
+    }
+    Err(MetadataError::NotFound)
+}
+
+/// Read and decode customData from a single OVF location.
+async fn read_ovf_custom_data(
+    path: &Path,
+    max_size: Option<usize>,
+) -> Result<Vec<u8>, MetadataError> {
+    // Allow for base64 expansion and the surrounding XML
+    let max_file_size = max_size.map(|max| max.saturating_mul(4) / 3 + 64 * 1024);
+
+    let is_dir = tokio::fs::metadata(path)
+        .await
+        .map_err(map_io_error)?
+        .is_dir();
+    let xml = if is_dir {
+        read_file_limited(&path.join(OVF_FILE_NAME), max_file_size).await?
+    } else if path.extension().is_some_and(|ext| ext == "xml") {
+        read_file_limited(path, max_file_size).await?
+    } else {
+        iso9660::read_file(path, OVF_FILE_NAME, max_file_size).await?
+    };
+
+    let xml = String::from_utf8(xml).map_err(|_| MetadataError::Utf8)?;
+    let b64 = extract_element(&xml, CUSTOM_DATA_ELEMENT).ok_or(MetadataError::NotFound)?;
+    let b64: String = b64.split_whitespace().collect();
+    if b64.is_empty() {
+        return Err(MetadataError::NotFound);
+    }
+
+    decode_base64(b64.as_bytes(), max_size)
+}
+
+/// Extract the text content of the first element with the given local name.
+///
+/// The OVF file uses namespace prefixes (e.g. `<ns1:CustomData>`), so the
+/// prefix is ignored when matching.
+fn extract_element<'a>(xml: &'a str, local_name: &str) -> Option<&'a str> {
+    let mut rest = xml;
+    while let Some(start) = rest.find('<') {
+        rest = &rest[start + 1..];
+        let tag_end = rest.find('>')?;
+        let tag = &rest[..tag_end];
+        let name = tag
+            .trim_end_matches('/')
+            .split_whitespace()
+            .next()
+            .unwrap_or("");
+        let local = name.rsplit(':').next().unwrap_or(name);
+        if local == local_name && !tag.starts_with('/') {
+            if tag.ends_with('/') {
+                return Some("");
+            }
+            let content = &rest[tag_end + 1..];
+            let close = format!("</{}>", name);
+            return content.find(&close).map(|end| &content[..end]);
+        }
+    }
+    None
+}
+
+/// Decode base64 data and enforce the size limit on the decoded length.
+fn decode_base64(b64_bytes: &[u8], max_size: Option<usize>) -> Result<Vec<u8>, MetadataError> {
     // Convert to string for base64 decoding
-    let b64 = String::from_utf8(b64_bytes).map_err(|_| MetadataError::Utf8)?;
+    let b64 = std::str::from_utf8(b64_bytes).map_err(|_| MetadataError::Utf8)?;
 
-    let decoded = STANDARD.decode(&b64).map_err(|_| MetadataError::Base64)?;
+    let decoded = STANDARD.decode(b64).map_err(|_| MetadataError::Base64)?;
 
     // Check size limit after decoding (the actual constraint)
     if let Some(max) = max_size {
@@ -97,6 +806,216 @@ pub async fn fetch_custom_data(
     Ok(decoded)
 }
 
+/// A PKCS7-signed attested data document.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct AzureAttestedDocument {
+    /// The signature encoding, `pkcs7`.
+    pub encoding: String,
+    /// The base64-encoded PKCS7 SignedData, embedding the attested data.
+    pub signature: String,
+}
+
+/// Fetch the attested data document for `nonce`.
+pub async fn fetch_attested_document(
+    client: &MetadataClient,
+    versions: &ApiVersions,
+    nonce: &str,
+    max_size: Option<usize>,
+) -> Result<AzureAttestedDocument, MetadataError> {
+    let api_version = versions.resolve(client, Endpoint::Attested).await;
+    let url = format!("{}{}", client.base_url(), ATTESTED_PATH);
+
+    let response = client
+        .inner()
+        .get(&url)
+        .query(&[("api-version", api_version.as_str()), ("nonce", nonce)])
+        .header(METADATA_HEADER, METADATA_VALUE)
+        .send()
+        .await?;
+
+    let status = response.status();
+    if status.as_u16() == 404 {
+        return Err(MetadataError::NotFound);
+    }
+    if !status.is_success() {
+        return Err(MetadataError::Http(status.as_u16()));
+    }
+
+    let body = read_body_limited(response, max_size).await?;
+    serde_json::from_slice(&body).map_err(MetadataError::from)
+}
+
+/// Managed identity to request tokens for.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub enum AzureIdentity {
+    /// The system-assigned identity.
+    #[default]
+    SystemAssigned,
+    /// A user-assigned identity by client id.
+    ClientId(String),
+    /// A user-assigned identity by object id.
+    ObjectId(String),
+    /// A user-assigned identity by Azure resource id.
+    ResourceId(String),
+}
+
+impl AzureIdentity {
+    /// Query parameter selecting this identity.
+    fn query(&self) -> Option<(&'static str, &str)> {
+        match self {
+            AzureIdentity::SystemAssigned => None,
+            AzureIdentity::ClientId(id) => Some(("client_id", id)),
+            AzureIdentity::ObjectId(id) => Some(("object_id", id)),
+            AzureIdentity::ResourceId(id) => Some(("msi_res_id", id)),
+        }
+    }
+}
+
+/// A managed identity access token.
+#[derive(Clone, PartialEq, Eq)]
+pub struct AzureToken {
+    /// The access token.
+    pub token: String,
+    /// The resource the token is valid for.
+    pub resource: String,
+    /// When the token expires.
+    pub expires_at: SystemTime,
+}
+
+impl fmt::Debug for AzureToken {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AzureToken")
+            .field("token", &"<redacted>")
+            .field("resource", &self.resource)
+            .field("expires_at", &self.expires_at)
+            .finish()
+    }
+}
+
+impl Expiring for AzureToken {
+    fn expires_at(&self) -> SystemTime {
+        self.expires_at
+    }
+}
+
+/// Managed identity token response.
+#[derive(Deserialize)]
+struct TokenResponse {
+    access_token: String,
+    #[serde(default)]
+    resource: Option<String>,
+    expires_on: serde_json::Value,
+}
+
+/// Managed identity error response.
+#[derive(Deserialize)]
+struct TokenError {
+    #[serde(default)]
+    error: String,
+    #[serde(default)]
+    error_description: String,
+}
+
+/// Fetch a managed identity token for `resource`.
+pub async fn fetch_token(
+    client: &MetadataClient,
+    identity: &AzureIdentity,
+    resource: &str,
+) -> Result<AzureToken, MetadataError> {
+    let url = format!("{}{}", client.base_url(), IDENTITY_TOKEN_PATH);
+    let mut query = vec![
+        ("api-version", IDENTITY_API_VERSION),
+        ("resource", resource),
+    ];
+    query.extend(identity.query());
+
+    let response = client
+        .inner()
+        .get(&url)
+        .query(&query)
+        .header(METADATA_HEADER, METADATA_VALUE)
+        .send()
+        .await?;
+
+    let status = response.status();
+    let body = read_body_limited(response, Some(MAX_TOKEN_SIZE)).await?;
+    if !status.is_success() {
+        // Unknown identities are reported as 400 with an OAuth error body
+        return match serde_json::from_slice::<TokenError>(&body) {
+            Ok(e) if !e.error_description.is_empty() => {
+                Err(MetadataError::Credentials(e.error_description))
+            }
+            Ok(e) if !e.error.is_empty() => Err(MetadataError::Credentials(e.error)),
+            _ if status.as_u16() == 404 => Err(MetadataError::NotFound),
+            _ => Err(MetadataError::Http(status.as_u16())),
+        };
+    }
+
+    let response: TokenResponse = serde_json::from_slice(&body)?;
+    // `expires_on` is epoch seconds, sent as a string by IMDS
+    let expires_on = match &response.expires_on {
+        serde_json::Value::String(s) => s.parse().ok(),
+        value => value.as_u64(),
+    }
+    .ok_or_else(|| MetadataError::Credentials("invalid expires_on".to_string()))?;
+
+    Ok(AzureToken {
+        token: response.access_token,
+        resource: response.resource.unwrap_or_else(|| resource.to_string()),
+        expires_at: SystemTime::UNIX_EPOCH + Duration::from_secs(expires_on),
+    })
+}
+
+/// Caching provider for managed identity tokens.
+///
+/// Tokens are cached per resource until they are within the refresh margin
+/// of their expiry. Clones share the cache.
+#[derive(Debug, Clone)]
+pub struct AzureTokenProvider {
+    client: MetadataClient,
+    identity: AzureIdentity,
+    cache: CredentialCache<String, AzureToken>,
+}
+
+impl AzureTokenProvider {
+    pub(crate) fn new(client: MetadataClient) -> Self {
+        Self {
+            client,
+            identity: AzureIdentity::SystemAssigned,
+            cache: CredentialCache::new(DEFAULT_REFRESH_MARGIN),
+        }
+    }
+
+    /// Request tokens for a user-assigned identity.
+    pub fn with_identity(mut self, identity: AzureIdentity) -> Self {
+        self.identity = identity;
+        self
+    }
+
+    /// Set how long before expiration tokens are refreshed.
+    ///
+    /// Defaults to five minutes.
+    pub fn with_refresh_margin(mut self, margin: Duration) -> Self {
+        self.cache = self.cache.with_refresh_margin(margin);
+        self
+    }
+
+    /// Return a valid token for `resource`, e.g. `https://management.azure.com/`.
+    ///
+    /// # Errors
+    ///
+    /// Returns `MetadataError::Credentials` if IMDS rejects the request (for
+    /// example when the identity does not exist), or an error if the request
+    /// fails.
+    pub async fn token(&self, resource: &str) -> Result<AzureToken, MetadataError> {
+        self.cache
+            .get(resource.to_string(), |_| {
+                fetch_token(&self.client, &self.identity, resource)
+            })
+            .await
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -104,9 +1023,94 @@ mod tests {
     #[test]
     fn test_paths() {
         assert!(CUSTOM_DATA_PATH.starts_with(METADATA_PATH));
+        assert!(USER_DATA_PATH.starts_with(METADATA_PATH));
+        assert!(CUSTOM_DATA_PATH.starts_with(COMPUTE_PATH));
+        assert!(TAGS_LIST_PATH.starts_with(COMPUTE_PATH));
+        assert!(NETWORK_PATH.starts_with(METADATA_PATH));
+        assert!(IDENTITY_TOKEN_PATH.starts_with(METADATA_PATH));
+        assert!(ATTESTED_PATH.starts_with(METADATA_PATH));
+        assert!(SCHEDULED_EVENTS_PATH.starts_with(METADATA_PATH));
         assert_eq!(API_VERSION, "2021-02-01");
     }
 
+    #[test]
+    fn test_scheduled_events() {
+        let body = br#"{
+            "DocumentIncarnation": 2,
+            "Events": [
+                {"EventId": "C7061BAC-AFDC-4513-B24B-AA5F13A16123", "EventStatus": "Scheduled",
+                 "EventType": "Freeze", "ResourceType": "VirtualMachine",
+                 "Resources": ["WestNO_0"], "NotBefore": "Mon, 11 Apr 2022 22:26:58 GMT",
+                 "Description": "Virtual machine is being paused", "EventSource": "Platform",
+                 "DurationInSeconds": 5},
+                {"EventId": "1C4F5D2B-8C13-4D57-B4A5-A14E5B3A2F6D", "EventStatus": "Started",
+                 "EventType": "Preempt", "NotBefore": ""},
+                {"EventId": "00000000-0000-0000-0000-000000000000", "EventType": "Unknown"},
+                {"EventType": "Reboot"}
+            ]
+        }"#;
+        let events = scheduled_events(body).unwrap();
+        assert_eq!(events.len(), 3);
+        assert!(matches!(events[2], Err(MetadataError::Json(_))));
+        let events: Vec<_> = events.into_iter().filter_map(Result::ok).collect();
+        assert_eq!(events[0].kind, LifecycleEventKind::Maintenance);
+        assert_eq!(
+            events[0].id.as_deref(),
+            Some("C7061BAC-AFDC-4513-B24B-AA5F13A16123")
+        );
+        assert_eq!(
+            events[0].deadline,
+            parse_http_date("Mon, 11 Apr 2022 22:26:58 GMT")
+        );
+        assert!(events[0].raw.contains("WestNO_0"));
+        assert_eq!(events[1].kind, LifecycleEventKind::Preemption);
+        assert_eq!(events[1].deadline, None);
+
+        let empty = scheduled_events(br#"{"DocumentIncarnation": 0, "Events": []}"#).unwrap();
+        assert!(empty.is_empty());
+    }
+
+    #[test]
+    fn test_select_version() {
+        let available: Vec<String> = ["2019-02-01", "2021-02-01", "2023-07-01", "2099-01-01"]
+            .iter()
+            .map(|v| v.to_string())
+            .collect();
+        assert_eq!(
+            select_version(&available, Endpoint::UserData),
+            Some("2023-07-01")
+        );
+        assert_eq!(
+            select_version(&available[..2], Endpoint::CustomData),
+            Some("2021-02-01")
+        );
+        assert_eq!(select_version(&available[..1], Endpoint::UserData), None);
+        assert_eq!(select_version(&[], Endpoint::CustomData), None);
+    }
+
+    #[test]
+    fn test_image_id() {
+        let marketplace = serde_json::json!({
+            "id": "",
+            "offer": "0001-com-ubuntu-server-jammy",
+            "publisher": "canonical",
+            "sku": "22_04-lts-gen2",
+            "version": "latest"
+        });
+        assert_eq!(
+            image_id(&marketplace).as_deref(),
+            Some("canonical:0001-com-ubuntu-server-jammy:22_04-lts-gen2:latest")
+        );
+
+        let custom = serde_json::json!({"id": "/subscriptions/x/images/y", "offer": ""});
+        assert_eq!(
+            image_id(&custom).as_deref(),
+            Some("/subscriptions/x/images/y")
+        );
+
+        assert_eq!(image_id(&serde_json::json!({})), None);
+    }
+
     #[test]
     fn test_base64_decode() {
         let encoded = STANDARD.encode(b"hello world");
@@ -114,6 +1118,79 @@ mod tests {
         assert_eq!(decoded, b"hello world");
     }
 
+    const OVF: &str = r#"<?xml version="1.0" encoding="utf-8"?>
+<ns0:Environment xmlns:ns0="http://schemas.dmtf.org/ovf/environment/1" xmlns:ns1="http://schemas.microsoft.com/windowsazure">
+  <ns1:ProvisioningSection>
+    <ns1:LinuxProvisioningConfigurationSet>
+      <ns1:HostName>node-1</ns1:HostName>
+      <ns1:CustomData>aGVsbG8g
+d29ybGQ=</ns1:CustomData>
+    </ns1:LinuxProvisioningConfigurationSet>
+  </ns1:ProvisioningSection>
+</ns0:Environment>"#;
+
+    #[test]
+    fn test_extract_element() {
+        assert_eq!(extract_element(OVF, "HostName"), Some("node-1"));
+        assert_eq!(extract_element(OVF, "Missing"), None);
+        assert_eq!(extract_element("<CustomData/>", "CustomData"), Some(""));
+    }
+
+    #[tokio::test]
+    async fn test_ovf_custom_data_from_file() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join(OVF_FILE_NAME), OVF).unwrap();
+
+        let data = fetch_ovf_custom_data(Some(dir.path()), None).await.unwrap();
+        assert_eq!(data, b"hello world");
+
+        let file = dir.path().join(OVF_FILE_NAME);
+        let result = fetch_ovf_custom_data(Some(&file), Some(4)).await;
+        assert!(matches!(result, Err(MetadataError::TooLarge(11, 4))));
+    }
+
+    #[tokio::test]
+    async fn test_ovf_custom_data_from_iso() {
+        let image = iso9660::tests::build_image(&[(OVF_FILE_NAME, OVF.as_bytes())], true);
+        let file = tempfile::NamedTempFile::new().unwrap();
+        std::fs::write(file.path(), image).unwrap();
+
+        let data = fetch_ovf_custom_data(Some(file.path()), None)
+            .await
+            .unwrap();
+        assert_eq!(data, b"hello world");
+    }
+
+    #[tokio::test]
+    async fn test_ovf_without_custom_data() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join(OVF_FILE_NAME), "<Environment/>").unwrap();
+
+        let result = fetch_ovf_custom_data(Some(dir.path()), None).await;
+        assert!(matches!(result, Err(MetadataError::NotFound)));
+    }
+
+    #[tokio::test]
+    async fn test_ovf_unreadable_default_paths() {
+        let dir = tempfile::tempdir().unwrap();
+        // Neither an OVF file nor an ISO image, as on an empty drive
+        let drive = dir.path().join("sr0");
+        std::fs::write(&drive, "not an image").unwrap();
+
+        let result = fetch_ovf_custom_data(Some(&drive), None).await;
+        assert!(matches!(result, Err(MetadataError::Io(_))));
+
+        let result = read_first_ovf_custom_data(&[&drive], None).await;
+        assert!(matches!(result, Err(MetadataError::NotFound)));
+
+        let ovf = dir.path().join(OVF_FILE_NAME);
+        std::fs::write(&ovf, OVF).unwrap();
+        let data = read_first_ovf_custom_data(&[&drive, &ovf], None)
+            .await
+            .unwrap();
+        assert_eq!(data, b"hello world");
+    }
+
     #[test]
     fn test_base64_decode_json() {
         let json = r#"{"key": "value"}"#;
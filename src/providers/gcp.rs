@@ -1,17 +1,63 @@
 //! GCP metadata implementation.
 
-use crate::client::{read_body_limited, MetadataClient};
-use crate::error::MetadataError;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::time::{Duration, SystemTime};
+
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use serde::Deserialize;
+
+use crate::client::{check_absolute_path, encode_path_segment, read_body_limited, MetadataClient};
+use crate::credentials::{CredentialCache, Expiring};
+use crate::error::{optional, MetadataError};
+use crate::identity::{gcp_region_from_zone, last_segment, non_empty, InstanceIdentity};
+use crate::lifecycle::{LifecycleEvent, LifecycleEventKind};
+use crate::network::{normalize_mac, NetworkInterface};
 
 /// GCP metadata service base path.
 const METADATA_PATH: &str = "/computeMetadata/v1";
 
+/// Instance metadata path.
+const INSTANCE_PATH: &str = "/computeMetadata/v1/instance";
+
+/// Project metadata path.
+const PROJECT_PATH: &str = "/computeMetadata/v1/project";
+
 /// Instance attributes path.
 const INSTANCE_ATTRIBUTES_PATH: &str = "/computeMetadata/v1/instance/attributes";
 
 /// Project attributes path.
 const PROJECT_ATTRIBUTES_PATH: &str = "/computeMetadata/v1/project/attributes";
 
+/// Network interfaces path.
+const NETWORK_INTERFACES_PATH: &str = "/computeMetadata/v1/instance/network-interfaces";
+
+/// Service accounts path.
+const SERVICE_ACCOUNTS_PATH: &str = "/computeMetadata/v1/instance/service-accounts";
+
+/// Guest attributes path.
+const GUEST_ATTRIBUTES_PATH: &str = "/computeMetadata/v1/instance/guest-attributes";
+
+/// Preemption notice path.
+const PREEMPTED_PATH: &str = "/computeMetadata/v1/instance/preempted";
+
+/// Host maintenance event path.
+const MAINTENANCE_EVENT_PATH: &str = "/computeMetadata/v1/instance/maintenance-event";
+
+/// Paths that announce lifecycle events, see [`lifecycle_event`].
+pub const LIFECYCLE_PATHS: &[&str] = &[PREEMPTED_PATH, MAINTENANCE_EVENT_PATH];
+
+/// Service account used when none is configured.
+pub const DEFAULT_SERVICE_ACCOUNT: &str = "default";
+
+/// Refresh tokens this long before they expire.
+const DEFAULT_REFRESH_MARGIN: Duration = Duration::from_secs(60);
+
+/// How long a change notification request waits before returning the
+/// unchanged value.
+const WAIT_TIMEOUT: Duration = Duration::from_secs(60);
+
 /// Required header for GCP metadata requests.
 const METADATA_FLAVOR_HEADER: &str = "Metadata-Flavor";
 
@@ -42,11 +88,262 @@ pub async fn fetch_instance_attribute(
     key: &str,
     max_size: Option<usize>,
 ) -> Result<Vec<u8>, MetadataError> {
-    let url = format!("{}{}/{}", client.base_url(), INSTANCE_ATTRIBUTES_PATH, key);
+    get(
+        client,
+        &format!("{}/{}", INSTANCE_ATTRIBUTES_PATH, key),
+        max_size,
+    )
+    .await
+}
+
+/// Where GCP custom data attributes are looked up.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum GcpAttributeScope {
+    /// Instance attributes only.
+    #[default]
+    Instance,
+    /// Project attributes only.
+    Project,
+    /// The instance attribute, or the project attribute if the instance does
+    /// not set the key.
+    InstanceThenProject,
+}
+
+/// Fetch an attribute by key from the given scope.
+pub async fn fetch_attribute(
+    client: &MetadataClient,
+    scope: GcpAttributeScope,
+    key: &str,
+    max_size: Option<usize>,
+) -> Result<Vec<u8>, MetadataError> {
+    match scope {
+        GcpAttributeScope::Instance => fetch_instance_attribute(client, key, max_size).await,
+        GcpAttributeScope::Project => fetch_project_attribute(client, key, max_size).await,
+        GcpAttributeScope::InstanceThenProject => {
+            match fetch_instance_attribute(client, key, max_size).await {
+                Err(MetadataError::NotFound) => {
+                    fetch_project_attribute(client, key, max_size).await
+                }
+                result => result,
+            }
+        }
+    }
+}
+
+/// Path of an attribute in a single scope.
+///
+/// Returns `None` for `InstanceThenProject`, which spans two paths.
+pub fn attribute_path(scope: GcpAttributeScope, key: &str) -> Option<String> {
+    let attributes = match scope {
+        GcpAttributeScope::Instance => INSTANCE_ATTRIBUTES_PATH,
+        GcpAttributeScope::Project => PROJECT_ATTRIBUTES_PATH,
+        GcpAttributeScope::InstanceThenProject => return None,
+    };
+    Some(format!("{}/{}", attributes, key))
+}
+
+/// Fetch a metadata path together with its ETag, waiting for a change if
+/// `last_etag` is given.
+pub async fn wait_for_change(
+    client: &MetadataClient,
+    path: &str,
+    last_etag: Option<&str>,
+    max_size: Option<usize>,
+) -> Result<(Vec<u8>, String), MetadataError> {
+    let url = format!("{}{}", client.base_url(), path);
+
+    let mut request = client
+        .inner()
+        .get(&url)
+        .header(METADATA_FLAVOR_HEADER, METADATA_FLAVOR_VALUE);
+    if let Some(etag) = last_etag {
+        let timeout = WAIT_TIMEOUT.as_secs().to_string();
+        request = request
+            .query(&[
+                ("wait_for_change", "true"),
+                ("last_etag", etag),
+                ("timeout_sec", &timeout),
+            ])
+            .timeout(WAIT_TIMEOUT + client.timeout());
+    }
+    let response = request.send().await?;
+
+    let status = response.status();
+    if status.as_u16() == 404 {
+        return Err(MetadataError::NotFound);
+    }
+    if !status.is_success() {
+        return Err(MetadataError::Http(status.as_u16()));
+    }
+
+    let etag = response
+        .headers()
+        .get(reqwest::header::ETAG)
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or_default()
+        .to_string();
+    Ok((read_body_limited(response, max_size).await?, etag))
+}
+
+/// Interpret the value of one of the [`LIFECYCLE_PATHS`].
+///
+/// Returns `None` while no event is pending. GCP does not report deadlines;
+/// preemption follows about 30 seconds after the notice.
+pub fn lifecycle_event(path: &str, value: &[u8]) -> Option<LifecycleEvent> {
+    let raw = String::from_utf8_lossy(value).trim().to_string();
+    let kind = match (path, raw.as_str()) {
+        (PREEMPTED_PATH, "TRUE") => LifecycleEventKind::Preemption,
+        (MAINTENANCE_EVENT_PATH, "MIGRATE_ON_HOST_MAINTENANCE") => LifecycleEventKind::Maintenance,
+        (MAINTENANCE_EVENT_PATH, "TERMINATE_ON_HOST_MAINTENANCE") => {
+            LifecycleEventKind::Termination
+        }
+        _ => return None,
+    };
+    Some(LifecycleEvent {
+        kind,
+        id: None,
+        deadline: None,
+        raw,
+    })
+}
+
+/// Fetch instance attributes whose names start with `prefix` as tags.
+///
+/// The prefix is stripped from the returned keys.
+pub async fn fetch_tags(
+    client: &MetadataClient,
+    prefix: &str,
+    max_size: Option<usize>,
+) -> Result<BTreeMap<String, String>, MetadataError> {
+    let path = format!("{}/", INSTANCE_ATTRIBUTES_PATH);
+    let body = get_with_query(client, &path, &[("recursive", "true")], max_size).await?;
+    let attributes: BTreeMap<String, String> = serde_json::from_slice(&body)?;
+
+    Ok(attributes
+        .into_iter()
+        .filter_map(|(key, value)| {
+            key.strip_prefix(prefix)
+                .filter(|k| !k.is_empty())
+                .map(|k| (k.to_string(), value))
+        })
+        .collect())
+}
+
+/// A network interface from `network-interfaces/?recursive=true`.
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Interface {
+    mac: String,
+    #[serde(default)]
+    ip: Option<String>,
+    #[serde(default)]
+    ipv6s: Vec<String>,
+    #[serde(default)]
+    access_configs: Vec<AccessConfig>,
+    #[serde(default)]
+    network: Option<String>,
+}
+
+/// An external access configuration of a network interface.
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct AccessConfig {
+    #[serde(default)]
+    external_ip: Option<String>,
+}
+
+/// Fetch network interfaces. The first interface is the primary one.
+pub async fn fetch_network(
+    client: &MetadataClient,
+    max_size: Option<usize>,
+) -> Result<Vec<NetworkInterface>, MetadataError> {
+    let path = format!("{}/", NETWORK_INTERFACES_PATH);
+    let body = get_with_query(client, &path, &[("recursive", "true")], max_size).await?;
+    let interfaces: Vec<Interface> = serde_json::from_slice(&body)?;
+
+    Ok(interfaces
+        .into_iter()
+        .enumerate()
+        .map(|(index, interface)| NetworkInterface {
+            mac: normalize_mac(&interface.mac),
+            primary: index == 0,
+            private_ipv4: interface
+                .ip
+                .into_iter()
+                .filter(|ip| !ip.is_empty())
+                .collect(),
+            ipv6: interface.ipv6s,
+            public_ipv4: interface
+                .access_configs
+                .into_iter()
+                .filter_map(|c| c.external_ip)
+                .filter(|ip| !ip.is_empty())
+                .collect(),
+            subnet_id: None,
+            vpc_id: interface.network.map(|n| last_segment(&n).to_string()),
+        })
+        .collect())
+}
+
+/// Fetch the instance identity from instance and project metadata.
+pub async fn fetch_identity(
+    client: &MetadataClient,
+    max_size: Option<usize>,
+) -> Result<InstanceIdentity, MetadataError> {
+    let text = |path: &'static str, base: &'static str| async move {
+        let value = optional(get(client, &format!("{}/{}", base, path), max_size).await)?;
+        Ok::<_, MetadataError>(value.and_then(|v| non_empty(&String::from_utf8_lossy(&v))))
+    };
+
+    let (id, zone, machine_type, hostname, image, project_id) = tokio::try_join!(
+        text("id", INSTANCE_PATH),
+        text("zone", INSTANCE_PATH),
+        text("machine-type", INSTANCE_PATH),
+        text("hostname", INSTANCE_PATH),
+        text("image", INSTANCE_PATH),
+        text("project-id", PROJECT_PATH),
+    )?;
+
+    // Zone and machine type are full resource names
+    let zone = zone.map(|z| last_segment(&z).to_string());
+    let region = zone
+        .as_deref()
+        .and_then(gcp_region_from_zone)
+        .map(str::to_string);
+
+    Ok(InstanceIdentity {
+        instance_id: id,
+        region,
+        zone,
+        machine_type: machine_type.map(|m| last_segment(&m).to_string()),
+        hostname,
+        account_id: project_id,
+        image_id: image,
+    })
+}
+
+/// Fetch a metadata path.
+async fn get(
+    client: &MetadataClient,
+    path: &str,
+    max_size: Option<usize>,
+) -> Result<Vec<u8>, MetadataError> {
+    get_with_query(client, path, &[], max_size).await
+}
+
+/// Fetch a metadata path with URL-encoded query parameters.
+async fn get_with_query(
+    client: &MetadataClient,
+    path: &str,
+    query: &[(&str, &str)],
+    max_size: Option<usize>,
+) -> Result<Vec<u8>, MetadataError> {
+    let url = format!("{}{}", client.base_url(), path);
 
     let response = client
         .inner()
         .get(&url)
+        .query(query)
         .header(METADATA_FLAVOR_HEADER, METADATA_FLAVOR_VALUE)
         .send()
         .await?;
@@ -62,17 +359,129 @@ pub async fn fetch_instance_attribute(
     read_body_limited(response, max_size).await
 }
 
-/// Fetch a project attribute by key.
-pub async fn fetch_project_attribute(
+/// Fetch an arbitrary metadata path, e.g. `/computeMetadata/v1/instance/id`.
+pub async fn fetch_path(
+    client: &MetadataClient,
+    path: &str,
+    max_size: Option<usize>,
+) -> Result<Vec<u8>, MetadataError> {
+    check_absolute_path(path)?;
+    get(client, path, max_size).await
+}
+
+/// Fetch a metadata directory with all entries below it as one JSON document.
+///
+/// `path` is relative to `/computeMetadata/v1/`, e.g. `instance` or
+/// `project/attributes`. An empty path fetches the whole metadata tree.
+pub async fn fetch_tree(
+    client: &MetadataClient,
+    path: &str,
+    max_size: Option<usize>,
+) -> Result<serde_json::Value, MetadataError> {
+    let path = match path.trim_matches('/') {
+        "" => format!("{}/", METADATA_PATH),
+        path => format!("{}/{}/", METADATA_PATH, path),
+    };
+    let query = [("recursive", "true"), ("alt", "json")];
+    let body = get_with_query(client, &path, &query, max_size).await?;
+    serde_json::from_slice(&body).map_err(MetadataError::from)
+}
+
+/// List instance attribute names.
+pub async fn list_instance_attributes(
+    client: &MetadataClient,
+    max_size: Option<usize>,
+) -> Result<Vec<String>, MetadataError> {
+    list(client, &format!("{}/", INSTANCE_ATTRIBUTES_PATH), max_size).await
+}
+
+/// List project attribute names.
+pub async fn list_project_attributes(
+    client: &MetadataClient,
+    max_size: Option<usize>,
+) -> Result<Vec<String>, MetadataError> {
+    list(client, &format!("{}/", PROJECT_ATTRIBUTES_PATH), max_size).await
+}
+
+/// Fetch a directory listing, one entry per line.
+async fn list(
+    client: &MetadataClient,
+    path: &str,
+    max_size: Option<usize>,
+) -> Result<Vec<String>, MetadataError> {
+    let body = get(client, path, max_size).await?;
+    let body = String::from_utf8(body).map_err(|_| MetadataError::Utf8)?;
+    Ok(body
+        .lines()
+        .filter(|l| !l.is_empty())
+        .map(str::to_string)
+        .collect())
+}
+
+/// Check that a guest attribute namespace or key only uses letters, digits,
+/// `-` and `_`, as the metadata server requires.
+fn check_guest_attribute_name(name: &str) -> Result<(), MetadataError> {
+    let valid = !name.is_empty()
+        && name
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || b == b'-' || b == b'_');
+    if valid {
+        Ok(())
+    } else {
+        Err(MetadataError::InvalidKey(name.to_string()))
+    }
+}
+
+/// Fetch a guest attribute.
+pub async fn fetch_guest_attribute(
     client: &MetadataClient,
+    namespace: &str,
     key: &str,
+    max_size: Option<usize>,
 ) -> Result<String, MetadataError> {
-    let url = format!("{}{}/{}", client.base_url(), PROJECT_ATTRIBUTES_PATH, key);
+    check_guest_attribute_name(namespace)?;
+    check_guest_attribute_name(key)?;
+    let path = format!("{}/{}/{}", GUEST_ATTRIBUTES_PATH, namespace, key);
+    let value = get(client, &path, max_size).await?;
+    String::from_utf8(value).map_err(|_| MetadataError::Utf8)
+}
+
+/// List the guest attribute keys in a namespace.
+pub async fn list_guest_attributes(
+    client: &MetadataClient,
+    namespace: &str,
+    max_size: Option<usize>,
+) -> Result<Vec<String>, MetadataError> {
+    check_guest_attribute_name(namespace)?;
+    let path = format!("{}/{}/", GUEST_ATTRIBUTES_PATH, namespace);
+    list(client, &path, max_size).await
+}
+
+/// Set a guest attribute.
+///
+/// Guest attributes must be enabled for the instance or project with the
+/// `enable-guest-attributes` metadata key.
+pub async fn set_guest_attribute(
+    client: &MetadataClient,
+    namespace: &str,
+    key: &str,
+    value: &str,
+) -> Result<(), MetadataError> {
+    check_guest_attribute_name(namespace)?;
+    check_guest_attribute_name(key)?;
+    let url = format!(
+        "{}{}/{}/{}",
+        client.base_url(),
+        GUEST_ATTRIBUTES_PATH,
+        namespace,
+        key
+    );
 
     let response = client
         .inner()
-        .get(&url)
+        .put(&url)
         .header(METADATA_FLAVOR_HEADER, METADATA_FLAVOR_VALUE)
+        .body(value.to_string())
         .send()
         .await?;
 
@@ -83,8 +492,273 @@ pub async fn fetch_project_attribute(
     if !status.is_success() {
         return Err(MetadataError::Http(status.as_u16()));
     }
+    Ok(())
+}
+
+/// Read and write access to the guest attributes of the instance.
+///
+/// Guest attributes let the instance report values, such as its bootstrap
+/// status, that are readable through the Compute Engine API. This is the
+/// only way the crate writes to a metadata service.
+#[derive(Debug, Clone)]
+pub struct GcpGuestAttributes {
+    client: MetadataClient,
+    max_size: Option<usize>,
+}
+
+impl GcpGuestAttributes {
+    pub(crate) fn new(client: MetadataClient, max_size: Option<usize>) -> Self {
+        Self { client, max_size }
+    }
+
+    /// Read a guest attribute.
+    ///
+    /// Namespaces and keys may only contain letters, digits, `-` and `_`.
+    ///
+    /// # Errors
+    ///
+    /// Returns `MetadataError::NotFound` if the attribute is not set,
+    /// `MetadataError::InvalidKey` for invalid names, or
+    /// `MetadataError::TooLarge` if the value exceeds the maximum size.
+    pub async fn get(&self, namespace: &str, key: &str) -> Result<String, MetadataError> {
+        fetch_guest_attribute(&self.client, namespace, key, self.max_size).await
+    }
+
+    /// List the keys set in `namespace`.
+    ///
+    /// # Errors
+    ///
+    /// Returns `MetadataError::NotFound` if the namespace has no attributes,
+    /// or `MetadataError::InvalidKey` for an invalid namespace.
+    pub async fn list(&self, namespace: &str) -> Result<Vec<String>, MetadataError> {
+        list_guest_attributes(&self.client, namespace, self.max_size).await
+    }
+
+    /// Set a guest attribute, replacing any previous value.
+    ///
+    /// # Errors
+    ///
+    /// Returns `MetadataError::InvalidKey` for invalid names, or an error if
+    /// the request fails, for example with `MetadataError::Http(403)` if
+    /// guest attributes are not enabled.
+    pub async fn set(&self, namespace: &str, key: &str, value: &str) -> Result<(), MetadataError> {
+        set_guest_attribute(&self.client, namespace, key, value).await
+    }
+}
+
+/// Fetch a project attribute by key.
+pub async fn fetch_project_attribute(
+    client: &MetadataClient,
+    key: &str,
+    max_size: Option<usize>,
+) -> Result<Vec<u8>, MetadataError> {
+    get(
+        client,
+        &format!("{}/{}", PROJECT_ATTRIBUTES_PATH, key),
+        max_size,
+    )
+    .await
+}
+
+/// A service account attached to the instance.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct GcpServiceAccount {
+    /// The service account email.
+    pub email: String,
+    /// Aliases of the account, e.g. `default`.
+    #[serde(default)]
+    pub aliases: Vec<String>,
+    /// OAuth scopes granted to the instance for this account.
+    #[serde(default)]
+    pub scopes: Vec<String>,
+}
+
+/// Fetch the service accounts attached to the instance.
+///
+/// The metadata server lists each account under its email and its aliases;
+/// each account is returned once.
+pub async fn fetch_service_accounts(
+    client: &MetadataClient,
+) -> Result<Vec<GcpServiceAccount>, MetadataError> {
+    let path = format!("{}/", SERVICE_ACCOUNTS_PATH);
+    let body = get_with_query(client, &path, &[("recursive", "true")], None).await?;
+    let accounts: BTreeMap<String, GcpServiceAccount> = serde_json::from_slice(&body)?;
+
+    let mut unique: Vec<GcpServiceAccount> = Vec::new();
+    for account in accounts.into_values() {
+        if !unique.iter().any(|a| a.email == account.email) {
+            unique.push(account);
+        }
+    }
+    Ok(unique)
+}
+
+/// An OAuth access token or identity token.
+#[derive(Clone, PartialEq, Eq)]
+pub struct GcpToken {
+    /// The token value.
+    pub token: String,
+    /// When the token expires.
+    pub expires_at: SystemTime,
+}
+
+impl fmt::Debug for GcpToken {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("GcpToken")
+            .field("token", &"<redacted>")
+            .field("expires_at", &self.expires_at)
+            .finish()
+    }
+}
+
+impl Expiring for GcpToken {
+    fn expires_at(&self) -> SystemTime {
+        self.expires_at
+    }
+}
+
+/// Access token response.
+#[derive(Deserialize)]
+struct AccessTokenResponse {
+    access_token: String,
+    expires_in: u64,
+}
+
+/// Fetch an OAuth access token for `account`, optionally narrowed to `scopes`.
+pub async fn fetch_access_token(
+    client: &MetadataClient,
+    account: &str,
+    scopes: &[&str],
+) -> Result<GcpToken, MetadataError> {
+    let path = format!(
+        "{}/{}/token",
+        SERVICE_ACCOUNTS_PATH,
+        encode_path_segment(account)
+    );
+    let scopes = scopes.join(",");
+    let query: &[(&str, &str)] = if scopes.is_empty() {
+        &[]
+    } else {
+        &[("scopes", &scopes)]
+    };
+    let body = get_with_query(client, &path, query, None).await?;
+    let response: AccessTokenResponse = serde_json::from_slice(&body)?;
+
+    Ok(GcpToken {
+        token: response.access_token,
+        expires_at: SystemTime::now() + Duration::from_secs(response.expires_in),
+    })
+}
+
+/// Fetch a signed identity token (JWT) for `account` and `audience`.
+///
+/// The token is requested in `full` format, which includes the
+/// `google.compute_engine` instance claims.
+pub async fn fetch_identity_token(
+    client: &MetadataClient,
+    account: &str,
+    audience: &str,
+) -> Result<GcpToken, MetadataError> {
+    let path = format!(
+        "{}/{}/identity",
+        SERVICE_ACCOUNTS_PATH,
+        encode_path_segment(account)
+    );
+    let query = [("audience", audience), ("format", "full")];
+    let body = get_with_query(client, &path, &query, None).await?;
+    let token = String::from_utf8(body).map_err(|_| MetadataError::Utf8)?;
+    let token = token.trim().to_string();
+
+    let expires_at = jwt_expiry(&token).ok_or_else(|| {
+        MetadataError::Credentials("identity token has no valid exp claim".to_string())
+    })?;
+    Ok(GcpToken { token, expires_at })
+}
+
+/// Read the `exp` claim of a JWT without verifying it.
+fn jwt_expiry(token: &str) -> Option<SystemTime> {
+    #[derive(Deserialize)]
+    struct Claims {
+        exp: u64,
+    }
+
+    let payload = token.split('.').nth(1)?;
+    let payload = URL_SAFE_NO_PAD.decode(payload).ok()?;
+    let claims: Claims = serde_json::from_slice(&payload).ok()?;
+    Some(SystemTime::UNIX_EPOCH + Duration::from_secs(claims.exp))
+}
+
+/// Cache key for [`GcpTokenProvider`].
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum TokenKey {
+    Access(String),
+    Identity(String),
+}
 
-    response.text().await.map_err(MetadataError::from)
+/// Caching provider for service-account tokens.
+///
+/// Access tokens are cached per scope set and identity tokens per audience,
+/// until they are within the refresh margin of their expiry. Clones share the
+/// cache.
+#[derive(Debug, Clone)]
+pub struct GcpTokenProvider {
+    client: MetadataClient,
+    account: String,
+    cache: CredentialCache<TokenKey, GcpToken>,
+}
+
+impl GcpTokenProvider {
+    pub(crate) fn new(client: MetadataClient) -> Self {
+        Self {
+            client,
+            account: DEFAULT_SERVICE_ACCOUNT.to_string(),
+            cache: CredentialCache::new(DEFAULT_REFRESH_MARGIN),
+        }
+    }
+
+    /// Use `account` (an email or alias) instead of the default service account.
+    pub fn with_account(mut self, account: &str) -> Self {
+        self.account = account.to_string();
+        self
+    }
+
+    /// Set how long before expiration tokens are refreshed.
+    ///
+    /// Defaults to one minute.
+    pub fn with_refresh_margin(mut self, margin: Duration) -> Self {
+        self.cache = self.cache.with_refresh_margin(margin);
+        self
+    }
+
+    /// Return a valid OAuth access token, optionally narrowed to `scopes`.
+    ///
+    /// # Errors
+    ///
+    /// Returns `MetadataError::NotFound` if the service account does not
+    /// exist, or an error if the request fails.
+    pub async fn access_token(&self, scopes: &[&str]) -> Result<GcpToken, MetadataError> {
+        let key = TokenKey::Access(scopes.join(","));
+        self.cache
+            .get(key, |_| {
+                fetch_access_token(&self.client, &self.account, scopes)
+            })
+            .await
+    }
+
+    /// Return a valid identity token for `audience`.
+    ///
+    /// # Errors
+    ///
+    /// Returns `MetadataError::NotFound` if the service account does not
+    /// exist, or an error if the request fails.
+    pub async fn identity_token(&self, audience: &str) -> Result<GcpToken, MetadataError> {
+        let key = TokenKey::Identity(audience.to_string());
+        self.cache
+            .get(key, |_| {
+                fetch_identity_token(&self.client, &self.account, audience)
+            })
+            .await
+    }
 }
 
 #[cfg(test)]
@@ -93,8 +767,54 @@ mod tests {
 
     #[test]
     fn test_paths() {
-        assert!(INSTANCE_ATTRIBUTES_PATH.starts_with(METADATA_PATH));
-        assert!(PROJECT_ATTRIBUTES_PATH.starts_with(METADATA_PATH));
+        assert!(INSTANCE_ATTRIBUTES_PATH.starts_with(INSTANCE_PATH));
+        assert!(PROJECT_ATTRIBUTES_PATH.starts_with(PROJECT_PATH));
+        assert!(INSTANCE_PATH.starts_with(METADATA_PATH));
+        assert!(NETWORK_INTERFACES_PATH.starts_with(INSTANCE_PATH));
+        assert!(PROJECT_PATH.starts_with(METADATA_PATH));
+        assert!(SERVICE_ACCOUNTS_PATH.starts_with(INSTANCE_PATH));
+        assert!(GUEST_ATTRIBUTES_PATH.starts_with(INSTANCE_PATH));
+        assert!(PREEMPTED_PATH.starts_with(INSTANCE_PATH));
+        assert!(MAINTENANCE_EVENT_PATH.starts_with(INSTANCE_PATH));
+    }
+
+    #[test]
+    fn test_check_guest_attribute_name() {
+        assert!(check_guest_attribute_name("bootstrap").is_ok());
+        assert!(check_guest_attribute_name("Boot_strap-2").is_ok());
+        for name in ["", "a/b", "..", "a b", "a?b", "ä"] {
+            assert!(matches!(
+                check_guest_attribute_name(name),
+                Err(MetadataError::InvalidKey(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn test_lifecycle_event() {
+        let event = lifecycle_event(PREEMPTED_PATH, b"TRUE").unwrap();
+        assert_eq!(event.kind, LifecycleEventKind::Preemption);
+        assert!(lifecycle_event(PREEMPTED_PATH, b"FALSE").is_none());
+
+        let event =
+            lifecycle_event(MAINTENANCE_EVENT_PATH, b"MIGRATE_ON_HOST_MAINTENANCE").unwrap();
+        assert_eq!(event.kind, LifecycleEventKind::Maintenance);
+        assert_eq!(event.raw, "MIGRATE_ON_HOST_MAINTENANCE");
+        let event =
+            lifecycle_event(MAINTENANCE_EVENT_PATH, b"TERMINATE_ON_HOST_MAINTENANCE").unwrap();
+        assert_eq!(event.kind, LifecycleEventKind::Termination);
+        assert!(lifecycle_event(MAINTENANCE_EVENT_PATH, b"NONE").is_none());
+    }
+
+    #[test]
+    fn test_jwt_expiry() {
+        let payload = URL_SAFE_NO_PAD.encode(r#"{"aud":"x","exp":1700000000}"#);
+        let token = format!("eyJhbGciOiJSUzI1NiJ9.{}.sig", payload);
+        assert_eq!(
+            jwt_expiry(&token),
+            Some(SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000))
+        );
+        assert_eq!(jwt_expiry("not-a-jwt"), None);
     }
 
     #[test]
@@ -0,0 +1,208 @@
+//! cloud-init NoCloud seed implementation.
+//!
+//! NoCloud seeds are plain files (`user-data`, `meta-data`, `vendor-data`)
+//! in a local directory, typically `/var/lib/cloud/seed/nocloud` or the
+//! mount point of a `CIDATA`-labelled volume.
+
+use std::path::{Path, PathBuf};
+
+use crate::error::MetadataError;
+use crate::identity::InstanceIdentity;
+use crate::local::{map_io_error, read_file_limited, relative_path};
+
+/// Default seed directory used by cloud-init.
+pub const DEFAULT_SEED_PATH: &str = "/var/lib/cloud/seed/nocloud";
+
+/// Seed directories probed during detection, in order.
+const SEED_PATHS: &[&str] = &[DEFAULT_SEED_PATH, "/var/lib/cloud/seed/nocloud-net"];
+
+/// user-data file name.
+const USER_DATA_FILE: &str = "user-data";
+
+/// meta-data file name.
+const META_DATA_FILE: &str = "meta-data";
+
+/// vendor-data file name.
+const VENDOR_DATA_FILE: &str = "vendor-data";
+
+/// Instance information parsed from a NoCloud `meta-data` file.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct NoCloudMetaData {
+    /// The `instance-id` value.
+    pub instance_id: Option<String>,
+    /// The `local-hostname` (or `hostname`) value.
+    pub hostname: Option<String>,
+}
+
+/// Probe the default seed directories and return the first one containing `meta-data`.
+///
+/// cloud-init requires `meta-data` to be present in a NoCloud seed, so its
+/// presence is used as the detection signal.
+pub async fn probe() -> Result<PathBuf, MetadataError> {
+    for seed in SEED_PATHS {
+        let seed = Path::new(seed);
+        if tokio::fs::try_exists(seed.join(META_DATA_FILE))
+            .await
+            .unwrap_or(false)
+        {
+            return Ok(seed.to_path_buf());
+        }
+    }
+    Err(MetadataError::NotDetected)
+}
+
+/// Fetch user-data from a NoCloud seed directory.
+pub async fn fetch_user_data(
+    seed: &Path,
+    max_size: Option<usize>,
+) -> Result<Vec<u8>, MetadataError> {
+    read_file_limited(&seed.join(USER_DATA_FILE), max_size).await
+}
+
+/// Fetch vendor-data from a NoCloud seed directory.
+pub async fn fetch_vendor_data(
+    seed: &Path,
+    max_size: Option<usize>,
+) -> Result<Vec<u8>, MetadataError> {
+    read_file_limited(&seed.join(VENDOR_DATA_FILE), max_size).await
+}
+
+/// Fetch and parse meta-data from a NoCloud seed directory.
+pub async fn fetch_meta_data(
+    seed: &Path,
+    max_size: Option<usize>,
+) -> Result<NoCloudMetaData, MetadataError> {
+    let data = read_file_limited(&seed.join(META_DATA_FILE), max_size).await?;
+    let text = String::from_utf8(data).map_err(|_| MetadataError::Utf8)?;
+    parse_meta_data(&text)
+}
+
+/// Read a file relative to a NoCloud seed directory.
+///
+/// Paths leaving the seed directory are reported as `MetadataError::NotFound`.
+pub async fn fetch_path(
+    seed: &Path,
+    path: &str,
+    max_size: Option<usize>,
+) -> Result<Vec<u8>, MetadataError> {
+    read_file_limited(&seed.join(relative_path(path)?), max_size).await
+}
+
+/// List the seed files present in a NoCloud seed directory.
+///
+/// A missing seed directory is reported as `MetadataError::NotFound`.
+pub async fn list_files(seed: &Path) -> Result<Vec<String>, MetadataError> {
+    tokio::fs::metadata(seed).await.map_err(map_io_error)?;
+
+    let mut files = Vec::new();
+    for name in [META_DATA_FILE, USER_DATA_FILE, VENDOR_DATA_FILE] {
+        if tokio::fs::try_exists(seed.join(name)).await? {
+            files.push(name.to_string());
+        }
+    }
+    Ok(files)
+}
+
+/// Fetch the instance identity from meta-data.
+pub async fn fetch_identity(
+    seed: &Path,
+    max_size: Option<usize>,
+) -> Result<InstanceIdentity, MetadataError> {
+    let meta = fetch_meta_data(seed, max_size).await?;
+    Ok(InstanceIdentity {
+        instance_id: meta.instance_id,
+        hostname: meta.hostname,
+        ..Default::default()
+    })
+}
+
+/// Parse a `meta-data` document.
+///
+/// cloud-init accepts YAML here, but seeds only ever use flat `key: value`
+/// mappings (or the equivalent JSON object), so only top-level scalars are read.
+fn parse_meta_data(text: &str) -> Result<NoCloudMetaData, MetadataError> {
+    if text.trim_start().starts_with('{') {
+        let value: serde_json::Value = serde_json::from_str(text)?;
+        let get = |key: &str| value.get(key).and_then(|v| v.as_str()).map(str::to_string);
+        return Ok(NoCloudMetaData {
+            instance_id: get("instance-id"),
+            hostname: get("local-hostname").or_else(|| get("hostname")),
+        });
+    }
+
+    let mut meta = NoCloudMetaData::default();
+    let mut hostname = None;
+    for line in text.lines() {
+        // Nested values are indented; only top-level keys are of interest
+        if line.starts_with([' ', '\t', '#']) {
+            continue;
+        }
+        let Some((key, value)) = line.split_once(':') else {
+            continue;
+        };
+        let value = parse_scalar(value);
+        if value.is_empty() {
+            continue;
+        }
+        match key.trim() {
+            "instance-id" => meta.instance_id = Some(value),
+            "local-hostname" => meta.hostname = Some(value),
+            "hostname" => hostname = Some(value),
+            _ => {}
+        }
+    }
+    meta.hostname = meta.hostname.or(hostname);
+
+    Ok(meta)
+}
+
+/// Parse a YAML scalar value, stripping trailing comments and quotes.
+fn parse_scalar(value: &str) -> String {
+    let value = value.trim();
+    for quote in ['"', '\''] {
+        if let Some(inner) = value.strip_prefix(quote) {
+            if let Some(end) = inner.find(quote) {
+                return inner[..end].to_string();
+            }
+        }
+    }
+    let value = match value.find(" #") {
+        Some(pos) => &value[..pos],
+        None => value,
+    };
+    value.trim().to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_meta_data_yaml() {
+        let meta = parse_meta_data("instance-id: iid-local01\nlocal-hostname: node-1\n").unwrap();
+        assert_eq!(meta.instance_id.as_deref(), Some("iid-local01"));
+        assert_eq!(meta.hostname.as_deref(), Some("node-1"));
+    }
+
+    #[test]
+    fn test_parse_meta_data_quotes_and_comments() {
+        let text = "# seed\ninstance-id: \"iid-01\"\nhostname: 'node-2' # comment\nnetwork-interfaces: |\n  iface eth0 inet dhcp\n";
+        let meta = parse_meta_data(text).unwrap();
+        assert_eq!(meta.instance_id.as_deref(), Some("iid-01"));
+        assert_eq!(meta.hostname.as_deref(), Some("node-2"));
+    }
+
+    #[test]
+    fn test_parse_meta_data_prefers_local_hostname() {
+        let meta = parse_meta_data("hostname: a\nlocal-hostname: b\n").unwrap();
+        assert_eq!(meta.hostname.as_deref(), Some("b"));
+    }
+
+    #[test]
+    fn test_parse_meta_data_json() {
+        let meta =
+            parse_meta_data(r#"{"instance-id": "iid-json", "local-hostname": "node-3"}"#).unwrap();
+        assert_eq!(meta.instance_id.as_deref(), Some("iid-json"));
+        assert_eq!(meta.hostname.as_deref(), Some("node-3"));
+    }
+}
@@ -0,0 +1,277 @@
+//! OpenStack config drive implementation.
+//!
+//! A config drive is a `config-2` labelled ISO9660 (or vfat) volume. Either can
+//! be read from its mount point; only ISO9660 can also be read directly from an
+//! image file or block device.
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use serde::Deserialize;
+
+use crate::error::MetadataError;
+use crate::identity::InstanceIdentity;
+use crate::iso9660;
+use crate::local::{map_io_error, read_file_limited, relative_path};
+use crate::network::{normalize_mac, NetworkInterface};
+
+/// Default config drive location (read directly as an ISO9660 image).
+pub const DEFAULT_CONFIG_DRIVE_PATH: &str = "/dev/disk/by-label/config-2";
+
+/// Config drive devices probed during detection, in order.
+const CONFIG_DRIVE_PATHS: &[&str] = &[DEFAULT_CONFIG_DRIVE_PATH, "/dev/disk/by-label/CONFIG-2"];
+
+/// user_data path within the config drive.
+const USER_DATA_PATH: &str = "openstack/latest/user_data";
+
+/// meta_data.json path within the config drive.
+const META_DATA_PATH: &str = "openstack/latest/meta_data.json";
+
+/// network_data.json path within the config drive.
+const NETWORK_DATA_PATH: &str = "openstack/latest/network_data.json";
+
+/// vendor_data2.json path within the config drive.
+const VENDOR_DATA_PATH: &str = "openstack/latest/vendor_data2.json";
+
+/// Instance information parsed from `meta_data.json`.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(default)]
+pub struct OpenStackMetaData {
+    /// The instance UUID.
+    pub uuid: Option<String>,
+    /// The instance name.
+    pub name: Option<String>,
+    /// The instance hostname.
+    pub hostname: Option<String>,
+    /// The availability zone.
+    pub availability_zone: Option<String>,
+    /// The project (tenant) id.
+    pub project_id: Option<String>,
+    /// User-defined `meta` key/value pairs.
+    pub meta: BTreeMap<String, String>,
+}
+
+/// Probe the default config drive devices and return the first one present.
+pub async fn probe() -> Result<PathBuf, MetadataError> {
+    for drive in CONFIG_DRIVE_PATHS {
+        if tokio::fs::try_exists(drive).await.unwrap_or(false) {
+            return Ok(PathBuf::from(drive));
+        }
+    }
+    Err(MetadataError::NotDetected)
+}
+
+/// Read a file from a config drive directory or image.
+async fn read(drive: &Path, path: &str, max_size: Option<usize>) -> Result<Vec<u8>, MetadataError> {
+    if tokio::fs::metadata(drive)
+        .await
+        .map_err(map_io_error)?
+        .is_dir()
+    {
+        read_file_limited(&drive.join(path), max_size).await
+    } else {
+        iso9660::read_file(drive, path, max_size).await
+    }
+}
+
+/// Fetch user_data from a config drive.
+pub async fn fetch_user_data(
+    drive: &Path,
+    max_size: Option<usize>,
+) -> Result<Vec<u8>, MetadataError> {
+    read(drive, USER_DATA_PATH, max_size).await
+}
+
+/// Fetch vendor_data2.json from a config drive.
+pub async fn fetch_vendor_data(
+    drive: &Path,
+    max_size: Option<usize>,
+) -> Result<Vec<u8>, MetadataError> {
+    read(drive, VENDOR_DATA_PATH, max_size).await
+}
+
+/// Read a file relative to the config drive root, e.g. `openstack/latest/network_data.json`.
+///
+/// Paths leaving the config drive are reported as `MetadataError::NotFound`.
+pub async fn fetch_path(
+    drive: &Path,
+    path: &str,
+    max_size: Option<usize>,
+) -> Result<Vec<u8>, MetadataError> {
+    read(drive, relative_path(path)?, max_size).await
+}
+
+/// Fetch and parse meta_data.json from a config drive.
+pub async fn fetch_meta_data(
+    drive: &Path,
+    max_size: Option<usize>,
+) -> Result<OpenStackMetaData, MetadataError> {
+    let data = read(drive, META_DATA_PATH, max_size).await?;
+    serde_json::from_slice(&data).map_err(MetadataError::from)
+}
+
+/// Fetch the instance identity from meta_data.json.
+pub async fn fetch_identity(
+    drive: &Path,
+    max_size: Option<usize>,
+) -> Result<InstanceIdentity, MetadataError> {
+    let meta = fetch_meta_data(drive, max_size).await?;
+    Ok(InstanceIdentity {
+        instance_id: meta.uuid,
+        zone: meta.availability_zone,
+        hostname: meta.hostname.or(meta.name),
+        account_id: meta.project_id,
+        ..Default::default()
+    })
+}
+
+/// network_data.json document.
+#[derive(Deserialize)]
+struct NetworkData {
+    #[serde(default)]
+    links: Vec<Link>,
+    #[serde(default)]
+    networks: Vec<Network>,
+}
+
+/// A physical or virtual link.
+#[derive(Deserialize)]
+struct Link {
+    id: String,
+    #[serde(default)]
+    ethernet_mac_address: String,
+}
+
+/// A network configured on a link.
+#[derive(Deserialize)]
+struct Network {
+    link: String,
+    #[serde(rename = "type")]
+    kind: String,
+    #[serde(default)]
+    ip_address: Option<String>,
+    #[serde(default)]
+    network_id: Option<String>,
+}
+
+/// Fetch network interfaces from network_data.json. The first link is the
+/// primary interface.
+pub async fn fetch_network(
+    drive: &Path,
+    max_size: Option<usize>,
+) -> Result<Vec<NetworkInterface>, MetadataError> {
+    let data = read(drive, NETWORK_DATA_PATH, max_size).await?;
+    let data: NetworkData = serde_json::from_slice(&data)?;
+
+    Ok(data
+        .links
+        .iter()
+        .enumerate()
+        .map(|(index, link)| {
+            let networks: Vec<&Network> =
+                data.networks.iter().filter(|n| n.link == link.id).collect();
+            let addresses = |ipv6: bool| {
+                networks
+                    .iter()
+                    .filter(|n| n.kind.starts_with("ipv6") == ipv6)
+                    .filter_map(|n| n.ip_address.clone())
+                    .collect()
+            };
+            NetworkInterface {
+                mac: normalize_mac(&link.ethernet_mac_address),
+                primary: index == 0,
+                private_ipv4: addresses(false),
+                ipv6: addresses(true),
+                public_ipv4: Vec::new(),
+                subnet_id: None,
+                vpc_id: networks.iter().find_map(|n| n.network_id.clone()),
+            }
+        })
+        .collect())
+}
+
+/// Fetch the `meta` entries of meta_data.json as tags.
+pub async fn fetch_tags(
+    drive: &Path,
+    max_size: Option<usize>,
+) -> Result<BTreeMap<String, String>, MetadataError> {
+    Ok(fetch_meta_data(drive, max_size).await?.meta)
+}
+
+/// List the `meta` keys of meta_data.json.
+pub async fn list_meta_keys(
+    drive: &Path,
+    max_size: Option<usize>,
+) -> Result<Vec<String>, MetadataError> {
+    Ok(fetch_meta_data(drive, max_size)
+        .await?
+        .meta
+        .into_keys()
+        .collect())
+}
+
+/// Fetch an entry of the `meta` section of meta_data.json.
+///
+/// Returns `None` if there is no such entry.
+pub async fn fetch_meta_entry(
+    drive: &Path,
+    key: &str,
+    max_size: Option<usize>,
+) -> Result<Option<Vec<u8>>, MetadataError> {
+    let meta = fetch_meta_data(drive, max_size).await?;
+    Ok(meta.meta.get(key).map(|value| value.clone().into_bytes()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_paths() {
+        assert!(USER_DATA_PATH.starts_with("openstack/latest/"));
+        assert!(META_DATA_PATH.starts_with("openstack/latest/"));
+        assert!(VENDOR_DATA_PATH.starts_with("openstack/latest/"));
+        assert!(NETWORK_DATA_PATH.starts_with("openstack/latest/"));
+    }
+
+    #[test]
+    fn test_parse_meta_data() {
+        let json = r#"{
+            "uuid": "d8e02d56-2648-49a3-bf97-6be8f1204f38",
+            "hostname": "node-1.novalocal",
+            "availability_zone": "nova",
+            "launch_index": 0,
+            "meta": {"role": "worker"}
+        }"#;
+        let meta: OpenStackMetaData = serde_json::from_str(json).unwrap();
+        assert_eq!(
+            meta.uuid.as_deref(),
+            Some("d8e02d56-2648-49a3-bf97-6be8f1204f38")
+        );
+        assert_eq!(meta.availability_zone.as_deref(), Some("nova"));
+        assert_eq!(meta.meta.get("role").map(String::as_str), Some("worker"));
+        assert_eq!(meta.name, None);
+    }
+
+    #[tokio::test]
+    async fn test_read_from_image() {
+        let image = iso9660::tests::build_image(
+            &[
+                (USER_DATA_PATH, b"#cloud-config\n"),
+                (META_DATA_PATH, br#"{"meta": {"config": "{}"}}"#),
+            ],
+            true,
+        );
+        let file = tempfile::NamedTempFile::new().unwrap();
+        std::fs::write(file.path(), image).unwrap();
+
+        let data = fetch_meta_entry(file.path(), "config", None).await.unwrap();
+        assert_eq!(data.as_deref(), Some(&b"{}"[..]));
+        let data = fetch_meta_entry(file.path(), "missing", None)
+            .await
+            .unwrap();
+        assert_eq!(data, None);
+        let data = fetch_user_data(file.path(), None).await.unwrap();
+        assert_eq!(data, b"#cloud-config\n");
+    }
+}
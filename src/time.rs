@@ -0,0 +1,115 @@
+//! Timestamp parsing for expiry and event deadlines.
+
+use std::time::{Duration, SystemTime};
+
+/// Convert a UTC calendar date and time to a `SystemTime`.
+///
+/// Returns `None` for out-of-range fields or dates before the epoch.
+pub(crate) fn from_civil(
+    year: i64,
+    month: i64,
+    day: i64,
+    hour: u64,
+    minute: u64,
+    second: u64,
+) -> Option<SystemTime> {
+    if !(1..=12).contains(&month)
+        || !(1..=31).contains(&day)
+        || hour > 23
+        || minute > 59
+        || second > 60
+    {
+        return None;
+    }
+
+    // Days since the epoch for a proleptic Gregorian date
+    let (y, m) = if month <= 2 {
+        (year - 1, month + 9)
+    } else {
+        (year, month - 3)
+    };
+    let era = y.div_euclid(400);
+    let yoe = y - era * 400;
+    let doy = (153 * m + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    let days = u64::try_from(era * 146_097 + doe - 719_468).ok()?;
+
+    let seconds = days * 86_400 + hour * 3_600 + minute * 60 + second;
+    Some(SystemTime::UNIX_EPOCH + Duration::from_secs(seconds))
+}
+
+/// Parse an RFC 3339 UTC timestamp such as `2024-05-01T12:00:00Z`.
+pub(crate) fn parse_timestamp(value: &str) -> Option<SystemTime> {
+    let value = value.strip_suffix('Z')?;
+    let (date, time) = value.split_once('T')?;
+    let time = time.split_once('.').map_or(time, |(whole, _)| whole);
+
+    let mut date = date.splitn(3, '-').map(str::parse::<i64>);
+    let (year, month, day) = (date.next()?.ok()?, date.next()?.ok()?, date.next()?.ok()?);
+    let mut time = time.splitn(3, ':').map(str::parse::<u64>);
+    let (hour, minute, second) = (time.next()?.ok()?, time.next()?.ok()?, time.next()?.ok()?);
+
+    from_civil(year, month, day, hour, minute, second)
+}
+
+/// Parse an HTTP date such as `Mon, 19 Sep 2016 18:29:47 GMT`.
+///
+/// The weekday is optional, as in AWS maintenance event times.
+pub(crate) fn parse_http_date(value: &str) -> Option<SystemTime> {
+    let value = value.split_once(", ").map_or(value, |(_, date)| date);
+    let mut parts = value.split_whitespace();
+    let day = parts.next()?.parse().ok()?;
+    let month = parts.next()?;
+    let month = MONTHS.iter().position(|m| *m == month)? as i64 + 1;
+    let year = parts.next()?.parse().ok()?;
+    let mut time = parts.next()?.splitn(3, ':').map(str::parse::<u64>);
+    let (hour, minute, second) = (time.next()?.ok()?, time.next()?.ok()?, time.next()?.ok()?);
+    if !matches!(parts.next(), Some("GMT" | "UTC")) {
+        return None;
+    }
+
+    from_civil(year, month, day, hour, minute, second)
+}
+
+/// Abbreviated month names of HTTP dates.
+const MONTHS: [&str; 12] = [
+    "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_timestamp() {
+        let epoch = |s: u64| SystemTime::UNIX_EPOCH + Duration::from_secs(s);
+        assert_eq!(parse_timestamp("1970-01-01T00:00:00Z"), Some(epoch(0)));
+        assert_eq!(
+            parse_timestamp("2024-05-01T12:34:56Z"),
+            Some(epoch(1_714_566_896))
+        );
+        assert_eq!(
+            parse_timestamp("2024-02-29T00:00:00.123Z"),
+            Some(epoch(1_709_164_800))
+        );
+        assert_eq!(parse_timestamp("2024-05-01T12:34:56+02:00"), None);
+        assert_eq!(parse_timestamp("2024-13-01T00:00:00Z"), None);
+        assert_eq!(parse_timestamp("bogus"), None);
+    }
+
+    #[test]
+    fn test_parse_http_date() {
+        let epoch = |s: u64| SystemTime::UNIX_EPOCH + Duration::from_secs(s);
+        assert_eq!(
+            parse_http_date("Mon, 19 Sep 2016 18:29:47 GMT"),
+            Some(epoch(1_474_309_787))
+        );
+        assert_eq!(
+            parse_http_date("21 Jan 2019 09:00:43 GMT"),
+            Some(epoch(1_548_061_243))
+        );
+        assert_eq!(parse_http_date("21 Foo 2019 09:00:43 GMT"), None);
+        assert_eq!(parse_http_date("21 Jan 2019 09:00:43 +0200"), None);
+        assert_eq!(parse_http_date(""), None);
+    }
+}
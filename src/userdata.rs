@@ -0,0 +1,649 @@
+//! Decoding and classification of user-data.
+
+use std::io::Read;
+
+use base64::alphabet;
+use base64::engine::general_purpose::{GeneralPurpose, GeneralPurposeConfig};
+use base64::engine::DecodePaddingMode;
+use base64::Engine;
+use flate2::read::MultiGzDecoder;
+
+use crate::error::MetadataError;
+
+/// Magic bytes of a gzip stream.
+const GZIP_MAGIC: &[u8] = &[0x1f, 0x8b];
+
+/// Magic bytes of a zstd frame.
+const ZSTD_MAGIC: &[u8] = &[0x28, 0xb5, 0x2f, 0xfd];
+
+/// Upper bound on decompressed data when no `max_size` is given, so a small
+/// compression bomb cannot exhaust memory.
+const MAX_DECOMPRESSED_SIZE: usize = 16 * 1024 * 1024;
+
+/// How many nested encodings are removed at most, e.g. base64 of gzip.
+const MAX_LAYERS: usize = 4;
+
+/// Padding is optional, as base64 in user-data is often hand-made.
+const LENIENT: GeneralPurposeConfig =
+    GeneralPurposeConfig::new().with_decode_padding_mode(DecodePaddingMode::Indifferent);
+
+/// Standard base64 with optional padding.
+const STANDARD_LENIENT: GeneralPurpose = GeneralPurpose::new(&alphabet::STANDARD, LENIENT);
+
+/// URL-safe base64 with optional padding.
+const URL_SAFE_LENIENT: GeneralPurpose = GeneralPurpose::new(&alphabet::URL_SAFE, LENIENT);
+
+/// Kind of user-data content, as cloud-init tells them apart.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum UserDataKind {
+    /// A cloud-config document, starting with `#cloud-config`.
+    CloudConfig,
+    /// A script, starting with `#!`.
+    ShellScript,
+    /// A JSON object or array.
+    Json,
+    /// A MIME multipart archive.
+    Multipart,
+    /// Other UTF-8 text.
+    Text,
+    /// Data that is not UTF-8.
+    Binary,
+}
+
+impl UserDataKind {
+    /// Classify user-data by its content.
+    ///
+    /// Compressed or base64-encoded data is not looked into; decode it first.
+    pub fn detect(data: &[u8]) -> Self {
+        if data.starts_with(b"#cloud-config") {
+            UserDataKind::CloudConfig
+        } else if data.starts_with(b"#!") {
+            UserDataKind::ShellScript
+        } else if is_multipart(data) {
+            UserDataKind::Multipart
+        } else if is_json_document(data) {
+            UserDataKind::Json
+        } else if std::str::from_utf8(data).is_ok() {
+            UserDataKind::Text
+        } else {
+            UserDataKind::Binary
+        }
+    }
+
+    /// The content type cloud-init uses for the kind.
+    fn content_type(self) -> &'static str {
+        match self {
+            UserDataKind::CloudConfig => "text/cloud-config",
+            UserDataKind::ShellScript => "text/x-shellscript",
+            UserDataKind::Json => "application/json",
+            UserDataKind::Multipart => "multipart/mixed",
+            UserDataKind::Text => "text/plain",
+            UserDataKind::Binary => "application/octet-stream",
+        }
+    }
+
+    /// Whether the kind is a format user-data is written in, rather than
+    /// plain text or binary data.
+    fn is_known(self) -> bool {
+        !matches!(self, UserDataKind::Text | UserDataKind::Binary)
+    }
+}
+
+/// Check for a JSON object or array. Scalars are not user-data documents, and
+/// would let decoded base64 such as `MTAw` (`100`) pass as JSON.
+fn is_json_document(data: &[u8]) -> bool {
+    let start = data.iter().find(|b| !b.is_ascii_whitespace());
+    matches!(start, Some(b'{' | b'['))
+        && serde_json::from_slice::<serde::de::IgnoredAny>(data).is_ok()
+}
+
+/// Check for a `multipart/*` content type with a boundary in the leading
+/// MIME headers.
+fn is_multipart(data: &[u8]) -> bool {
+    Entity::parse(data).boundary().is_some()
+}
+
+/// Remove gzip, zstd and base64 encodings from user-data.
+///
+/// Encodings may be nested, e.g. gzip inside base64. Base64 is only decoded
+/// if the result is compressed or a known kind of user-data, so plain text
+/// that happens to be valid base64 is kept as is. The `max_size` limit
+/// applies to every decompressed layer; without it, decompression stops with
+/// an error after 16 MiB. zstd requires the `zstd` feature; without it zstd
+/// data is returned unchanged.
+pub(crate) fn decode(data: Vec<u8>, max_size: Option<usize>) -> Result<Vec<u8>, MetadataError> {
+    let mut data = data;
+    for _ in 0..MAX_LAYERS {
+        match decode_layer(&data, max_size)? {
+            Some(decoded) => data = decoded,
+            None => break,
+        }
+    }
+    Ok(data)
+}
+
+/// Remove the outermost encoding, or return `None` if there is none.
+fn decode_layer(data: &[u8], max_size: Option<usize>) -> Result<Option<Vec<u8>>, MetadataError> {
+    if data.starts_with(GZIP_MAGIC) {
+        return decompress(MultiGzDecoder::new(data), max_size).map(Some);
+    }
+
+    #[cfg(feature = "zstd")]
+    if is_zstd(data) {
+        let decoder = ruzstd::decoding::StreamingDecoder::new(data)
+            .map_err(|e| MetadataError::Decompress(e.to_string()))?;
+        return decompress(decoder, max_size).map(Some);
+    }
+
+    if UserDataKind::detect(data).is_known() {
+        return Ok(None);
+    }
+
+    Ok(decode_base64(data).filter(|decoded| {
+        decoded.starts_with(GZIP_MAGIC)
+            || is_zstd(decoded)
+            || UserDataKind::detect(decoded).is_known()
+    }))
+}
+
+/// Decode standard or URL-safe base64, ignoring line breaks.
+fn decode_base64(data: &[u8]) -> Option<Vec<u8>> {
+    let b64: Vec<u8> = data
+        .iter()
+        .copied()
+        .filter(|b| !b.is_ascii_whitespace())
+        .collect();
+    if b64.is_empty() {
+        return None;
+    }
+    if b64.iter().any(|b| matches!(b, b'-' | b'_')) {
+        URL_SAFE_LENIENT.decode(&b64).ok()
+    } else {
+        STANDARD_LENIENT.decode(&b64).ok()
+    }
+}
+
+/// Whether the data is a zstd frame that can be decompressed.
+fn is_zstd(data: &[u8]) -> bool {
+    cfg!(feature = "zstd") && data.starts_with(ZSTD_MAGIC)
+}
+
+/// Read a decompressing reader to the end, enforcing the size limit.
+///
+/// At most one byte past the limit is decompressed, so the reported size of
+/// oversized data is `max_size + 1`. Without a limit, data larger than
+/// `MAX_DECOMPRESSED_SIZE` is a decompression error.
+fn decompress(reader: impl Read, max_size: Option<usize>) -> Result<Vec<u8>, MetadataError> {
+    let limit = max_size.unwrap_or(MAX_DECOMPRESSED_SIZE);
+    let mut decompressed = Vec::new();
+    reader
+        .take(limit as u64 + 1)
+        .read_to_end(&mut decompressed)
+        .map_err(|e| MetadataError::Decompress(e.to_string()))?;
+
+    if decompressed.len() > limit {
+        return Err(match max_size {
+            Some(max) => MetadataError::TooLarge(decompressed.len(), max),
+            None => MetadataError::Decompress(format!(
+                "decompressed data exceeds {} bytes",
+                MAX_DECOMPRESSED_SIZE
+            )),
+        });
+    }
+
+    Ok(decompressed)
+}
+
+/// A part of MIME multipart user-data.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UserDataPart {
+    /// The `filename` of the part's Content-Disposition, or the `name`
+    /// parameter of its Content-Type.
+    pub filename: Option<String>,
+    /// The lowercase media type without parameters, e.g. `text/cloud-config`.
+    pub content_type: String,
+    /// The content, with its Content-Transfer-Encoding removed.
+    pub data: Vec<u8>,
+}
+
+/// Split user-data into its MIME parts.
+///
+/// Nested multipart parts are flattened. User-data that is not multipart is
+/// returned as a single part, with the content type cloud-init uses for its
+/// kind.
+pub(crate) fn parts(data: &[u8]) -> Result<Vec<UserDataPart>, MetadataError> {
+    let entity = Entity::parse(data);
+    if entity.boundary().is_some() {
+        let mut leaves = Vec::new();
+        collect_leaves(entity, &mut leaves);
+        return leaves
+            .iter()
+            .map(|part| {
+                Ok(UserDataPart {
+                    filename: part.filename(),
+                    content_type: part.media_type().unwrap_or("text/plain").to_string(),
+                    data: part.content()?,
+                })
+            })
+            .collect();
+    }
+
+    Ok(vec![UserDataPart {
+        filename: None,
+        content_type: UserDataKind::detect(data).content_type().to_string(),
+        data: data.to_vec(),
+    }])
+}
+
+/// Find the multipart part whose filename or content type is `key`.
+///
+/// Returns `None` if the data is not multipart or no part matches. Only the
+/// matching part is decoded, so a broken encoding of another part does not
+/// fail the lookup.
+pub(crate) fn select_part(data: &[u8], key: &str) -> Result<Option<Vec<u8>>, MetadataError> {
+    let entity = Entity::parse(data);
+    if entity.boundary().is_none() {
+        return Ok(None);
+    }
+    let mut leaves = Vec::new();
+    collect_leaves(entity, &mut leaves);
+    leaves
+        .iter()
+        .find(|part| {
+            part.filename().as_deref() == Some(key)
+                || part
+                    .media_type()
+                    .unwrap_or("text/plain")
+                    .eq_ignore_ascii_case(key)
+        })
+        .map(Entity::content)
+        .transpose()
+}
+
+/// Add the non-multipart parts of a multipart entity to `leaves`,
+/// descending into nested multipart parts.
+fn collect_leaves<'a>(entity: Entity<'a>, leaves: &mut Vec<Entity<'a>>) {
+    let Some(boundary) = entity.boundary() else {
+        return;
+    };
+    for body in split_multipart(entity.body, &boundary) {
+        let part = Entity::parse(body);
+        if part.boundary().is_some() {
+            collect_leaves(part, leaves);
+        } else {
+            leaves.push(part);
+        }
+    }
+}
+
+/// Split a multipart body at the boundary delimiter lines.
+///
+/// The preamble before the first delimiter and the epilogue after the close
+/// delimiter are dropped.
+fn split_multipart<'a>(body: &'a [u8], boundary: &str) -> Vec<&'a [u8]> {
+    let delimiter = format!("--{}", boundary);
+    let mut parts = Vec::new();
+    let mut start = None;
+    let mut pos = 0;
+    for line in body.split_inclusive(|&b| b == b'\n') {
+        let rest = line
+            .trim_ascii_end()
+            .strip_prefix(delimiter.as_bytes())
+            .filter(|rest| rest.is_empty() || *rest == b"--");
+        if let Some(rest) = rest {
+            if let Some(start) = start {
+                // The line break before a delimiter belongs to the delimiter
+                let part: &[u8] = &body[start..pos];
+                let part = part.strip_suffix(b"\n").unwrap_or(part);
+                parts.push(part.strip_suffix(b"\r").unwrap_or(part));
+            }
+            if rest == b"--" {
+                break;
+            }
+            start = Some(pos + line.len());
+        }
+        pos += line.len();
+    }
+    parts
+}
+
+/// A MIME entity: headers and body.
+struct Entity<'a> {
+    /// Header names in lowercase, with their unfolded values.
+    headers: Vec<(String, String)>,
+    body: &'a [u8],
+}
+
+impl<'a> Entity<'a> {
+    /// Split data into headers and body at the first empty line.
+    ///
+    /// Data that does not start with headers has an empty header list.
+    fn parse(data: &'a [u8]) -> Self {
+        let mut headers: Vec<(String, String)> = Vec::new();
+        let mut pos = 0;
+        for line in data.split_inclusive(|&b| b == b'\n') {
+            let text = String::from_utf8_lossy(line);
+            let text = text.trim_end();
+            if text.is_empty() {
+                pos += line.len();
+                break;
+            }
+            if text.starts_with([' ', '\t']) && !headers.is_empty() {
+                // Folded continuation of the previous header
+                if let Some((_, value)) = headers.last_mut() {
+                    value.push(' ');
+                    value.push_str(text.trim());
+                }
+            } else if let Some((name, value)) = text.split_once(':') {
+                headers.push((name.trim().to_ascii_lowercase(), value.trim().to_string()));
+            } else {
+                return Entity {
+                    headers: Vec::new(),
+                    body: data,
+                };
+            }
+            pos += line.len();
+        }
+        Entity {
+            headers,
+            body: &data[pos..],
+        }
+    }
+
+    /// The value of the first header with the given lowercase name.
+    fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(header, _)| header == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// The media type of the Content-Type header, without parameters.
+    fn media_type(&self) -> Option<&str> {
+        let value = self.header("content-type")?;
+        Some(value.split(';').next().unwrap_or(value).trim())
+    }
+
+    /// The boundary of a multipart entity.
+    fn boundary(&self) -> Option<String> {
+        let media_type = self.media_type()?;
+        if !media_type.to_ascii_lowercase().starts_with("multipart/") {
+            return None;
+        }
+        parameter(self.header("content-type")?, "boundary")
+    }
+
+    /// The `filename` of the Content-Disposition header, or the `name`
+    /// parameter of the Content-Type header.
+    fn filename(&self) -> Option<String> {
+        self.header("content-disposition")
+            .and_then(|value| parameter(value, "filename"))
+            .or_else(|| {
+                self.header("content-type")
+                    .and_then(|value| parameter(value, "name"))
+            })
+    }
+
+    /// The body with its Content-Transfer-Encoding removed.
+    fn content(&self) -> Result<Vec<u8>, MetadataError> {
+        match self.header("content-transfer-encoding") {
+            Some(encoding) if encoding.eq_ignore_ascii_case("base64") => {
+                let b64: Vec<u8> = self
+                    .body
+                    .iter()
+                    .copied()
+                    .filter(|b| !b.is_ascii_whitespace())
+                    .collect();
+                STANDARD_LENIENT
+                    .decode(b64)
+                    .map_err(|_| MetadataError::Base64)
+            }
+            _ => Ok(self.body.to_vec()),
+        }
+    }
+}
+
+/// Look up a parameter of a header value such as `attachment; filename="a.json"`.
+fn parameter(value: &str, name: &str) -> Option<String> {
+    value.split(';').skip(1).find_map(|param| {
+        let (key, value) = param.split_once('=')?;
+        if !key.trim().eq_ignore_ascii_case(name) {
+            return None;
+        }
+        let value = value.trim();
+        let value = value
+            .strip_prefix('"')
+            .and_then(|v| v.strip_suffix('"'))
+            .unwrap_or(value);
+        Some(value.to_string())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::io::Write;
+
+    use base64::engine::general_purpose::{STANDARD, URL_SAFE_NO_PAD};
+    use flate2::write::GzEncoder;
+    use flate2::Compression;
+
+    fn gzip(data: &[u8]) -> Vec<u8> {
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(data).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    #[test]
+    fn test_detect() {
+        assert_eq!(
+            UserDataKind::detect(b"#cloud-config\npackages: [jq]\n"),
+            UserDataKind::CloudConfig
+        );
+        assert_eq!(
+            UserDataKind::detect(b"#!/bin/sh\necho hi\n"),
+            UserDataKind::ShellScript
+        );
+        assert_eq!(
+            UserDataKind::detect(b" {\"port\": 5432}\n"),
+            UserDataKind::Json
+        );
+        assert_eq!(
+            UserDataKind::detect(
+                b"MIME-Version: 1.0\r\nContent-Type: multipart/mixed; boundary=\"b\"\r\n\r\n--b--\r\n"
+            ),
+            UserDataKind::Multipart
+        );
+        assert_eq!(UserDataKind::detect(b"[1, 2]"), UserDataKind::Json);
+        assert_eq!(UserDataKind::detect(b"100"), UserDataKind::Text);
+        assert_eq!(UserDataKind::detect(b"\"text\""), UserDataKind::Text);
+        assert_eq!(UserDataKind::detect(b"hello"), UserDataKind::Text);
+        assert_eq!(UserDataKind::detect(&[0xff, 0x00]), UserDataKind::Binary);
+    }
+
+    #[test]
+    fn test_decode_gzip() {
+        let data = b"#cloud-config\nhostname: test\n";
+        assert_eq!(decode(gzip(data), None).unwrap(), data);
+    }
+
+    #[test]
+    fn test_decode_base64() {
+        let json = br#"{"db_host": "postgres.internal", "port": 5432}"#;
+
+        let wrapped = STANDARD.encode(json);
+        let wrapped = format!("{}\n{}\n", &wrapped[..40], &wrapped[40..]);
+        assert_eq!(decode(wrapped.into_bytes(), None).unwrap(), json);
+
+        let url_safe = URL_SAFE_NO_PAD.encode(gzip(json));
+        assert_eq!(decode(url_safe.into_bytes(), None).unwrap(), json);
+    }
+
+    #[test]
+    fn test_decode_keeps_plain_data() {
+        // Valid base64, but neither decodes to known user-data
+        for data in [
+            &b"hello world"[..],
+            b"abcd",
+            b"1234",
+            b"MTAw",
+            b"dHJ1ZQ==",
+            b"#!/bin/sh\n",
+        ] {
+            assert_eq!(decode(data.to_vec(), None).unwrap(), data);
+        }
+    }
+
+    #[test]
+    fn test_decode_limits_decompressed_size() {
+        let bomb = gzip(&vec![b' '; 1 << 20]);
+        assert!(bomb.len() < 4096);
+
+        let result = decode(bomb, Some(4096));
+        assert!(matches!(result, Err(MetadataError::TooLarge(4097, 4096))));
+    }
+
+    #[test]
+    fn test_decode_limits_decompressed_size_by_default() {
+        let bomb = gzip(&vec![0; MAX_DECOMPRESSED_SIZE + 1]);
+        assert!(bomb.len() < 64 * 1024);
+
+        assert!(matches!(
+            decode(bomb, None),
+            Err(MetadataError::Decompress(_))
+        ));
+    }
+
+    #[test]
+    fn test_decode_corrupt_gzip() {
+        let mut data = gzip(b"#cloud-config\n");
+        data.truncate(12);
+        assert!(matches!(
+            decode(data, None),
+            Err(MetadataError::Decompress(_))
+        ));
+    }
+
+    const MULTIPART: &str = "Content-Type: multipart/mixed; boundary=\"===============123==\"\r\n\
+MIME-Version: 1.0\r\n\
+\r\n\
+--===============123==\r\n\
+Content-Type: text/cloud-config; charset=\"us-ascii\"\r\n\
+Content-Transfer-Encoding: 7bit\r\n\
+Content-Disposition: attachment; filename=\"cloud-config.txt\"\r\n\
+\r\n\
+#cloud-config\r\n\
+packages: [jq]\r\n\
+\r\n\
+--===============123==\r\n\
+Content-Type: application/json\r\n\
+Content-Transfer-Encoding: base64\r\n\
+Content-Disposition: attachment;\r\n\
+\x20filename=\"app-config.json\"\r\n\
+\r\n\
+eyJwb3J0IjogNTQzMn0=\r\n\
+--===============123==--\r\n";
+
+    #[test]
+    fn test_parts() {
+        let parts = parts(MULTIPART.as_bytes()).unwrap();
+        assert_eq!(parts.len(), 2);
+        assert_eq!(parts[0].filename.as_deref(), Some("cloud-config.txt"));
+        assert_eq!(parts[0].content_type, "text/cloud-config");
+        assert_eq!(parts[0].data, b"#cloud-config\r\npackages: [jq]\r\n");
+        assert_eq!(parts[1].filename.as_deref(), Some("app-config.json"));
+        assert_eq!(parts[1].content_type, "application/json");
+        assert_eq!(parts[1].data, br#"{"port": 5432}"#);
+    }
+
+    #[test]
+    fn test_parts_nested() {
+        let data = "Content-Type: multipart/mixed; boundary=outer\n\n\
+--outer\n\
+Content-Type: multipart/alternative; boundary=inner\n\n\
+--inner\n\
+Content-Type: text/x-shellscript\n\n\
+#!/bin/sh\n\
+--inner--\n\
+--outer\n\
+Content-Type: text/plain; name=notes.txt\n\n\
+hello\n\
+--outer--\n";
+        let parts = parts(data.as_bytes()).unwrap();
+        assert_eq!(parts.len(), 2);
+        assert_eq!(parts[0].content_type, "text/x-shellscript");
+        assert_eq!(parts[0].data, b"#!/bin/sh");
+        assert_eq!(parts[1].filename.as_deref(), Some("notes.txt"));
+        assert_eq!(parts[1].data, b"hello");
+    }
+
+    #[test]
+    fn test_parts_single() {
+        let parts = parts(b"#cloud-config\n").unwrap();
+        assert_eq!(
+            parts,
+            vec![UserDataPart {
+                filename: None,
+                content_type: "text/cloud-config".to_string(),
+                data: b"#cloud-config\n".to_vec(),
+            }]
+        );
+    }
+
+    #[test]
+    fn test_select_part() {
+        let data = MULTIPART.as_bytes();
+        assert_eq!(
+            select_part(data, "app-config.json").unwrap().unwrap(),
+            br#"{"port": 5432}"#
+        );
+        assert_eq!(
+            select_part(data, "text/cloud-config").unwrap().unwrap(),
+            b"#cloud-config\r\npackages: [jq]\r\n"
+        );
+        assert_eq!(select_part(data, "missing").unwrap(), None);
+        assert_eq!(
+            select_part(b"#cloud-config\n", "text/cloud-config").unwrap(),
+            None
+        );
+    }
+
+    #[test]
+    fn test_select_part_ignores_other_encodings() {
+        let data = "Content-Type: multipart/mixed; boundary=b\n\n\
+--b\n\
+Content-Type: application/json\n\
+Content-Transfer-Encoding: base64\n\
+Content-Disposition: attachment; filename=broken.json\n\n\
+not base64!\n\
+--b\n\
+Content-Type: text/x-shellscript\n\n\
+#!/bin/sh\n\
+--b--\n";
+        assert_eq!(
+            select_part(data.as_bytes(), "text/x-shellscript")
+                .unwrap()
+                .unwrap(),
+            b"#!/bin/sh"
+        );
+        assert!(matches!(
+            select_part(data.as_bytes(), "broken.json"),
+            Err(MetadataError::Base64)
+        ));
+    }
+
+    #[cfg(feature = "zstd")]
+    #[test]
+    fn test_decode_zstd() {
+        use ruzstd::encoding::{compress_to_vec, CompressionLevel};
+
+        let data = b"#!/bin/sh\necho hi\n";
+        let compressed = compress_to_vec(&data[..], CompressionLevel::Fastest);
+        assert_eq!(decode(compressed.clone(), None).unwrap(), data);
+        assert_eq!(
+            decode(STANDARD.encode(compressed).into_bytes(), None).unwrap(),
+            data
+        );
+    }
+}
@@ -0,0 +1,73 @@
+//! AWS instance identity document verification.
+
+use super::pkcs7::Signed;
+use super::{decode_base64, fail, parse_certificates};
+use crate::error::MetadataError;
+use crate::providers::aws::AwsIdentityDocument;
+
+/// Verify an RSA-2048 PKCS7 signature of the instance identity document.
+///
+/// `signature` is the content of `dynamic/instance-identity/rsa2048`, which
+/// embeds the signed document. The DSA signature from
+/// `dynamic/instance-identity/pkcs7` is not supported. `certificates` are the
+/// PEM-encoded AWS RSA-2048 certificates of the regions to accept; AWS
+/// publishes them per region in the EC2 documentation. They are not bundled
+/// with the crate. The signer must name the certificate it was signed with.
+/// The document is parsed from the signed content.
+///
+/// # Errors
+///
+/// Returns `MetadataError::Verification` if the signature is not valid for
+/// any of the certificates.
+pub fn aws_identity_document(
+    signature: &str,
+    certificates: &[&str],
+) -> Result<AwsIdentityDocument, MetadataError> {
+    let certificates = parse_certificates(certificates)?;
+    let signed = Signed::parse(&decode_base64(signature)?)?;
+
+    if !certificates.iter().any(|cert| signed.verify(cert).is_ok()) {
+        return Err(fail("signature does not match any supplied certificate"));
+    }
+    serde_json::from_slice(&signed.content).map_err(MetadataError::from)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DOCUMENT: &str = include_str!("../../tests/fixtures/verify/aws-document.json");
+    const CERT: &str = include_str!("../../tests/fixtures/verify/aws-cert.pem");
+    const OTHER_CERT: &str = include_str!("../../tests/fixtures/verify/other-cert.pem");
+    const SIGNATURE: &str = include_str!("../../tests/fixtures/verify/aws-rsa2048");
+    const SIGNATURE_NOATTR: &str = include_str!("../../tests/fixtures/verify/aws-rsa2048-noattr");
+
+    #[test]
+    fn test_verify() {
+        let document = aws_identity_document(SIGNATURE, &[OTHER_CERT, CERT]).unwrap();
+        assert_eq!(document, serde_json::from_str(DOCUMENT).unwrap());
+        assert_eq!(document.instance_id, "i-1234567890abcdef0");
+    }
+
+    #[test]
+    fn test_verify_without_signed_attributes() {
+        let document = aws_identity_document(SIGNATURE_NOATTR, &[CERT]).unwrap();
+        assert_eq!(document.region, "us-east-1");
+    }
+
+    #[test]
+    fn test_wrong_certificate() {
+        let result = aws_identity_document(SIGNATURE, &[OTHER_CERT]);
+        assert!(matches!(result, Err(MetadataError::Verification(_))));
+    }
+
+    #[test]
+    fn test_tampered_signature() {
+        let mut der = decode_base64(SIGNATURE).unwrap();
+        let pos = der.len() - 10;
+        der[pos] ^= 0xff;
+        let tampered = base64::Engine::encode(&base64::engine::general_purpose::STANDARD, der);
+        let result = aws_identity_document(&tampered, &[CERT]);
+        assert!(matches!(result, Err(MetadataError::Verification(_))));
+    }
+}
@@ -0,0 +1,239 @@
+//! Azure attested data document verification.
+
+use std::time::{Duration, SystemTime};
+
+use serde::Deserialize;
+
+use super::pkcs7::Signed;
+use super::x509::{common_name, verify_chain};
+use super::{decode_base64, fail, parse_certificates};
+use crate::error::MetadataError;
+use crate::time::from_civil;
+
+/// Host names of the certificates that sign attested documents, per cloud.
+const ATTESTED_HOSTS: &[&str] = &[
+    "metadata.azure.com",
+    "metadata.azure.us",
+    "metadata.azure.cn",
+    "metadata.microsoftazure.de",
+];
+
+/// Verified content of an attested data document.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AzureAttestedData {
+    /// The nonce the document was requested with.
+    pub nonce: String,
+    /// The VM id.
+    pub vm_id: String,
+    /// The subscription id.
+    pub subscription_id: String,
+    /// The marketplace image SKU.
+    pub sku: String,
+    /// When the document was created.
+    pub created_on: SystemTime,
+    /// When the document expires.
+    pub expires_on: SystemTime,
+}
+
+/// Signed content of an attested data document.
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Attested {
+    nonce: String,
+    #[serde(default)]
+    vm_id: String,
+    #[serde(default)]
+    subscription_id: String,
+    #[serde(default)]
+    sku: String,
+    time_stamp: TimeStamp,
+}
+
+/// Validity period of an attested data document.
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct TimeStamp {
+    created_on: String,
+    expires_on: String,
+}
+
+/// Verify an attested data document and return its content.
+///
+/// `signature` is the `signature` field of the document. The signing
+/// certificate must be issued for `metadata.azure.com` (or the equivalent
+/// host of a sovereign cloud) and chain to one of the PEM-encoded `roots`.
+/// Intermediate certificates not embedded in the document can be passed in
+/// `intermediates`. The document must carry `nonce` and must not be expired.
+///
+/// # Errors
+///
+/// Returns `MetadataError::Verification` if the signature, certificate chain,
+/// nonce or expiry check fails.
+pub fn azure_attested_document(
+    signature: &str,
+    nonce: &str,
+    roots: &[&str],
+    intermediates: &[&str],
+) -> Result<AzureAttestedData, MetadataError> {
+    let now = SystemTime::now();
+    let roots = parse_certificates(roots)?;
+    let signed = Signed::parse(&decode_base64(signature)?)?;
+    let mut chain = signed.certificates.clone();
+    chain.extend(parse_certificates(intermediates)?);
+
+    let signer = signed
+        .certificates
+        .iter()
+        .filter(|cert| common_name(cert).is_some_and(|cn| is_attested_host(&cn)))
+        .find(|cert| signed.verify(cert).is_ok())
+        .ok_or_else(|| fail("document is not signed by an Azure metadata certificate"))?;
+    verify_chain(signer, &chain, &roots, now)?;
+
+    let attested: Attested = serde_json::from_slice(&signed.content)?;
+    if attested.nonce != nonce {
+        return Err(fail("nonce mismatch"));
+    }
+    let created_on = parse_timestamp(&attested.time_stamp.created_on)?;
+    let expires_on = parse_timestamp(&attested.time_stamp.expires_on)?;
+    if now > expires_on {
+        return Err(fail("document expired"));
+    }
+
+    Ok(AzureAttestedData {
+        nonce: attested.nonce,
+        vm_id: attested.vm_id,
+        subscription_id: attested.subscription_id,
+        sku: attested.sku,
+        created_on,
+        expires_on,
+    })
+}
+
+/// Whether `name` is an attested document signing host or a subdomain of one.
+fn is_attested_host(name: &str) -> bool {
+    ATTESTED_HOSTS.iter().any(|host| {
+        name == *host
+            || name
+                .strip_suffix(host)
+                .is_some_and(|prefix| prefix.ends_with('.'))
+    })
+}
+
+/// Parse an attested document timestamp such as `11/28/18 00:16:17 -0000`.
+fn parse_timestamp(value: &str) -> Result<SystemTime, MetadataError> {
+    let invalid = || fail(format!("invalid timestamp: {}", value));
+    let mut parts = value.split_whitespace();
+    let (date, time, offset) = (
+        parts.next().ok_or_else(invalid)?,
+        parts.next().ok_or_else(invalid)?,
+        parts.next().unwrap_or("-0000"),
+    );
+
+    let mut date = date.splitn(3, '/').map(str::parse::<i64>);
+    let mut next_date = || date.next().and_then(Result::ok).ok_or_else(invalid);
+    let (month, day, year) = (next_date()?, next_date()?, next_date()?);
+    let mut time = time.splitn(3, ':').map(str::parse::<u64>);
+    let mut next_time = || time.next().and_then(Result::ok).ok_or_else(invalid);
+    let (hour, minute, second) = (next_time()?, next_time()?, next_time()?);
+
+    let local = from_civil(2000 + year, month, day, hour, minute, second).ok_or_else(invalid)?;
+
+    // The offset is `+HHMM` or `-HHMM`; IMDS sends `-0000`
+    let duration = |digits: &str| {
+        let digits: u64 = digits.parse().map_err(|_| invalid())?;
+        Ok::<_, MetadataError>(Duration::from_secs(
+            (digits / 100) * 3_600 + (digits % 100) * 60,
+        ))
+    };
+    if let Some(digits) = offset.strip_prefix('+') {
+        Ok(local - duration(digits)?)
+    } else if let Some(digits) = offset.strip_prefix('-') {
+        Ok(local + duration(digits)?)
+    } else {
+        Err(invalid())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ROOT: &str = include_str!("../../tests/fixtures/verify/azure-root.pem");
+    const INTERMEDIATE: &str = include_str!("../../tests/fixtures/verify/azure-intermediate.pem");
+    const DOCUMENT: &str = include_str!("../../tests/fixtures/verify/azure-attested");
+    const DOCUMENT_CHAIN: &str = include_str!("../../tests/fixtures/verify/azure-attested-chain");
+    const DOCUMENT_EXPIRED: &str =
+        include_str!("../../tests/fixtures/verify/azure-attested-expired");
+    const DOCUMENT_WRONG_SUBJECT: &str =
+        include_str!("../../tests/fixtures/verify/azure-attested-wrong-subject");
+    const OTHER_CERT: &str = include_str!("../../tests/fixtures/verify/other-cert.pem");
+
+    #[test]
+    fn test_verify_embedded_chain() {
+        let data = azure_attested_document(DOCUMENT_CHAIN, "1234567890", &[ROOT], &[]).unwrap();
+        assert_eq!(data.vm_id, "d3e0e374-fda6-4649-bbc9-7f20dcd2f213");
+        assert_eq!(data.subscription_id, "8d10da13-8125-4ba9-a717-bf7490507b3d");
+        assert_eq!(data.sku, "22_04-lts-gen2");
+        assert_eq!(
+            data.created_on,
+            SystemTime::UNIX_EPOCH + Duration::from_secs(1_714_564_800)
+        );
+    }
+
+    #[test]
+    fn test_verify_supplied_intermediate() {
+        let result = azure_attested_document(DOCUMENT, "1234567890", &[ROOT], &[]);
+        assert!(matches!(result, Err(MetadataError::Verification(_))));
+
+        let data =
+            azure_attested_document(DOCUMENT, "1234567890", &[ROOT], &[INTERMEDIATE]).unwrap();
+        assert_eq!(data.nonce, "1234567890");
+    }
+
+    #[test]
+    fn test_untrusted_root() {
+        let result = azure_attested_document(DOCUMENT_CHAIN, "1234567890", &[OTHER_CERT], &[]);
+        assert!(matches!(result, Err(MetadataError::Verification(_))));
+    }
+
+    #[test]
+    fn test_nonce_mismatch() {
+        let result = azure_attested_document(DOCUMENT_CHAIN, "0987654321", &[ROOT], &[]);
+        assert!(matches!(result, Err(MetadataError::Verification(msg)) if msg == "nonce mismatch"));
+    }
+
+    #[test]
+    fn test_expired() {
+        let result = azure_attested_document(DOCUMENT_EXPIRED, "1234567890", &[ROOT], &[]);
+        assert!(
+            matches!(result, Err(MetadataError::Verification(msg)) if msg == "document expired")
+        );
+    }
+
+    #[test]
+    fn test_wrong_subject() {
+        let result = azure_attested_document(DOCUMENT_WRONG_SUBJECT, "1234567890", &[ROOT], &[]);
+        assert!(matches!(result, Err(MetadataError::Verification(_))));
+    }
+
+    #[test]
+    fn test_is_attested_host() {
+        assert!(is_attested_host("metadata.azure.com"));
+        assert!(is_attested_host("eastus.metadata.azure.com"));
+        assert!(!is_attested_host("evilmetadata.azure.com"));
+        assert!(!is_attested_host("metadata.azure.com.evil"));
+    }
+
+    #[test]
+    fn test_parse_timestamp() {
+        assert_eq!(
+            parse_timestamp("05/01/24 12:00:00 -0000").unwrap(),
+            SystemTime::UNIX_EPOCH + Duration::from_secs(1_714_564_800)
+        );
+        assert_eq!(
+            parse_timestamp("05/01/24 14:00:00 +0200").unwrap(),
+            SystemTime::UNIX_EPOCH + Duration::from_secs(1_714_564_800)
+        );
+        assert!(parse_timestamp("bogus").is_err());
+    }
+}
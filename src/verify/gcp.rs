@@ -0,0 +1,262 @@
+//! GCP instance identity token verification.
+
+use std::time::{Duration, SystemTime};
+
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use rsa::{BigUint, RsaPublicKey};
+use serde::Deserialize;
+
+use super::{fail, verify_rsa, Hash};
+use crate::error::MetadataError;
+
+/// Issuers of Google-signed identity tokens.
+const ISSUERS: &[&str] = &["https://accounts.google.com", "accounts.google.com"];
+
+/// Verified claims of a GCP identity token.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GcpIdentityClaims {
+    /// The token issuer.
+    pub issuer: String,
+    /// The audience the token was issued for.
+    pub audience: String,
+    /// The subject, the unique id of the service account.
+    pub subject: String,
+    /// The service account email.
+    pub email: Option<String>,
+    /// When the token was issued.
+    pub issued_at: SystemTime,
+    /// When the token expires.
+    pub expires_at: SystemTime,
+    /// Instance claims, present for tokens requested with `format=full`.
+    pub compute_engine: Option<GcpComputeEngineClaims>,
+}
+
+/// The `google.compute_engine` claims of a `format=full` identity token.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(default)]
+pub struct GcpComputeEngineClaims {
+    /// The instance id.
+    pub instance_id: String,
+    /// The instance name.
+    pub instance_name: String,
+    /// The zone of the instance.
+    pub zone: String,
+    /// The project id.
+    pub project_id: String,
+    /// The project number.
+    pub project_number: u64,
+    /// Instance creation time in seconds since the epoch.
+    pub instance_creation_timestamp: u64,
+    /// License codes of the instance image, requested with `licenses=TRUE`.
+    pub license_id: Vec<String>,
+}
+
+/// JOSE header.
+#[derive(Deserialize)]
+struct Header {
+    alg: String,
+    #[serde(default)]
+    kid: Option<String>,
+}
+
+/// Token claims.
+#[derive(Deserialize)]
+struct Claims {
+    iss: String,
+    aud: String,
+    #[serde(default)]
+    sub: String,
+    #[serde(default)]
+    email: Option<String>,
+    #[serde(default)]
+    iat: u64,
+    exp: u64,
+    #[serde(default)]
+    google: Option<Google>,
+}
+
+/// The `google` claim.
+#[derive(Deserialize)]
+struct Google {
+    #[serde(default)]
+    compute_engine: Option<GcpComputeEngineClaims>,
+}
+
+/// JSON Web Key Set.
+#[derive(Deserialize)]
+struct Jwks {
+    keys: Vec<Jwk>,
+}
+
+/// An RSA JSON Web Key.
+#[derive(Deserialize)]
+struct Jwk {
+    kty: String,
+    #[serde(default)]
+    kid: Option<String>,
+    #[serde(default)]
+    n: String,
+    #[serde(default)]
+    e: String,
+}
+
+impl Jwk {
+    fn public_key(&self) -> Result<RsaPublicKey, MetadataError> {
+        let n = URL_SAFE_NO_PAD
+            .decode(&self.n)
+            .map_err(|_| MetadataError::Base64)?;
+        let e = URL_SAFE_NO_PAD
+            .decode(&self.e)
+            .map_err(|_| MetadataError::Base64)?;
+        RsaPublicKey::new(BigUint::from_bytes_be(&n), BigUint::from_bytes_be(&e))
+            .map_err(|e| fail(format!("invalid key: {}", e)))
+    }
+}
+
+/// Verify a GCP identity token and return its claims.
+///
+/// The RS256 signature is checked against the keys of `jwks`, a JSON Web Key
+/// Set such as the one served at `https://www.googleapis.com/oauth2/v3/certs`.
+/// The token must be issued by Google for `audience` and must not be expired.
+///
+/// # Errors
+///
+/// Returns `MetadataError::Verification` if the signature, issuer, audience
+/// or expiry check fails.
+pub fn gcp_identity_token(
+    token: &str,
+    jwks: &str,
+    audience: &str,
+) -> Result<GcpIdentityClaims, MetadataError> {
+    // The signature covers the encoded header and payload
+    let (signed, signature) = token
+        .trim()
+        .rsplit_once('.')
+        .ok_or_else(|| fail("malformed token"))?;
+    let (header, payload) = signed
+        .split_once('.')
+        .filter(|(_, payload)| !payload.contains('.'))
+        .ok_or_else(|| fail("malformed token"))?;
+    let decode = |part: &str| {
+        URL_SAFE_NO_PAD
+            .decode(part)
+            .map_err(|_| MetadataError::Base64)
+    };
+
+    let header: Header = serde_json::from_slice(&decode(header)?)?;
+    if header.alg != "RS256" {
+        return Err(fail(format!("unsupported algorithm {}", header.alg)));
+    }
+
+    let signature = decode(signature)?;
+    let jwks: Jwks = serde_json::from_str(jwks)?;
+    let verified = jwks
+        .keys
+        .iter()
+        .filter(|key| key.kty == "RSA")
+        .filter(|key| header.kid.is_none() || key.kid == header.kid)
+        .filter_map(|key| key.public_key().ok())
+        .any(|key| verify_rsa(&key, Hash::Sha256, signed.as_bytes(), &signature).is_ok());
+    if !verified {
+        return Err(fail("signature does not match any supplied key"));
+    }
+
+    let claims: Claims = serde_json::from_slice(&decode(payload)?)?;
+    if !ISSUERS.contains(&claims.iss.as_str()) {
+        return Err(fail(format!("unexpected issuer {}", claims.iss)));
+    }
+    if claims.aud != audience {
+        return Err(fail("audience mismatch"));
+    }
+    let expires_at = SystemTime::UNIX_EPOCH + Duration::from_secs(claims.exp);
+    if SystemTime::now() > expires_at {
+        return Err(fail("token expired"));
+    }
+
+    Ok(GcpIdentityClaims {
+        issuer: claims.iss,
+        audience: claims.aud,
+        subject: claims.sub,
+        email: claims.email,
+        issued_at: SystemTime::UNIX_EPOCH + Duration::from_secs(claims.iat),
+        expires_at,
+        compute_engine: claims.google.and_then(|g| g.compute_engine),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const JWKS: &str = include_str!("../../tests/fixtures/verify/gcp-jwks.json");
+    const TOKEN: &str = include_str!("../../tests/fixtures/verify/gcp-identity-token");
+    const TOKEN_EXPIRED: &str =
+        include_str!("../../tests/fixtures/verify/gcp-identity-token-expired");
+    const AUDIENCE: &str = "https://svc.example.com";
+
+    #[test]
+    fn test_verify() {
+        let claims = gcp_identity_token(TOKEN, JWKS, AUDIENCE).unwrap();
+        assert_eq!(claims.issuer, "https://accounts.google.com");
+        assert_eq!(
+            claims.email.as_deref(),
+            Some("123-compute@developer.gserviceaccount.com")
+        );
+        assert_eq!(
+            claims.expires_at,
+            SystemTime::UNIX_EPOCH + Duration::from_secs(4_102_444_800)
+        );
+
+        let instance = claims.compute_engine.unwrap();
+        assert_eq!(instance.instance_id, "5678901234567890");
+        assert_eq!(instance.instance_name, "worker-1");
+        assert_eq!(instance.zone, "us-central1-a");
+        assert_eq!(instance.project_id, "my-project");
+        assert_eq!(instance.project_number, 123_456_789_012);
+    }
+
+    #[test]
+    fn test_audience_mismatch() {
+        let result = gcp_identity_token(TOKEN, JWKS, "https://other.example.com");
+        assert!(
+            matches!(result, Err(MetadataError::Verification(msg)) if msg == "audience mismatch")
+        );
+    }
+
+    #[test]
+    fn test_expired() {
+        let result = gcp_identity_token(TOKEN_EXPIRED, JWKS, AUDIENCE);
+        assert!(matches!(result, Err(MetadataError::Verification(msg)) if msg == "token expired"));
+    }
+
+    #[test]
+    fn test_unknown_key() {
+        let jwks = r#"{"keys": [{"kty": "RSA", "kid": "test-key-1", "n": "w8PD", "e": "AQAB"}]}"#;
+        let result = gcp_identity_token(TOKEN, jwks, AUDIENCE);
+        assert!(matches!(result, Err(MetadataError::Verification(_))));
+    }
+
+    #[test]
+    fn test_tampered_payload() {
+        let parts: Vec<&str> = TOKEN.split('.').collect();
+        let mut claims: serde_json::Value =
+            serde_json::from_slice(&URL_SAFE_NO_PAD.decode(parts[1]).unwrap()).unwrap();
+        claims["google"]["compute_engine"]["project_id"] = "other-project".into();
+        let payload = URL_SAFE_NO_PAD.encode(claims.to_string());
+        let tampered = format!("{}.{}.{}", parts[0], payload, parts[2]);
+
+        let result = gcp_identity_token(&tampered, JWKS, AUDIENCE);
+        assert!(matches!(result, Err(MetadataError::Verification(_))));
+    }
+
+    #[test]
+    fn test_rejects_other_algorithms() {
+        let header = URL_SAFE_NO_PAD.encode(r#"{"alg":"none"}"#);
+        let payload = TOKEN.split('.').nth(1).unwrap();
+        let token = format!("{}.{}.", header, payload);
+
+        let result = gcp_identity_token(&token, JWKS, AUDIENCE);
+        assert!(matches!(result, Err(MetadataError::Verification(_))));
+    }
+}
@@ -0,0 +1,135 @@
+//! Offline verification of signed identity documents.
+//!
+//! Requires the `verify` feature. Verification uses only the supplied
+//! certificates and never contacts the network, so a receiving service can
+//! check documents presented by other instances.
+
+mod aws;
+mod azure;
+mod gcp;
+mod pkcs7;
+mod x509;
+
+pub use aws::aws_identity_document;
+pub use azure::{azure_attested_document, AzureAttestedData};
+pub use gcp::{gcp_identity_token, GcpComputeEngineClaims, GcpIdentityClaims};
+
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use der::asn1::ObjectIdentifier;
+use der::{Decode, Encode};
+use rsa::pkcs8::DecodePublicKey;
+use rsa::{Pkcs1v15Sign, RsaPublicKey};
+use sha2::{Digest, Sha256, Sha384, Sha512};
+use x509_cert::spki::SubjectPublicKeyInfoOwned;
+use x509_cert::Certificate;
+
+use crate::error::MetadataError;
+
+/// Shorthand for a verification failure.
+pub(crate) fn fail(reason: impl Into<String>) -> MetadataError {
+    MetadataError::Verification(reason.into())
+}
+
+/// Hash algorithms accepted for RSA PKCS#1 v1.5 signatures.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Hash {
+    Sha256,
+    Sha384,
+    Sha512,
+}
+
+impl Hash {
+    /// Map a digest algorithm OID to a hash.
+    pub(crate) fn from_digest_oid(oid: &ObjectIdentifier) -> Result<Self, MetadataError> {
+        match oid.to_string().as_str() {
+            "2.16.840.1.101.3.4.2.1" => Ok(Hash::Sha256),
+            "2.16.840.1.101.3.4.2.2" => Ok(Hash::Sha384),
+            "2.16.840.1.101.3.4.2.3" => Ok(Hash::Sha512),
+            other => Err(fail(format!("unsupported digest algorithm {}", other))),
+        }
+    }
+
+    /// Compute the digest of `data`.
+    pub(crate) fn digest(self, data: &[u8]) -> Vec<u8> {
+        match self {
+            Hash::Sha256 => Sha256::digest(data).to_vec(),
+            Hash::Sha384 => Sha384::digest(data).to_vec(),
+            Hash::Sha512 => Sha512::digest(data).to_vec(),
+        }
+    }
+
+    fn scheme(self) -> Pkcs1v15Sign {
+        match self {
+            Hash::Sha256 => Pkcs1v15Sign::new::<Sha256>(),
+            Hash::Sha384 => Pkcs1v15Sign::new::<Sha384>(),
+            Hash::Sha512 => Pkcs1v15Sign::new::<Sha512>(),
+        }
+    }
+}
+
+/// Verify an RSA PKCS#1 v1.5 signature over `message`.
+pub(crate) fn verify_rsa(
+    key: &RsaPublicKey,
+    hash: Hash,
+    message: &[u8],
+    signature: &[u8],
+) -> Result<(), MetadataError> {
+    key.verify(hash.scheme(), &hash.digest(message), signature)
+        .map_err(|_| fail("signature mismatch"))
+}
+
+/// Extract the RSA public key of a certificate.
+pub(crate) fn rsa_key(spki: &SubjectPublicKeyInfoOwned) -> Result<RsaPublicKey, MetadataError> {
+    let der = spki.to_der().map_err(|e| fail(e.to_string()))?;
+    RsaPublicKey::from_public_key_der(&der).map_err(|_| fail("certificate key is not RSA"))
+}
+
+/// Parse PEM certificates. Each entry may hold several certificates.
+pub(crate) fn parse_certificates(pems: &[&str]) -> Result<Vec<Certificate>, MetadataError> {
+    let mut certificates = Vec::new();
+    for pem in pems {
+        let chain = Certificate::load_pem_chain(pem.as_bytes())
+            .map_err(|e| fail(format!("invalid certificate: {}", e)))?;
+        certificates.extend(chain);
+    }
+    Ok(certificates)
+}
+
+/// Decode base64 that may be wrapped in PEM armor or split over lines.
+pub(crate) fn decode_base64(text: &str) -> Result<Vec<u8>, MetadataError> {
+    let body: String = text
+        .lines()
+        .filter(|line| !line.starts_with("-----"))
+        .flat_map(|line| line.chars().filter(|c| !c.is_whitespace()))
+        .collect();
+    STANDARD.decode(body).map_err(|_| MetadataError::Base64)
+}
+
+/// Parse a DER value, mapping errors to verification failures.
+pub(crate) fn from_der<'a, T: Decode<'a>>(bytes: &'a [u8]) -> Result<T, MetadataError> {
+    T::from_der(bytes).map_err(|e| fail(e.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decode_base64() {
+        assert_eq!(decode_base64("aGVs\nbG8=\n").unwrap(), b"hello");
+        assert_eq!(
+            decode_base64("-----BEGIN PKCS7-----\naGVsbG8=\n-----END PKCS7-----\n").unwrap(),
+            b"hello"
+        );
+        assert!(decode_base64("!!").is_err());
+    }
+
+    #[test]
+    fn test_hash_from_digest_oid() {
+        let oid = ObjectIdentifier::new_unwrap("2.16.840.1.101.3.4.2.1");
+        assert_eq!(Hash::from_digest_oid(&oid).unwrap(), Hash::Sha256);
+        let oid = ObjectIdentifier::new_unwrap("1.3.14.3.2.26");
+        assert!(Hash::from_digest_oid(&oid).is_err());
+    }
+}
@@ -0,0 +1,149 @@
+//! Minimal PKCS#7 / CMS SignedData verification.
+
+use cms::cert::CertificateChoices;
+use cms::content_info::ContentInfo;
+use cms::signed_data::{SignedData, SignerIdentifier, SignerInfo};
+use der::asn1::{ObjectIdentifier, OctetString};
+use der::Encode;
+use x509_cert::ext::pkix::SubjectKeyIdentifier;
+use x509_cert::Certificate;
+
+use super::{fail, from_der, rsa_key, verify_rsa, Hash};
+use crate::error::MetadataError;
+
+/// `id-signedData` content type.
+const SIGNED_DATA: ObjectIdentifier = ObjectIdentifier::new_unwrap("1.2.840.113549.1.7.2");
+
+/// `id-messageDigest` signed attribute.
+const MESSAGE_DIGEST: ObjectIdentifier = ObjectIdentifier::new_unwrap("1.2.840.113549.1.9.4");
+
+/// A parsed SignedData structure with attached content.
+pub(crate) struct Signed {
+    /// The signed content.
+    pub content: Vec<u8>,
+    /// Certificates embedded in the structure.
+    pub certificates: Vec<Certificate>,
+    signers: Vec<SignerInfo>,
+}
+
+impl Signed {
+    /// Parse a DER-encoded ContentInfo holding SignedData.
+    pub fn parse(der: &[u8]) -> Result<Self, MetadataError> {
+        let info: ContentInfo = from_der(der)?;
+        if info.content_type != SIGNED_DATA {
+            return Err(fail("not a PKCS7 SignedData structure"));
+        }
+        let data: SignedData = info.content.decode_as().map_err(|e| fail(e.to_string()))?;
+
+        let content = data
+            .encap_content_info
+            .econtent
+            .ok_or_else(|| fail("signed content is detached"))?;
+        let content: OctetString = content.decode_as().map_err(|e| fail(e.to_string()))?;
+
+        let certificates = data
+            .certificates
+            .map(|set| {
+                set.0
+                    .into_vec()
+                    .into_iter()
+                    .filter_map(|choice| match choice {
+                        CertificateChoices::Certificate(cert) => Some(cert),
+                        _ => None,
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        Ok(Self {
+            content: content.into_bytes(),
+            certificates,
+            signers: data.signer_infos.0.into_vec(),
+        })
+    }
+
+    /// Check that a signer naming `certificate` signed the content with its key.
+    pub fn verify(&self, certificate: &Certificate) -> Result<(), MetadataError> {
+        let key = rsa_key(&certificate.tbs_certificate.subject_public_key_info)?;
+        let mut result = Err(fail("no signer for the certificate"));
+        for signer in self
+            .signers
+            .iter()
+            .filter(|signer| identifies(signer, certificate))
+        {
+            result = self.verify_signer(signer, &key);
+            if result.is_ok() {
+                break;
+            }
+        }
+        result
+    }
+
+    fn verify_signer(
+        &self,
+        signer: &SignerInfo,
+        key: &rsa::RsaPublicKey,
+    ) -> Result<(), MetadataError> {
+        let hash = Hash::from_digest_oid(&signer.digest_alg.oid)?;
+        let signature = signer.signature.as_bytes();
+
+        let Some(attributes) = &signer.signed_attrs else {
+            return verify_rsa(key, hash, &self.content, signature);
+        };
+
+        // With signed attributes, the signature covers their DER encoding and
+        // the content is bound through the message digest attribute.
+        let digest = attributes
+            .iter()
+            .find(|attr| attr.oid == MESSAGE_DIGEST)
+            .and_then(|attr| attr.values.get(0))
+            .ok_or_else(|| fail("missing message digest"))?;
+        let digest: OctetString = digest.decode_as().map_err(|e| fail(e.to_string()))?;
+        if digest.as_bytes() != hash.digest(&self.content) {
+            return Err(fail("message digest mismatch"));
+        }
+
+        let signed = attributes.to_der().map_err(|e| fail(e.to_string()))?;
+        verify_rsa(key, hash, &signed, signature)
+    }
+}
+
+/// Whether `signer` names `certificate` as its signing certificate, by issuer
+/// and serial number or by subject key identifier.
+fn identifies(signer: &SignerInfo, certificate: &Certificate) -> bool {
+    let tbs = &certificate.tbs_certificate;
+    match &signer.sid {
+        SignerIdentifier::IssuerAndSerialNumber(id) => {
+            id.issuer == tbs.issuer && id.serial_number == tbs.serial_number
+        }
+        SignerIdentifier::SubjectKeyIdentifier(ski) => {
+            matches!(tbs.get::<SubjectKeyIdentifier>(), Ok(Some((_, own))) if own == *ski)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use x509_cert::serial_number::SerialNumber;
+
+    use crate::verify::{decode_base64, parse_certificates};
+
+    const CERT: &str = include_str!("../../tests/fixtures/verify/aws-cert.pem");
+    const SIGNATURE: &str = include_str!("../../tests/fixtures/verify/aws-rsa2048");
+
+    #[test]
+    fn test_signer_must_name_certificate() {
+        let signed = Signed::parse(&decode_base64(SIGNATURE).unwrap()).unwrap();
+        let mut cert = parse_certificates(&[CERT]).unwrap().remove(0);
+        assert!(signed.verify(&cert).is_ok());
+
+        // Same key, but not the certificate the signer names
+        cert.tbs_certificate.serial_number = SerialNumber::new(&[0x42]).unwrap();
+        assert!(matches!(
+            signed.verify(&cert),
+            Err(MetadataError::Verification(_))
+        ));
+    }
+}
@@ -0,0 +1,107 @@
+//! Certificate chain validation.
+
+use std::time::SystemTime;
+
+use der::asn1::ObjectIdentifier;
+use der::Encode;
+use x509_cert::ext::pkix::BasicConstraints;
+use x509_cert::Certificate;
+
+use super::{fail, from_der, rsa_key, verify_rsa, Hash};
+use crate::error::MetadataError;
+
+/// Longest chain accepted between the leaf and a trusted root.
+const MAX_CHAIN_DEPTH: usize = 8;
+
+/// `id-ce-basicConstraints` extension.
+const BASIC_CONSTRAINTS: ObjectIdentifier = ObjectIdentifier::new_unwrap("2.5.29.19");
+
+/// `id-at-commonName` attribute.
+const COMMON_NAME: ObjectIdentifier = ObjectIdentifier::new_unwrap("2.5.4.3");
+
+/// Validate that `leaf` chains to one of `roots` through `intermediates`.
+///
+/// Every certificate on the path must be valid at `now`, and intermediates
+/// must be CA certificates. Roots are trusted as given.
+pub(crate) fn verify_chain(
+    leaf: &Certificate,
+    intermediates: &[Certificate],
+    roots: &[Certificate],
+    now: SystemTime,
+) -> Result<(), MetadataError> {
+    let mut current = leaf;
+    for _ in 0..MAX_CHAIN_DEPTH {
+        check_validity(current, now)?;
+        if roots.contains(current) {
+            return Ok(());
+        }
+        if let Some(root) = roots.iter().find(|root| issued_by(current, root)) {
+            return check_validity(root, now);
+        }
+        current = intermediates
+            .iter()
+            .find(|cert| is_ca(cert) && issued_by(current, cert))
+            .ok_or_else(|| fail("certificate chain does not lead to a trusted root"))?;
+    }
+    Err(fail("certificate chain too long"))
+}
+
+/// The common name of the certificate subject.
+pub(crate) fn common_name(cert: &Certificate) -> Option<String> {
+    cert.tbs_certificate
+        .subject
+        .0
+        .iter()
+        .flat_map(|rdn| rdn.0.iter())
+        .find(|atv| atv.oid == COMMON_NAME)
+        .and_then(|atv| String::from_utf8(atv.value.value().to_vec()).ok())
+}
+
+/// Check the validity period of a certificate.
+fn check_validity(cert: &Certificate, now: SystemTime) -> Result<(), MetadataError> {
+    let validity = &cert.tbs_certificate.validity;
+    if now < validity.not_before.to_system_time() || now > validity.not_after.to_system_time() {
+        return Err(fail(format!(
+            "certificate {} is not valid at this time",
+            cert.tbs_certificate.subject
+        )));
+    }
+    Ok(())
+}
+
+/// Whether `issuer` issued and signed `cert`.
+fn issued_by(cert: &Certificate, issuer: &Certificate) -> bool {
+    cert.tbs_certificate.issuer == issuer.tbs_certificate.subject
+        && verify_signature(cert, issuer).is_ok()
+}
+
+/// Verify the signature of `cert` with the key of `issuer`.
+fn verify_signature(cert: &Certificate, issuer: &Certificate) -> Result<(), MetadataError> {
+    let hash = match cert.signature_algorithm.oid.to_string().as_str() {
+        "1.2.840.113549.1.1.11" => Hash::Sha256,
+        "1.2.840.113549.1.1.12" => Hash::Sha384,
+        "1.2.840.113549.1.1.13" => Hash::Sha512,
+        other => return Err(fail(format!("unsupported signature algorithm {}", other))),
+    };
+    let key = rsa_key(&issuer.tbs_certificate.subject_public_key_info)?;
+    let tbs = cert
+        .tbs_certificate
+        .to_der()
+        .map_err(|e| fail(e.to_string()))?;
+    let signature = cert
+        .signature
+        .as_bytes()
+        .ok_or_else(|| fail("malformed certificate signature"))?;
+    verify_rsa(&key, hash, &tbs, signature)
+}
+
+/// Whether the certificate is marked as a CA in its basic constraints.
+fn is_ca(cert: &Certificate) -> bool {
+    cert.tbs_certificate
+        .extensions
+        .iter()
+        .flatten()
+        .find(|ext| ext.extn_id == BASIC_CONSTRAINTS)
+        .and_then(|ext| from_der::<BasicConstraints>(ext.extn_value.as_bytes()).ok())
+        .is_some_and(|constraints| constraints.ca)
+}
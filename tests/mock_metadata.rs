@@ -6,7 +6,7 @@ use serde::Deserialize;
 use wiremock::matchers::{header, method, path, query_param};
 use wiremock::{Mock, MockServer, ResponseTemplate};
 
-use cloud_metadata::{CloudMetadata, CloudProvider, MetadataError};
+use cloud_metadata::{CloudMetadata, CloudProvider, GcpAttributeScope, MetadataError};
 
 /// Test configuration struct for JSON deserialization tests.
 #[derive(Debug, Deserialize, PartialEq)]
@@ -15,6 +15,26 @@ struct TestConfig {
     port: u16,
 }
 
+/// Mock the AWS IMDSv2 token endpoint.
+async fn setup_aws_token(server: &MockServer) {
+    Mock::given(method("PUT"))
+        .and(path("/latest/api/token"))
+        .respond_with(ResponseTemplate::new(200).set_body_string("mock-token"))
+        .mount(server)
+        .await;
+}
+
+/// Mock the AWS token and user-data endpoints.
+async fn setup_aws_user_data(server: &MockServer, user_data: impl Into<Vec<u8>>) {
+    setup_aws_token(server).await;
+    Mock::given(method("GET"))
+        .and(path("/latest/user-data"))
+        .and(header("X-aws-ec2-metadata-token", "mock-token"))
+        .respond_with(ResponseTemplate::new(200).set_body_bytes(user_data))
+        .mount(server)
+        .await;
+}
+
 // =============================================================================
 // AWS Tests
 // =============================================================================
@@ -22,29 +42,13 @@ struct TestConfig {
 mod aws {
     use super::*;
 
-    async fn setup_aws_mock(server: &MockServer, user_data: &str) {
-        // Mock the token endpoint
-        Mock::given(method("PUT"))
-            .and(path("/latest/api/token"))
-            .and(header("X-aws-ec2-metadata-token-ttl-seconds", "60"))
-            .respond_with(ResponseTemplate::new(200).set_body_string("mock-token"))
-            .mount(server)
-            .await;
-
-        // Mock the user-data endpoint
-        Mock::given(method("GET"))
-            .and(path("/latest/user-data"))
-            .and(header("X-aws-ec2-metadata-token", "mock-token"))
-            .respond_with(ResponseTemplate::new(200).set_body_string(user_data))
-            .mount(server)
-            .await;
-    }
+    use std::time::Duration;
 
     #[tokio::test]
     async fn test_aws_fetch_user_data() {
         let server = MockServer::start().await;
         let user_data = r#"{"db_host": "postgres.internal", "port": 5432}"#;
-        setup_aws_mock(&server, user_data).await;
+        setup_aws_user_data(&server, user_data).await;
 
         let metadata = CloudMetadata::aws_with_base_url(&server.uri());
         let data = metadata.custom_data("ignored").await.unwrap();
@@ -56,7 +60,7 @@ mod aws {
     async fn test_aws_fetch_user_data_as_text() {
         let server = MockServer::start().await;
         let user_data = "Hello, AWS!";
-        setup_aws_mock(&server, user_data).await;
+        setup_aws_user_data(&server, user_data).await;
 
         let metadata = CloudMetadata::aws_with_base_url(&server.uri());
         let text = metadata.custom_text("ignored").await.unwrap();
@@ -68,7 +72,7 @@ mod aws {
     async fn test_aws_fetch_user_data_as_json() {
         let server = MockServer::start().await;
         let user_data = r#"{"db_host": "postgres.internal", "port": 5432}"#;
-        setup_aws_mock(&server, user_data).await;
+        setup_aws_user_data(&server, user_data).await;
 
         let metadata = CloudMetadata::aws_with_base_url(&server.uri());
         let config: TestConfig = metadata.custom_json("ignored").await.unwrap();
@@ -87,23 +91,192 @@ mod aws {
         let server = MockServer::start().await;
 
         // Mock the token endpoint
+        setup_aws_token(&server).await;
+
+        // Mock 404 for user-data
+        Mock::given(method("GET"))
+            .and(path("/latest/user-data"))
+            .respond_with(ResponseTemplate::new(404))
+            .mount(&server)
+            .await;
+
+        let metadata = CloudMetadata::aws_with_base_url(&server.uri());
+        let result = metadata.custom_data("ignored").await;
+
+        assert!(matches!(result, Err(MetadataError::NotFound)));
+    }
+
+    #[tokio::test]
+    async fn test_aws_token_reused() {
+        let server = MockServer::start().await;
         Mock::given(method("PUT"))
             .and(path("/latest/api/token"))
             .respond_with(ResponseTemplate::new(200).set_body_string("mock-token"))
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/latest/user-data"))
+            .and(header("X-aws-ec2-metadata-token", "mock-token"))
+            .respond_with(ResponseTemplate::new(200).set_body_string("data"))
+            .expect(3)
             .mount(&server)
             .await;
 
-        // Mock 404 for user-data
+        let metadata = CloudMetadata::aws_with_base_url(&server.uri());
+        for _ in 0..3 {
+            assert_eq!(metadata.custom_text("ignored").await.unwrap(), "data");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_aws_token_ttl() {
+        let server = MockServer::start().await;
+        Mock::given(method("PUT"))
+            .and(path("/latest/api/token"))
+            .and(header("X-aws-ec2-metadata-token-ttl-seconds", "300"))
+            .respond_with(ResponseTemplate::new(200).set_body_string("mock-token"))
+            .expect(1)
+            .mount(&server)
+            .await;
         Mock::given(method("GET"))
             .and(path("/latest/user-data"))
-            .respond_with(ResponseTemplate::new(404))
+            .respond_with(ResponseTemplate::new(200).set_body_string("data"))
+            .mount(&server)
+            .await;
+
+        let metadata = CloudMetadata::aws_with_base_url(&server.uri())
+            .with_aws_token_ttl(Duration::from_secs(300));
+        assert_eq!(metadata.custom_text("ignored").await.unwrap(), "data");
+    }
+
+    #[tokio::test]
+    async fn test_aws_token_reacquired_on_401() {
+        let server = MockServer::start().await;
+        Mock::given(method("PUT"))
+            .and(path("/latest/api/token"))
+            .respond_with(ResponseTemplate::new(200).set_body_string("stale-token"))
+            .up_to_n_times(1)
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("PUT"))
+            .and(path("/latest/api/token"))
+            .respond_with(ResponseTemplate::new(200).set_body_string("fresh-token"))
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/latest/user-data"))
+            .and(header("X-aws-ec2-metadata-token", "stale-token"))
+            .respond_with(ResponseTemplate::new(401))
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/latest/user-data"))
+            .and(header("X-aws-ec2-metadata-token", "fresh-token"))
+            .respond_with(ResponseTemplate::new(200).set_body_string("data"))
+            .expect(2)
+            .mount(&server)
+            .await;
+
+        let metadata = CloudMetadata::aws_with_base_url(&server.uri());
+        assert_eq!(metadata.custom_text("ignored").await.unwrap(), "data");
+        assert_eq!(metadata.custom_text("ignored").await.unwrap(), "data");
+    }
+
+    #[tokio::test]
+    async fn test_aws_token_retried_once() {
+        let server = MockServer::start().await;
+        Mock::given(method("PUT"))
+            .and(path("/latest/api/token"))
+            .respond_with(ResponseTemplate::new(200).set_body_string("mock-token"))
+            .expect(2)
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/latest/user-data"))
+            .respond_with(ResponseTemplate::new(401))
+            .expect(2)
             .mount(&server)
             .await;
 
         let metadata = CloudMetadata::aws_with_base_url(&server.uri());
         let result = metadata.custom_data("ignored").await;
 
-        assert!(matches!(result, Err(MetadataError::NotFound)));
+        assert!(matches!(result, Err(MetadataError::Http(401))));
+    }
+
+    /// Token endpoint whose response never arrives in time, as behind a
+    /// hop limit of 1 inside a container.
+    async fn setup_dropped_token(server: &MockServer) {
+        Mock::given(method("PUT"))
+            .and(path("/latest/api/token"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_string("mock-token")
+                    .set_delay(Duration::from_secs(3)),
+            )
+            .mount(server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/latest/meta-data/"))
+            .respond_with(ResponseTemplate::new(200).set_body_string("ami-id\n"))
+            .mount(server)
+            .await;
+    }
+
+    #[tokio::test]
+    async fn test_aws_hop_limit() {
+        let server = MockServer::start().await;
+        setup_dropped_token(&server).await;
+
+        let metadata = CloudMetadata::aws_with_base_url(&server.uri())
+            .with_aws_token_timeout(Duration::from_secs(1));
+        let result = metadata.custom_data("ignored").await;
+
+        assert!(matches!(result, Err(MetadataError::HopLimit)));
+    }
+
+    #[tokio::test]
+    async fn test_aws_imdsv1_fallback() {
+        let server = MockServer::start().await;
+        setup_dropped_token(&server).await;
+        Mock::given(method("GET"))
+            .and(path("/latest/user-data"))
+            .respond_with(ResponseTemplate::new(200).set_body_string("data"))
+            .expect(2)
+            .mount(&server)
+            .await;
+
+        let metadata =
+            CloudMetadata::aws_with_base_url(&server.uri()).with_aws_imdsv1_fallback(true);
+        assert_eq!(metadata.custom_text("ignored").await.unwrap(), "data");
+        assert_eq!(metadata.custom_text("ignored").await.unwrap(), "data");
+
+        let requests = server.received_requests().await.unwrap();
+        let puts = requests.iter().filter(|r| r.method.as_str() == "PUT");
+        assert_eq!(puts.count(), 1);
+        assert!(requests
+            .iter()
+            .all(|r| !r.headers.contains_key("X-aws-ec2-metadata-token")));
+    }
+
+    #[tokio::test]
+    async fn test_aws_imdsv1_fallback_not_when_disabled() {
+        let server = MockServer::start().await;
+        Mock::given(method("PUT"))
+            .and(path("/latest/api/token"))
+            .respond_with(ResponseTemplate::new(403))
+            .mount(&server)
+            .await;
+
+        let metadata =
+            CloudMetadata::aws_with_base_url(&server.uri()).with_aws_imdsv1_fallback(true);
+        let result = metadata.custom_data("ignored").await;
+
+        assert!(matches!(result, Err(MetadataError::Http(403))));
     }
 
     #[tokio::test]
@@ -209,6 +382,72 @@ mod gcp {
         assert_eq!(value, "project-value");
     }
 
+    async fn setup_gcp_project_mock(server: &MockServer, key: &str, value: &str) {
+        Mock::given(method("GET"))
+            .and(path(format!(
+                "/computeMetadata/v1/project/attributes/{}",
+                key
+            )))
+            .and(header("Metadata-Flavor", "Google"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(value))
+            .mount(server)
+            .await;
+    }
+
+    #[tokio::test]
+    async fn test_gcp_project_scope() {
+        let server = MockServer::start().await;
+        setup_gcp_mock(&server, "config", "instance-value").await;
+        let config = r#"{"db_host": "postgres.internal", "port": 5432}"#;
+        setup_gcp_project_mock(&server, "config", config).await;
+
+        let metadata = CloudMetadata::gcp_with_base_url(&server.uri())
+            .with_gcp_attribute_scope(GcpAttributeScope::Project);
+        let config: TestConfig = metadata.custom_json("config").await.unwrap();
+
+        assert_eq!(config.db_host, "postgres.internal");
+    }
+
+    #[tokio::test]
+    async fn test_gcp_instance_then_project_scope() {
+        let server = MockServer::start().await;
+        setup_gcp_mock(&server, "ssh-keys", "instance-keys").await;
+        setup_gcp_project_mock(&server, "ssh-keys", "project-keys").await;
+        setup_gcp_project_mock(&server, "enable-oslogin", "TRUE").await;
+
+        Mock::given(method("GET"))
+            .and(path(
+                "/computeMetadata/v1/instance/attributes/enable-oslogin",
+            ))
+            .respond_with(ResponseTemplate::new(404))
+            .mount(&server)
+            .await;
+
+        let metadata = CloudMetadata::gcp_with_base_url(&server.uri())
+            .with_gcp_attribute_scope(GcpAttributeScope::InstanceThenProject);
+
+        let keys = metadata.custom_text("ssh-keys").await.unwrap();
+        assert_eq!(keys, "instance-keys");
+        let oslogin = metadata.custom_text("enable-oslogin").await.unwrap();
+        assert_eq!(oslogin, "TRUE");
+    }
+
+    #[tokio::test]
+    async fn test_gcp_instance_then_project_scope_not_found() {
+        let server = MockServer::start().await;
+
+        Mock::given(method("GET"))
+            .respond_with(ResponseTemplate::new(404))
+            .mount(&server)
+            .await;
+
+        let metadata = CloudMetadata::gcp_with_base_url(&server.uri())
+            .with_gcp_attribute_scope(GcpAttributeScope::InstanceThenProject);
+        let result = metadata.custom_data("missing").await;
+
+        assert!(matches!(result, Err(MetadataError::NotFound)));
+    }
+
     #[tokio::test]
     async fn test_gcp_provider() {
         let metadata = CloudMetadata::gcp_with_base_url("http://localhost:1234");
@@ -223,6 +462,10 @@ mod gcp {
 mod azure {
     use super::*;
 
+    use std::time::Duration;
+
+    use cloud_metadata::AzureDataSource;
+
     async fn setup_azure_mock(server: &MockServer, custom_data: &[u8]) {
         let encoded = STANDARD.encode(custom_data);
 
@@ -300,103 +543,2814 @@ mod azure {
             .mount(&server)
             .await;
 
-        let metadata = CloudMetadata::azure_with_base_url(&server.uri());
+        let metadata = CloudMetadata::azure_with_base_url(&server.uri())
+            .with_ovf_path("/nonexistent/ovf-env.xml");
         let result = metadata.custom_data("ignored").await;
 
         assert!(matches!(result, Err(MetadataError::NotFound)));
     }
 
     #[tokio::test]
-    async fn test_azure_invalid_base64() {
+    async fn test_azure_custom_data_falls_back_to_ovf() {
+        let server = MockServer::start().await;
+        setup_azure_mock(&server, b"").await;
+
+        let ovf = tempfile::tempdir().unwrap();
+        std::fs::write(
+            ovf.path().join("ovf-env.xml"),
+            format!(
+                "<ns1:LinuxProvisioningConfigurationSet><ns1:CustomData>{}</ns1:CustomData></ns1:LinuxProvisioningConfigurationSet>",
+                STANDARD.encode(r#"{"db_host": "postgres.internal", "port": 5432}"#)
+            ),
+        )
+        .unwrap();
+
+        let metadata = CloudMetadata::azure_with_base_url(&server.uri()).with_ovf_path(ovf.path());
+        let config: TestConfig = metadata.custom_json("ignored").await.unwrap();
+
+        assert_eq!(
+            config,
+            TestConfig {
+                db_host: "postgres.internal".to_string(),
+                port: 5432,
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn test_azure_custom_data_missing_in_ovf() {
         let server = MockServer::start().await;
+        setup_azure_mock(&server, b"").await;
 
+        let ovf = tempfile::tempdir().unwrap();
+        std::fs::write(ovf.path().join("ovf-env.xml"), "<Environment/>").unwrap();
+
+        let metadata = CloudMetadata::azure_with_base_url(&server.uri()).with_ovf_path(ovf.path());
+        let result = metadata.custom_data("ignored").await;
+
+        assert!(matches!(result, Err(MetadataError::NotFound)));
+    }
+
+    async fn setup_azure_user_data_mock(server: &MockServer, user_data: &[u8]) {
         Mock::given(method("GET"))
-            .and(path("/metadata/instance/compute/customData"))
+            .and(path("/metadata/instance/compute/userData"))
             .and(query_param("api-version", "2021-02-01"))
             .and(query_param("format", "text"))
             .and(header("Metadata", "true"))
-            .respond_with(ResponseTemplate::new(200).set_body_string("not-valid-base64!!!"))
-            .mount(&server)
+            .respond_with(ResponseTemplate::new(200).set_body_string(STANDARD.encode(user_data)))
+            .mount(server)
             .await;
+    }
+
+    #[tokio::test]
+    async fn test_azure_user_data_preferred() {
+        let server = MockServer::start().await;
+        setup_azure_user_data_mock(&server, b"from userData").await;
+        setup_azure_mock(&server, b"from customData").await;
 
         let metadata = CloudMetadata::azure_with_base_url(&server.uri());
-        let result = metadata.custom_data("ignored").await;
+        let text = metadata.custom_text("ignored").await.unwrap();
 
-        assert!(matches!(result, Err(MetadataError::Base64)));
+        assert_eq!(text, "from userData");
     }
 
     #[tokio::test]
-    async fn test_azure_provider() {
-        let metadata = CloudMetadata::azure_with_base_url("http://localhost:1234");
-        assert_eq!(metadata.provider(), CloudProvider::Azure);
-    }
-}
+    async fn test_azure_empty_user_data_falls_back_to_custom_data() {
+        let server = MockServer::start().await;
+        setup_azure_user_data_mock(&server, b"").await;
+        setup_azure_mock(&server, b"from customData").await;
 
-// =============================================================================
-// Detection Tests
-// =============================================================================
+        let metadata = CloudMetadata::azure_with_base_url(&server.uri());
+        let text = metadata.custom_text("ignored").await.unwrap();
 
-mod detection {
-    use super::*;
+        assert_eq!(text, "from customData");
+    }
 
     #[tokio::test]
-    async fn test_detect_aws() {
+    async fn test_azure_sources_order() {
         let server = MockServer::start().await;
+        setup_azure_user_data_mock(&server, b"from userData").await;
+        setup_azure_mock(&server, b"from customData").await;
 
-        // AWS probe endpoint (token endpoint with TTL=1)
-        Mock::given(method("PUT"))
-            .and(path("/latest/api/token"))
-            .respond_with(ResponseTemplate::new(200).set_body_string("token"))
-            .mount(&server)
-            .await;
-
-        // Also need the fetch endpoints for after detection
-        Mock::given(method("PUT"))
-            .and(path("/latest/api/token"))
-            .and(header("X-aws-ec2-metadata-token-ttl-seconds", "60"))
-            .respond_with(ResponseTemplate::new(200).set_body_string("mock-token"))
-            .mount(&server)
-            .await;
+        let metadata = CloudMetadata::azure_with_base_url(&server.uri())
+            .with_azure_sources(&[AzureDataSource::CustomData, AzureDataSource::UserData]);
+        let text = metadata.custom_text("ignored").await.unwrap();
 
-        let metadata = CloudMetadata::detect_with_base_url(&server.uri())
-            .await
-            .unwrap();
-        assert_eq!(metadata.provider(), CloudProvider::Aws);
+        assert_eq!(text, "from customData");
     }
 
     #[tokio::test]
-    async fn test_detect_gcp() {
+    async fn test_azure_user_data_only() {
         let server = MockServer::start().await;
+        setup_azure_mock(&server, b"from customData").await;
 
-        // GCP probe endpoint
-        Mock::given(method("GET"))
-            .and(path("/computeMetadata/v1"))
-            .and(header("Metadata-Flavor", "Google"))
-            .respond_with(ResponseTemplate::new(200))
-            .mount(&server)
-            .await;
+        let metadata = CloudMetadata::azure_with_base_url(&server.uri())
+            .with_azure_sources(&[AzureDataSource::UserData]);
+        let result = metadata.custom_data("ignored").await;
 
-        let metadata = CloudMetadata::detect_with_base_url(&server.uri())
-            .await
-            .unwrap();
-        assert_eq!(metadata.provider(), CloudProvider::Gcp);
+        assert!(matches!(result, Err(MetadataError::NotFound)));
     }
 
     #[tokio::test]
-    async fn test_detect_azure() {
+    async fn test_azure_api_version_negotiated_and_cached() {
         let server = MockServer::start().await;
 
-        // Azure probe endpoint
         Mock::given(method("GET"))
-            .and(path("/metadata"))
-            .and(query_param("api-version", "2021-02-01"))
+            .and(path("/metadata/versions"))
             .and(header("Metadata", "true"))
-            .respond_with(ResponseTemplate::new(200))
+            .respond_with(ResponseTemplate::new(200).set_body_string(
+                r#"{"apiVersions": ["2019-02-01", "2021-02-01", "2023-07-01", "2099-01-01"]}"#,
+            ))
+            .expect(1)
             .mount(&server)
             .await;
 
-        let metadata = CloudMetadata::detect_with_base_url(&server.uri())
-            .await
+        Mock::given(method("GET"))
+            .and(path("/metadata/instance/compute/userData"))
+            .and(query_param("api-version", "2023-07-01"))
+            .and(header("Metadata", "true"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(STANDARD.encode("negotiated")))
+            .mount(&server)
+            .await;
+
+        let metadata = CloudMetadata::azure_with_base_url(&server.uri());
+        assert_eq!(metadata.custom_text("ignored").await.unwrap(), "negotiated");
+        assert_eq!(metadata.custom_text("ignored").await.unwrap(), "negotiated");
+    }
+
+    #[tokio::test]
+    async fn test_azure_api_version_falls_back_when_listing_fails() {
+        let server = MockServer::start().await;
+
+        // Times out with the 5 s client timeout
+        Mock::given(method("GET"))
+            .and(path("/metadata/versions"))
+            .respond_with(ResponseTemplate::new(200).set_delay(Duration::from_secs(6)))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        Mock::given(method("GET"))
+            .and(path("/metadata/instance/compute/userData"))
+            .and(query_param("api-version", "2021-02-01"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(STANDARD.encode("pinned")))
+            .expect(2)
+            .mount(&server)
+            .await;
+
+        let metadata = CloudMetadata::azure_with_base_url(&server.uri());
+        assert_eq!(metadata.custom_text("ignored").await.unwrap(), "pinned");
+        // Clones share the cached fallback
+        let clone = metadata.clone();
+        assert_eq!(clone.custom_text("ignored").await.unwrap(), "pinned");
+    }
+
+    #[tokio::test]
+    async fn test_azure_api_version_override() {
+        let server = MockServer::start().await;
+
+        Mock::given(method("GET"))
+            .and(path("/metadata/versions"))
+            .respond_with(ResponseTemplate::new(200))
+            .expect(0)
+            .mount(&server)
+            .await;
+
+        Mock::given(method("GET"))
+            .and(path("/metadata/instance/compute/userData"))
+            .and(query_param("api-version", "2021-12-13"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(STANDARD.encode("pinned")))
+            .mount(&server)
+            .await;
+
+        let metadata =
+            CloudMetadata::azure_with_base_url(&server.uri()).with_azure_api_version("2021-12-13");
+        assert_eq!(metadata.custom_text("ignored").await.unwrap(), "pinned");
+    }
+
+    #[tokio::test]
+    async fn test_azure_invalid_base64() {
+        let server = MockServer::start().await;
+
+        Mock::given(method("GET"))
+            .and(path("/metadata/instance/compute/customData"))
+            .and(query_param("api-version", "2021-02-01"))
+            .and(query_param("format", "text"))
+            .and(header("Metadata", "true"))
+            .respond_with(ResponseTemplate::new(200).set_body_string("not-valid-base64!!!"))
+            .mount(&server)
+            .await;
+
+        let metadata = CloudMetadata::azure_with_base_url(&server.uri());
+        let result = metadata.custom_data("ignored").await;
+
+        assert!(matches!(result, Err(MetadataError::Base64)));
+    }
+
+    #[tokio::test]
+    async fn test_azure_provider() {
+        let metadata = CloudMetadata::azure_with_base_url("http://localhost:1234");
+        assert_eq!(metadata.provider(), CloudProvider::Azure);
+    }
+}
+
+// =============================================================================
+// NoCloud Tests
+// =============================================================================
+
+mod nocloud {
+    use super::*;
+
+    use cloud_metadata::NoCloudMetaData;
+
+    fn setup_seed(user_data: &str) -> tempfile::TempDir {
+        let seed = tempfile::tempdir().unwrap();
+        std::fs::write(
+            seed.path().join("meta-data"),
+            "instance-id: iid-local01\nlocal-hostname: node-1\n",
+        )
+        .unwrap();
+        std::fs::write(seed.path().join("user-data"), user_data).unwrap();
+        seed
+    }
+
+    #[tokio::test]
+    async fn test_nocloud_fetch_user_data_as_json() {
+        let seed = setup_seed(r#"{"db_host": "postgres.internal", "port": 5432}"#);
+
+        let metadata = CloudMetadata::nocloud_with_path(seed.path());
+        let config: TestConfig = metadata.custom_json("ignored").await.unwrap();
+
+        assert_eq!(
+            config,
+            TestConfig {
+                db_host: "postgres.internal".to_string(),
+                port: 5432,
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn test_nocloud_meta_data() {
+        let seed = setup_seed("#cloud-config\n");
+
+        let metadata = CloudMetadata::nocloud_with_path(seed.path());
+        let meta = metadata.nocloud_meta_data().await.unwrap();
+
+        assert_eq!(
+            meta,
+            NoCloudMetaData {
+                instance_id: Some("iid-local01".to_string()),
+                hostname: Some("node-1".to_string()),
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn test_nocloud_vendor_data() {
+        let seed = setup_seed("#cloud-config\n");
+        std::fs::write(seed.path().join("vendor-data"), "vendor").unwrap();
+
+        let metadata = CloudMetadata::nocloud_with_path(seed.path());
+        let data = metadata.vendor_data().await.unwrap();
+
+        assert_eq!(data, b"vendor");
+    }
+
+    #[tokio::test]
+    async fn test_nocloud_user_data_not_found() {
+        let seed = tempfile::tempdir().unwrap();
+
+        let metadata = CloudMetadata::nocloud_with_path(seed.path());
+        let result = metadata.custom_data("ignored").await;
+
+        assert!(matches!(result, Err(MetadataError::NotFound)));
+    }
+
+    #[tokio::test]
+    async fn test_nocloud_max_size() {
+        let seed = setup_seed("this data is too large for the limit");
+
+        let metadata = CloudMetadata::nocloud_with_path(seed.path()).with_max_size(10);
+        let result = metadata.custom_data("ignored").await;
+
+        assert!(matches!(result, Err(MetadataError::TooLarge(36, 10))));
+    }
+
+    #[tokio::test]
+    async fn test_nocloud_meta_data_not_supported_on_aws() {
+        let metadata = CloudMetadata::aws_with_base_url("http://localhost:1234");
+        let result = metadata.nocloud_meta_data().await;
+        assert!(matches!(result, Err(MetadataError::NotSupported)));
+    }
+}
+
+// =============================================================================
+// OpenStack Tests
+// =============================================================================
+
+mod openstack {
+    use super::*;
+
+    fn setup_config_drive(meta_data: &str, user_data: &str) -> tempfile::TempDir {
+        let drive = tempfile::tempdir().unwrap();
+        let latest = drive.path().join("openstack/latest");
+        std::fs::create_dir_all(&latest).unwrap();
+        std::fs::write(latest.join("meta_data.json"), meta_data).unwrap();
+        std::fs::write(latest.join("user_data"), user_data).unwrap();
+        drive
+    }
+
+    #[tokio::test]
+    async fn test_openstack_fetch_meta_key_as_json() {
+        let drive = setup_config_drive(
+            r#"{"uuid": "abc", "meta": {"config": "{\"db_host\": \"postgres.internal\", \"port\": 5432}"}}"#,
+            "#cloud-config\n",
+        );
+
+        let metadata = CloudMetadata::openstack_with_path(drive.path());
+        let config: TestConfig = metadata.custom_json("config").await.unwrap();
+
+        assert_eq!(
+            config,
+            TestConfig {
+                db_host: "postgres.internal".to_string(),
+                port: 5432,
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn test_openstack_empty_key_returns_user_data() {
+        let drive = setup_config_drive(r#"{"uuid": "abc"}"#, "Hello, OpenStack!");
+
+        let metadata = CloudMetadata::openstack_with_path(drive.path());
+        let text = metadata.custom_text("").await.unwrap();
+
+        assert_eq!(text, "Hello, OpenStack!");
+    }
+
+    #[tokio::test]
+    async fn test_openstack_unknown_key_not_found() {
+        let drive = setup_config_drive(r#"{"uuid": "abc", "meta": {"app": "{}"}}"#, "{}");
+
+        let metadata = CloudMetadata::openstack_with_path(drive.path());
+        let result = metadata.custom_json::<serde_json::Value>("ap").await;
+
+        assert!(matches!(result, Err(MetadataError::NotFound)));
+    }
+
+    #[tokio::test]
+    async fn test_openstack_meta_data_max_size() {
+        let meta_data = format!(
+            r#"{{"meta": {{"app": "{{}}", "big": "{}"}}}}"#,
+            "a".repeat(1024)
+        );
+        let drive = setup_config_drive(&meta_data, "");
+
+        let metadata = CloudMetadata::openstack_with_path(drive.path()).with_max_size(512);
+        let result = metadata.custom_data("app").await;
+
+        assert!(matches!(result, Err(MetadataError::TooLarge(_, 512))));
+    }
+
+    #[tokio::test]
+    async fn test_openstack_meta_data() {
+        let drive = setup_config_drive(
+            r#"{"uuid": "abc", "hostname": "node-1", "availability_zone": "nova"}"#,
+            "",
+        );
+
+        let metadata = CloudMetadata::openstack_with_path(drive.path());
+        let meta = metadata.openstack_meta_data().await.unwrap();
+
+        assert_eq!(meta.uuid.as_deref(), Some("abc"));
+        assert_eq!(meta.hostname.as_deref(), Some("node-1"));
+        assert_eq!(meta.availability_zone.as_deref(), Some("nova"));
+    }
+
+    #[tokio::test]
+    async fn test_openstack_vendor_data() {
+        let drive = setup_config_drive("{}", "");
+        std::fs::write(
+            drive.path().join("openstack/latest/vendor_data2.json"),
+            "{}",
+        )
+        .unwrap();
+
+        let metadata = CloudMetadata::openstack_with_path(drive.path());
+        let data = metadata.vendor_data().await.unwrap();
+
+        assert_eq!(data, b"{}");
+    }
+
+    #[tokio::test]
+    async fn test_openstack_missing_drive() {
+        let metadata = CloudMetadata::openstack_with_path("/nonexistent/config-2");
+        let result = metadata.custom_data("").await;
+
+        assert!(matches!(result, Err(MetadataError::NotFound)));
+    }
+}
+
+// =============================================================================
+// Identity Tests
+// =============================================================================
+
+mod identity {
+    use super::*;
+
+    use cloud_metadata::InstanceIdentity;
+
+    async fn mock_get(server: &MockServer, url_path: &str, body: &str) {
+        Mock::given(method("GET"))
+            .and(path(url_path))
+            .respond_with(ResponseTemplate::new(200).set_body_string(body))
+            .mount(server)
+            .await;
+    }
+
+    #[tokio::test]
+    async fn test_aws_identity() {
+        let server = MockServer::start().await;
+        setup_aws_token(&server).await;
+        mock_get(
+            &server,
+            "/latest/dynamic/instance-identity/document",
+            r#"{"accountId": "123456789012", "availabilityZone": "us-east-1a",
+                "imageId": "ami-0abc", "instanceId": "i-0abc", "instanceType": "t3.micro",
+                "region": "us-east-1"}"#,
+        )
+        .await;
+        mock_get(
+            &server,
+            "/latest/meta-data/local-hostname",
+            "ip-10-0-0-1.ec2.internal",
+        )
+        .await;
+
+        let metadata = CloudMetadata::aws_with_base_url(&server.uri());
+        let identity = metadata.identity().await.unwrap();
+
+        assert_eq!(
+            identity,
+            InstanceIdentity {
+                instance_id: Some("i-0abc".to_string()),
+                region: Some("us-east-1".to_string()),
+                zone: Some("us-east-1a".to_string()),
+                machine_type: Some("t3.micro".to_string()),
+                hostname: Some("ip-10-0-0-1.ec2.internal".to_string()),
+                account_id: Some("123456789012".to_string()),
+                image_id: Some("ami-0abc".to_string()),
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn test_gcp_identity() {
+        let server = MockServer::start().await;
+        let instance = "/computeMetadata/v1/instance";
+        mock_get(&server, &format!("{}/id", instance), "4567").await;
+        mock_get(
+            &server,
+            &format!("{}/zone", instance),
+            "projects/123/zones/us-central1-a",
+        )
+        .await;
+        mock_get(
+            &server,
+            &format!("{}/machine-type", instance),
+            "projects/123/machineTypes/e2-medium",
+        )
+        .await;
+        mock_get(
+            &server,
+            &format!("{}/hostname", instance),
+            "vm.c.my-project.internal",
+        )
+        .await;
+        mock_get(
+            &server,
+            "/computeMetadata/v1/project/project-id",
+            "my-project",
+        )
+        .await;
+
+        let metadata = CloudMetadata::gcp_with_base_url(&server.uri());
+        let identity = metadata.identity().await.unwrap();
+
+        assert_eq!(
+            identity,
+            InstanceIdentity {
+                instance_id: Some("4567".to_string()),
+                region: Some("us-central1".to_string()),
+                zone: Some("us-central1-a".to_string()),
+                machine_type: Some("e2-medium".to_string()),
+                hostname: Some("vm.c.my-project.internal".to_string()),
+                account_id: Some("my-project".to_string()),
+                image_id: None,
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn test_azure_identity() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/metadata/instance/compute"))
+            .and(query_param("format", "json"))
+            .and(header("Metadata", "true"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(
+                r#"{"location": "eastus", "zone": "2", "vmId": "02aab8a4", "vmSize": "Standard_D2s_v3",
+                    "name": "vm1", "osProfile": {"computerName": "node-1"},
+                    "subscriptionId": "8d10da13",
+                    "storageProfile": {"imageReference": {"id": "", "publisher": "canonical",
+                        "offer": "ubuntu", "sku": "22_04", "version": "latest"}}}"#,
+            ))
+            .mount(&server)
+            .await;
+
+        let metadata = CloudMetadata::azure_with_base_url(&server.uri());
+        let identity = metadata.identity().await.unwrap();
+
+        assert_eq!(
+            identity,
+            InstanceIdentity {
+                instance_id: Some("02aab8a4".to_string()),
+                region: Some("eastus".to_string()),
+                zone: Some("eastus-2".to_string()),
+                machine_type: Some("Standard_D2s_v3".to_string()),
+                hostname: Some("node-1".to_string()),
+                account_id: Some("8d10da13".to_string()),
+                image_id: Some("canonical:ubuntu:22_04:latest".to_string()),
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn test_nocloud_identity() {
+        let seed = tempfile::tempdir().unwrap();
+        std::fs::write(
+            seed.path().join("meta-data"),
+            "instance-id: iid-local01\nlocal-hostname: node-1\n",
+        )
+        .unwrap();
+
+        let metadata = CloudMetadata::nocloud_with_path(seed.path());
+        let identity = metadata.identity().await.unwrap();
+
+        assert_eq!(identity.instance_id.as_deref(), Some("iid-local01"));
+        assert_eq!(identity.hostname.as_deref(), Some("node-1"));
+        assert_eq!(identity.region, None);
+    }
+}
+
+// =============================================================================
+// Tags Tests
+// =============================================================================
+
+mod tags {
+    use std::collections::BTreeMap;
+
+    use super::*;
+
+    fn map(entries: &[(&str, &str)]) -> BTreeMap<String, String> {
+        entries
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    }
+
+    async fn mock_aws_token(server: &MockServer) {
+        Mock::given(method("PUT"))
+            .and(path("/latest/api/token"))
+            .respond_with(ResponseTemplate::new(200).set_body_string("mock-token"))
+            .mount(server)
+            .await;
+    }
+
+    #[tokio::test]
+    async fn test_aws_tags() {
+        let server = MockServer::start().await;
+        mock_aws_token(&server).await;
+        for (url_path, body) in [
+            ("/latest/meta-data/tags/instance", "Name\nrole\nCost Center"),
+            ("/latest/meta-data/tags/instance/Name", "web-1"),
+            ("/latest/meta-data/tags/instance/role", "frontend"),
+            ("/latest/meta-data/tags/instance/Cost%20Center", "1234"),
+        ] {
+            Mock::given(method("GET"))
+                .and(path(url_path))
+                .and(header("X-aws-ec2-metadata-token", "mock-token"))
+                .respond_with(ResponseTemplate::new(200).set_body_string(body))
+                .mount(&server)
+                .await;
+        }
+
+        let metadata = CloudMetadata::aws_with_base_url(&server.uri());
+        let tags = metadata.tags().await.unwrap();
+
+        assert_eq!(
+            tags,
+            map(&[
+                ("Cost Center", "1234"),
+                ("Name", "web-1"),
+                ("role", "frontend")
+            ])
+        );
+    }
+
+    #[tokio::test]
+    async fn test_aws_tags_not_enabled() {
+        let server = MockServer::start().await;
+        mock_aws_token(&server).await;
+
+        let metadata = CloudMetadata::aws_with_base_url(&server.uri());
+        let result = metadata.tags().await;
+
+        assert!(matches!(result, Err(MetadataError::NotFound)));
+    }
+
+    #[tokio::test]
+    async fn test_gcp_tags_with_prefix() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/computeMetadata/v1/instance/attributes/"))
+            .and(query_param("recursive", "true"))
+            .and(header("Metadata-Flavor", "Google"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(
+                r#"{"tag-role": "frontend", "tag-env": "prod", "ssh-keys": "...", "tag-": "x"}"#,
+            ))
+            .mount(&server)
+            .await;
+
+        let metadata = CloudMetadata::gcp_with_base_url(&server.uri()).with_gcp_tag_prefix("tag-");
+        let tags = metadata.tags().await.unwrap();
+
+        assert_eq!(tags, map(&[("env", "prod"), ("role", "frontend")]));
+    }
+
+    #[tokio::test]
+    async fn test_gcp_tags_require_prefix() {
+        let server = MockServer::start().await;
+
+        let metadata = CloudMetadata::gcp_with_base_url(&server.uri());
+        let result = metadata.tags().await;
+
+        assert!(matches!(result, Err(MetadataError::NotSupported)));
+    }
+
+    #[tokio::test]
+    async fn test_azure_tags() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/metadata/instance/compute/tagsList"))
+            .and(header("Metadata", "true"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(
+                r#"[{"name": "env", "value": "prod"}, {"name": "role", "value": "worker"}]"#,
+            ))
+            .mount(&server)
+            .await;
+
+        let metadata = CloudMetadata::azure_with_base_url(&server.uri());
+        let tags = metadata.tags().await.unwrap();
+
+        assert_eq!(tags, map(&[("env", "prod"), ("role", "worker")]));
+    }
+
+    #[tokio::test]
+    async fn test_tags_not_supported_on_nocloud() {
+        let metadata = CloudMetadata::nocloud_with_path("/nonexistent");
+        let result = metadata.tags().await;
+        assert!(matches!(result, Err(MetadataError::NotSupported)));
+    }
+}
+
+// =============================================================================
+// Network Tests
+// =============================================================================
+
+mod network {
+    use super::*;
+
+    use cloud_metadata::NetworkInterface;
+
+    #[tokio::test]
+    async fn test_aws_network() {
+        let server = MockServer::start().await;
+        setup_aws_token(&server).await;
+
+        let macs = "/latest/meta-data/network/interfaces/macs";
+        for (url_path, body) in [
+            (
+                format!("{}/", macs),
+                "0e:00:00:00:00:02/\n0e:00:00:00:00:01/",
+            ),
+            (format!("{}/0e:00:00:00:00:01/device-number", macs), "0"),
+            (
+                format!("{}/0e:00:00:00:00:01/local-ipv4s", macs),
+                "10.0.0.1\n10.0.0.2",
+            ),
+            (
+                format!("{}/0e:00:00:00:00:01/public-ipv4s", macs),
+                "3.3.3.3",
+            ),
+            (format!("{}/0e:00:00:00:00:01/subnet-id", macs), "subnet-1"),
+            (format!("{}/0e:00:00:00:00:01/vpc-id", macs), "vpc-1"),
+            (format!("{}/0e:00:00:00:00:02/device-number", macs), "1"),
+            (
+                format!("{}/0e:00:00:00:00:02/local-ipv4s", macs),
+                "10.0.1.1",
+            ),
+            (format!("{}/0e:00:00:00:00:02/ipv6s", macs), "2600::1"),
+            (format!("{}/0e:00:00:00:00:02/subnet-id", macs), "subnet-2"),
+            (format!("{}/0e:00:00:00:00:02/vpc-id", macs), "vpc-1"),
+        ] {
+            Mock::given(method("GET"))
+                .and(path(url_path))
+                .respond_with(ResponseTemplate::new(200).set_body_string(body))
+                .mount(&server)
+                .await;
+        }
+
+        let metadata = CloudMetadata::aws_with_base_url(&server.uri());
+        let interfaces = metadata.network().await.unwrap();
+
+        assert_eq!(
+            interfaces,
+            vec![
+                NetworkInterface {
+                    mac: "0e:00:00:00:00:01".to_string(),
+                    primary: true,
+                    private_ipv4: vec!["10.0.0.1".to_string(), "10.0.0.2".to_string()],
+                    ipv6: vec![],
+                    public_ipv4: vec!["3.3.3.3".to_string()],
+                    subnet_id: Some("subnet-1".to_string()),
+                    vpc_id: Some("vpc-1".to_string()),
+                },
+                NetworkInterface {
+                    mac: "0e:00:00:00:00:02".to_string(),
+                    primary: false,
+                    private_ipv4: vec!["10.0.1.1".to_string()],
+                    ipv6: vec!["2600::1".to_string()],
+                    public_ipv4: vec![],
+                    subnet_id: Some("subnet-2".to_string()),
+                    vpc_id: Some("vpc-1".to_string()),
+                },
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_gcp_network() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/computeMetadata/v1/instance/network-interfaces/"))
+            .and(query_param("recursive", "true"))
+            .and(header("Metadata-Flavor", "Google"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(
+                r#"[{"accessConfigs": [{"externalIp": "34.1.2.3", "type": "ONE_TO_ONE_NAT"}],
+                     "ip": "10.128.0.2", "mac": "42:01:0a:80:00:02",
+                     "network": "projects/123/networks/default"}]"#,
+            ))
+            .mount(&server)
+            .await;
+
+        let metadata = CloudMetadata::gcp_with_base_url(&server.uri());
+        let interfaces = metadata.network().await.unwrap();
+
+        assert_eq!(
+            interfaces,
+            vec![NetworkInterface {
+                mac: "42:01:0a:80:00:02".to_string(),
+                primary: true,
+                private_ipv4: vec!["10.128.0.2".to_string()],
+                ipv6: vec![],
+                public_ipv4: vec!["34.1.2.3".to_string()],
+                subnet_id: None,
+                vpc_id: Some("default".to_string()),
+            }]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_azure_network() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/metadata/instance/network"))
+            .and(header("Metadata", "true"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(
+                r#"{"interface": [{
+                    "ipv4": {"ipAddress": [{"privateIpAddress": "10.0.0.4", "publicIpAddress": "20.1.2.3"}],
+                             "subnet": [{"address": "10.0.0.0", "prefix": "24"}]},
+                    "ipv6": {"ipAddress": []},
+                    "macAddress": "000D3AF806EC"}]}"#,
+            ))
+            .mount(&server)
+            .await;
+
+        let metadata = CloudMetadata::azure_with_base_url(&server.uri());
+        let interfaces = metadata.network().await.unwrap();
+
+        assert_eq!(
+            interfaces,
+            vec![NetworkInterface {
+                mac: "00:0d:3a:f8:06:ec".to_string(),
+                primary: true,
+                private_ipv4: vec!["10.0.0.4".to_string()],
+                ipv6: vec![],
+                public_ipv4: vec!["20.1.2.3".to_string()],
+                subnet_id: Some("10.0.0.0/24".to_string()),
+                vpc_id: None,
+            }]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_openstack_network() {
+        let drive = tempfile::tempdir().unwrap();
+        let latest = drive.path().join("openstack/latest");
+        std::fs::create_dir_all(&latest).unwrap();
+        std::fs::write(
+            latest.join("network_data.json"),
+            r#"{"links": [{"id": "tap1", "ethernet_mac_address": "fa:16:3e:00:00:01", "type": "ovs"}],
+                "networks": [{"id": "network0", "link": "tap1", "type": "ipv4",
+                              "ip_address": "10.0.0.5", "network_id": "net-uuid"}]}"#,
+        )
+        .unwrap();
+
+        let metadata = CloudMetadata::openstack_with_path(drive.path());
+        let interfaces = metadata.network().await.unwrap();
+
+        assert_eq!(interfaces.len(), 1);
+        assert_eq!(interfaces[0].mac, "fa:16:3e:00:00:01");
+        assert_eq!(interfaces[0].private_ipv4, vec!["10.0.0.5".to_string()]);
+        assert_eq!(interfaces[0].vpc_id.as_deref(), Some("net-uuid"));
+    }
+}
+
+// =============================================================================
+// Key Listing Tests
+// =============================================================================
+
+mod list_keys {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_aws_list_keys() {
+        let server = MockServer::start().await;
+        setup_aws_token(&server).await;
+        Mock::given(method("GET"))
+            .and(path("/latest/meta-data/"))
+            .and(header("X-aws-ec2-metadata-token", "mock-token"))
+            .respond_with(
+                ResponseTemplate::new(200).set_body_string("ami-id\nhostname\nnetwork/\ntags/"),
+            )
+            .mount(&server)
+            .await;
+
+        let metadata = CloudMetadata::aws_with_base_url(&server.uri());
+        let keys = metadata.list_keys().await.unwrap();
+
+        assert_eq!(keys, vec!["ami-id", "hostname", "network/", "tags/"]);
+    }
+
+    #[tokio::test]
+    async fn test_gcp_list_keys() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/computeMetadata/v1/instance/attributes/"))
+            .and(header("Metadata-Flavor", "Google"))
+            .respond_with(ResponseTemplate::new(200).set_body_string("ssh-keys\nuser-data-json\n"))
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/computeMetadata/v1/project/attributes/"))
+            .and(header("Metadata-Flavor", "Google"))
+            .respond_with(ResponseTemplate::new(200).set_body_string("enable-oslogin\n"))
+            .mount(&server)
+            .await;
+
+        let metadata = CloudMetadata::gcp_with_base_url(&server.uri());
+
+        assert_eq!(
+            metadata.list_keys().await.unwrap(),
+            vec!["ssh-keys", "user-data-json"]
+        );
+        assert_eq!(
+            metadata.list_project_keys().await.unwrap(),
+            vec!["enable-oslogin"]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_azure_list_keys() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/metadata/instance/compute"))
+            .and(header("Metadata", "true"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_string(r#"{"vmId": "abc", "location": "eastus", "tagsList": []}"#),
+            )
+            .mount(&server)
+            .await;
+
+        let metadata = CloudMetadata::azure_with_base_url(&server.uri());
+        let keys = metadata.list_keys().await.unwrap();
+
+        assert_eq!(keys, vec!["location", "tagsList", "vmId"]);
+    }
+
+    #[tokio::test]
+    async fn test_nocloud_list_keys() {
+        let seed = tempfile::tempdir().unwrap();
+        std::fs::write(seed.path().join("meta-data"), "instance-id: iid\n").unwrap();
+        std::fs::write(seed.path().join("user-data"), "#cloud-config\n").unwrap();
+
+        let metadata = CloudMetadata::nocloud_with_path(seed.path());
+        let keys = metadata.list_keys().await.unwrap();
+
+        assert_eq!(keys, vec!["meta-data", "user-data"]);
+    }
+
+    #[tokio::test]
+    async fn test_nocloud_list_keys_missing_seed() {
+        let seed = tempfile::tempdir().unwrap();
+
+        let metadata = CloudMetadata::nocloud_with_path(seed.path().join("missing"));
+        let result = metadata.list_keys().await;
+
+        assert!(matches!(result, Err(MetadataError::NotFound)));
+    }
+
+    #[tokio::test]
+    async fn test_openstack_list_keys() {
+        let drive = tempfile::tempdir().unwrap();
+        let latest = drive.path().join("openstack/latest");
+        std::fs::create_dir_all(&latest).unwrap();
+        std::fs::write(
+            latest.join("meta_data.json"),
+            r#"{"meta": {"role": "worker", "config": "{}"}}"#,
+        )
+        .unwrap();
+
+        let metadata = CloudMetadata::openstack_with_path(drive.path());
+        let keys = metadata.list_keys().await.unwrap();
+
+        assert_eq!(keys, vec!["config", "role"]);
+    }
+
+    #[tokio::test]
+    async fn test_list_project_keys_not_supported() {
+        let metadata = CloudMetadata::aws_with_base_url("http://127.0.0.1:1");
+        let result = metadata.list_project_keys().await;
+
+        assert!(matches!(result, Err(MetadataError::NotSupported)));
+    }
+}
+
+// =============================================================================
+// Raw Path Tests
+// =============================================================================
+
+mod get_path {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_aws_get_path() {
+        let server = MockServer::start().await;
+        setup_aws_token(&server).await;
+        Mock::given(method("GET"))
+            .and(path("/latest/meta-data/placement/partition-number"))
+            .and(header("X-aws-ec2-metadata-token", "mock-token"))
+            .respond_with(ResponseTemplate::new(200).set_body_string("3"))
+            .mount(&server)
+            .await;
+
+        let metadata = CloudMetadata::aws_with_base_url(&server.uri());
+        let data = metadata
+            .get_path("/latest/meta-data/placement/partition-number")
+            .await
+            .unwrap();
+
+        assert_eq!(data, b"3");
+    }
+
+    #[tokio::test]
+    async fn test_gcp_get_path() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/computeMetadata/v1/instance/id"))
+            .and(header("Metadata-Flavor", "Google"))
+            .respond_with(ResponseTemplate::new(200).set_body_string("1234567890"))
+            .mount(&server)
+            .await;
+
+        let metadata = CloudMetadata::gcp_with_base_url(&server.uri());
+        let data = metadata
+            .get_path("/computeMetadata/v1/instance/id")
+            .await
+            .unwrap();
+
+        assert_eq!(data, b"1234567890");
+    }
+
+    #[tokio::test]
+    async fn test_azure_get_path_adds_api_version() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/metadata/instance/compute/vmId"))
+            .and(header("Metadata", "true"))
+            .and(query_param("format", "text"))
+            .and(query_param("api-version", "2020-01-01"))
+            .respond_with(ResponseTemplate::new(200).set_body_string("vm-id"))
+            .mount(&server)
+            .await;
+
+        let metadata =
+            CloudMetadata::azure_with_base_url(&server.uri()).with_azure_api_version("2020-01-01");
+        let data = metadata
+            .get_path("/metadata/instance/compute/vmId?format=text")
+            .await
+            .unwrap();
+
+        assert_eq!(data, b"vm-id");
+    }
+
+    #[tokio::test]
+    async fn test_azure_get_path_keeps_api_version() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/metadata/instance/compute/vmId"))
+            .and(query_param("api-version", "2019-06-01"))
+            .respond_with(ResponseTemplate::new(200).set_body_string("vm-id"))
+            .mount(&server)
+            .await;
+
+        let metadata =
+            CloudMetadata::azure_with_base_url(&server.uri()).with_azure_api_version("2020-01-01");
+        let data = metadata
+            .get_path("/metadata/instance/compute/vmId?format=text&api-version=2019-06-01")
+            .await
+            .unwrap();
+
+        assert_eq!(data, b"vm-id");
+    }
+
+    #[tokio::test]
+    async fn test_azure_get_path_uses_endpoint_api_version() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/metadata/scheduledevents"))
+            .and(query_param("api-version", "2020-07-01"))
+            .respond_with(ResponseTemplate::new(200).set_body_string("{\"Events\":[]}"))
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/metadata/identity/oauth2/token"))
+            .and(query_param("resource", "https://vault.azure.net"))
+            .and(query_param("api-version", "2018-02-01"))
+            .respond_with(ResponseTemplate::new(200).set_body_string("{}"))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let metadata =
+            CloudMetadata::azure_with_base_url(&server.uri()).with_azure_api_version("2020-01-01");
+        metadata
+            .get_path("/metadata/scheduledevents")
+            .await
+            .unwrap();
+        metadata
+            .get_path("/metadata/identity/oauth2/token?resource=https://vault.azure.net")
+            .await
+            .unwrap();
+    }
+
+    /// Paths that would change the host the request goes to.
+    const HOST_CHANGING_PATHS: &[&str] = &["@evil.example/", ".evil.example/"];
+
+    /// Assert that no request reaches `server`.
+    async fn expect_no_requests(server: &MockServer) {
+        Mock::given(wiremock::matchers::any())
+            .respond_with(ResponseTemplate::new(200))
+            .expect(0)
+            .mount(server)
+            .await;
+    }
+
+    #[tokio::test]
+    async fn test_aws_get_path_rejects_relative_path() {
+        let server = MockServer::start().await;
+        expect_no_requests(&server).await;
+
+        let metadata = CloudMetadata::aws_with_base_url(&server.uri());
+        for relative in HOST_CHANGING_PATHS {
+            let result = metadata.get_path(relative).await;
+            assert!(matches!(result, Err(MetadataError::InvalidKey(_))));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_gcp_get_path_rejects_relative_path() {
+        let server = MockServer::start().await;
+        expect_no_requests(&server).await;
+
+        let metadata = CloudMetadata::gcp_with_base_url(&server.uri());
+        for relative in HOST_CHANGING_PATHS {
+            let result = metadata.get_path(relative).await;
+            assert!(matches!(result, Err(MetadataError::InvalidKey(_))));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_azure_get_path_rejects_relative_path() {
+        let server = MockServer::start().await;
+        expect_no_requests(&server).await;
+
+        let metadata = CloudMetadata::azure_with_base_url(&server.uri());
+        for relative in HOST_CHANGING_PATHS {
+            let result = metadata.get_path(relative).await;
+            assert!(matches!(result, Err(MetadataError::InvalidKey(_))));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_get_path_not_found() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/computeMetadata/v1/instance/missing"))
+            .respond_with(ResponseTemplate::new(404))
+            .mount(&server)
+            .await;
+
+        let metadata = CloudMetadata::gcp_with_base_url(&server.uri());
+        let result = metadata
+            .get_path("/computeMetadata/v1/instance/missing")
+            .await;
+
+        assert!(matches!(result, Err(MetadataError::NotFound)));
+    }
+
+    #[tokio::test]
+    async fn test_get_path_respects_max_size() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/computeMetadata/v1/instance/description"))
+            .respond_with(ResponseTemplate::new(200).set_body_string("x".repeat(100)))
+            .mount(&server)
+            .await;
+
+        let metadata = CloudMetadata::gcp_with_base_url(&server.uri()).with_max_size(10);
+        let result = metadata
+            .get_path("/computeMetadata/v1/instance/description")
+            .await;
+
+        assert!(matches!(result, Err(MetadataError::TooLarge(_, 10))));
+    }
+
+    #[tokio::test]
+    async fn test_nocloud_get_path() {
+        let seed = tempfile::tempdir().unwrap();
+        std::fs::write(seed.path().join("network-config"), "version: 2\n").unwrap();
+
+        let metadata = CloudMetadata::nocloud_with_path(seed.path());
+        let data = metadata.get_path("network-config").await.unwrap();
+
+        assert_eq!(data, b"version: 2\n");
+    }
+
+    #[tokio::test]
+    async fn test_local_get_path_rejects_parent_dir() {
+        let root = tempfile::tempdir().unwrap();
+        std::fs::write(root.path().join("secret"), "secret\n").unwrap();
+        let seed = root.path().join("seed");
+        std::fs::create_dir(&seed).unwrap();
+
+        let metadata = CloudMetadata::nocloud_with_path(&seed);
+        let result = metadata.get_path("../secret").await;
+        assert!(matches!(result, Err(MetadataError::NotFound)));
+
+        let metadata = CloudMetadata::openstack_with_path(&seed);
+        let result = metadata.get_path("/openstack/../../secret").await;
+        assert!(matches!(result, Err(MetadataError::NotFound)));
+    }
+}
+
+// =============================================================================
+// AWS Credentials Tests
+// =============================================================================
+
+mod aws_credentials {
+    use super::*;
+
+    use std::time::SystemTime;
+
+    const CREDENTIALS_PATH: &str = "/latest/meta-data/iam/security-credentials/";
+
+    fn credentials_document(expiration: &str) -> String {
+        format!(
+            r#"{{"Code": "Success", "LastUpdated": "2024-05-01T06:00:00Z", "Type": "AWS-HMAC",
+                "AccessKeyId": "ASIAEXAMPLE", "SecretAccessKey": "secret-key",
+                "Token": "session-token", "Expiration": "{}"}}"#,
+            expiration
+        )
+    }
+
+    #[tokio::test]
+    async fn test_discovers_role_and_caches() {
+        let server = MockServer::start().await;
+        setup_aws_token(&server).await;
+        Mock::given(method("GET"))
+            .and(path(CREDENTIALS_PATH))
+            .and(header("X-aws-ec2-metadata-token", "mock-token"))
+            .respond_with(ResponseTemplate::new(200).set_body_string("my-role\n"))
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path(format!("{}my-role", CREDENTIALS_PATH)))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_string(credentials_document("2999-01-01T00:00:00Z")),
+            )
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let metadata = CloudMetadata::aws_with_base_url(&server.uri());
+        let provider = metadata.aws_credential_provider().unwrap();
+        let credentials = provider.credentials().await.unwrap();
+
+        assert_eq!(credentials.role, "my-role");
+        assert_eq!(credentials.access_key_id, "ASIAEXAMPLE");
+        assert_eq!(credentials.secret_access_key, "secret-key");
+        assert_eq!(credentials.token, "session-token");
+        assert!(credentials.expiration > SystemTime::now());
+
+        // Served from the cache, also for clones
+        let cached = provider.clone().credentials().await.unwrap();
+        assert_eq!(cached, credentials);
+    }
+
+    #[tokio::test]
+    async fn test_refreshes_before_expiry() {
+        let server = MockServer::start().await;
+        setup_aws_token(&server).await;
+        Mock::given(method("GET"))
+            .and(path(format!("{}my-role", CREDENTIALS_PATH)))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_string(credentials_document("2000-01-01T00:00:00Z")),
+            )
+            .expect(2)
+            .mount(&server)
+            .await;
+
+        let metadata = CloudMetadata::aws_with_base_url(&server.uri());
+        let provider = metadata
+            .aws_credential_provider()
+            .unwrap()
+            .with_role("my-role");
+
+        provider.credentials().await.unwrap();
+        provider.credentials().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_role_is_encoded() {
+        let server = MockServer::start().await;
+        setup_aws_token(&server).await;
+        Mock::given(method("GET"))
+            .and(path(format!("{}..%2Fmy%20role", CREDENTIALS_PATH)))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_string(credentials_document("2999-01-01T00:00:00Z")),
+            )
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let metadata = CloudMetadata::aws_with_base_url(&server.uri());
+        let credentials = metadata
+            .aws_credential_provider()
+            .unwrap()
+            .with_role("../my role")
+            .credentials()
+            .await
+            .unwrap();
+
+        assert_eq!(credentials.role, "../my role");
+    }
+
+    #[tokio::test]
+    async fn test_no_instance_profile() {
+        let server = MockServer::start().await;
+        setup_aws_token(&server).await;
+        Mock::given(method("GET"))
+            .and(path(CREDENTIALS_PATH))
+            .respond_with(ResponseTemplate::new(404))
+            .mount(&server)
+            .await;
+
+        let metadata = CloudMetadata::aws_with_base_url(&server.uri());
+        let result = metadata
+            .aws_credential_provider()
+            .unwrap()
+            .credentials()
+            .await;
+
+        assert!(matches!(result, Err(MetadataError::NotFound)));
+    }
+
+    #[tokio::test]
+    async fn test_failure_code() {
+        let server = MockServer::start().await;
+        setup_aws_token(&server).await;
+        Mock::given(method("GET"))
+            .and(path(format!("{}my-role", CREDENTIALS_PATH)))
+            .respond_with(ResponseTemplate::new(200).set_body_string(
+                r#"{"Code": "AssumeRoleUnauthorizedAccess", "Message": "not authorized"}"#,
+            ))
+            .mount(&server)
+            .await;
+
+        let metadata = CloudMetadata::aws_with_base_url(&server.uri());
+        let result = metadata
+            .aws_credential_provider()
+            .unwrap()
+            .with_role("my-role")
+            .credentials()
+            .await;
+
+        assert!(matches!(result, Err(MetadataError::Credentials(msg)) if msg == "not authorized"));
+    }
+
+    #[test]
+    fn test_not_supported() {
+        let metadata = CloudMetadata::gcp_with_base_url("http://127.0.0.1:1");
+
+        assert!(matches!(
+            metadata.aws_credential_provider(),
+            Err(MetadataError::NotSupported)
+        ));
+    }
+}
+
+// =============================================================================
+// GCP Token Tests
+// =============================================================================
+
+mod gcp_tokens {
+    use super::*;
+
+    use std::time::{Duration, SystemTime};
+
+    const ACCOUNTS_PATH: &str = "/computeMetadata/v1/instance/service-accounts";
+
+    /// Build an unsigned JWT with the given `exp` claim.
+    fn jwt(exp: u64) -> String {
+        use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+        format!(
+            "{}.{}.c2ln",
+            URL_SAFE_NO_PAD.encode(r#"{"alg":"RS256"}"#),
+            URL_SAFE_NO_PAD.encode(format!(r#"{{"aud":"https://svc","exp":{}}}"#, exp))
+        )
+    }
+
+    #[tokio::test]
+    async fn test_access_token_cached_per_scopes() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path(format!("{}/default/token", ACCOUNTS_PATH)))
+            .and(header("Metadata-Flavor", "Google"))
+            .and(query_param("scopes", "scope-a,scope-b"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(
+                r#"{"access_token": "ya29.scoped", "expires_in": 3599, "token_type": "Bearer"}"#,
+            ))
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path(format!("{}/default/token", ACCOUNTS_PATH)))
+            .respond_with(ResponseTemplate::new(200).set_body_string(
+                r#"{"access_token": "ya29.default", "expires_in": 3599, "token_type": "Bearer"}"#,
+            ))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let metadata = CloudMetadata::gcp_with_base_url(&server.uri());
+        let provider = metadata.gcp_token_provider().unwrap();
+
+        let token = provider.access_token(&[]).await.unwrap();
+        assert_eq!(token.token, "ya29.default");
+        assert!(token.expires_at > SystemTime::now() + Duration::from_secs(3000));
+
+        let scoped = provider
+            .access_token(&["scope-a", "scope-b"])
+            .await
+            .unwrap();
+        assert_eq!(scoped.token, "ya29.scoped");
+
+        // Both served from the cache
+        provider.access_token(&[]).await.unwrap();
+        provider
+            .access_token(&["scope-a", "scope-b"])
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_access_token_refreshed_near_expiry() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path(format!(
+                "{}/sa%40project.iam.gserviceaccount.com/token",
+                ACCOUNTS_PATH
+            )))
+            .respond_with(ResponseTemplate::new(200).set_body_string(
+                r#"{"access_token": "ya29.short", "expires_in": 30, "token_type": "Bearer"}"#,
+            ))
+            .expect(2)
+            .mount(&server)
+            .await;
+
+        let metadata = CloudMetadata::gcp_with_base_url(&server.uri());
+        let provider = metadata
+            .gcp_token_provider()
+            .unwrap()
+            .with_account("sa@project.iam.gserviceaccount.com");
+
+        provider.access_token(&[]).await.unwrap();
+        provider.access_token(&[]).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_account_is_encoded() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path(format!("{}/..%2Fattributes%2F/token", ACCOUNTS_PATH)))
+            .respond_with(ResponseTemplate::new(200).set_body_string(
+                r#"{"access_token": "ya29.token", "expires_in": 3600, "token_type": "Bearer"}"#,
+            ))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let metadata = CloudMetadata::gcp_with_base_url(&server.uri());
+        let provider = metadata
+            .gcp_token_provider()
+            .unwrap()
+            .with_account("../attributes/");
+
+        provider.access_token(&[]).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_identity_token() {
+        let exp = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap()
+            .as_secs()
+            + 3600;
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path(format!("{}/default/identity", ACCOUNTS_PATH)))
+            .and(header("Metadata-Flavor", "Google"))
+            .and(query_param("audience", "https://svc"))
+            .and(query_param("format", "full"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(jwt(exp)))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let metadata = CloudMetadata::gcp_with_base_url(&server.uri());
+        let provider = metadata.gcp_token_provider().unwrap();
+
+        let token = provider.identity_token("https://svc").await.unwrap();
+        assert_eq!(token.token, jwt(exp));
+        assert_eq!(
+            token.expires_at,
+            SystemTime::UNIX_EPOCH + Duration::from_secs(exp)
+        );
+
+        let cached = provider.identity_token("https://svc").await.unwrap();
+        assert_eq!(cached, token);
+    }
+
+    #[tokio::test]
+    async fn test_service_accounts() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path(format!("{}/", ACCOUNTS_PATH)))
+            .and(query_param("recursive", "true"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(
+                r#"{
+                    "123-compute@developer.gserviceaccount.com": {
+                        "aliases": ["default"],
+                        "email": "123-compute@developer.gserviceaccount.com",
+                        "scopes": ["https://www.googleapis.com/auth/cloud-platform"]
+                    },
+                    "default": {
+                        "aliases": ["default"],
+                        "email": "123-compute@developer.gserviceaccount.com",
+                        "scopes": ["https://www.googleapis.com/auth/cloud-platform"]
+                    }
+                }"#,
+            ))
+            .mount(&server)
+            .await;
+
+        let metadata = CloudMetadata::gcp_with_base_url(&server.uri());
+        let accounts = metadata.gcp_service_accounts().await.unwrap();
+
+        assert_eq!(accounts.len(), 1);
+        assert_eq!(
+            accounts[0].email,
+            "123-compute@developer.gserviceaccount.com"
+        );
+        assert_eq!(accounts[0].aliases, vec!["default"]);
+        assert_eq!(
+            accounts[0].scopes,
+            vec!["https://www.googleapis.com/auth/cloud-platform"]
+        );
+    }
+
+    #[test]
+    fn test_not_supported() {
+        let metadata = CloudMetadata::aws_with_base_url("http://127.0.0.1:1");
+
+        assert!(matches!(
+            metadata.gcp_token_provider(),
+            Err(MetadataError::NotSupported)
+        ));
+    }
+}
+
+// =============================================================================
+// Azure Managed Identity Tests
+// =============================================================================
+
+mod azure_tokens {
+    use super::*;
+
+    use std::time::{Duration, SystemTime};
+
+    use cloud_metadata::AzureIdentity;
+
+    const TOKEN_PATH: &str = "/metadata/identity/oauth2/token";
+
+    fn token_response(token: &str, expires_on: u64) -> String {
+        format!(
+            r#"{{"access_token": "{}", "refresh_token": "", "expires_in": "3599",
+                "expires_on": "{}", "not_before": "0",
+                "resource": "https://management.azure.com/", "token_type": "Bearer"}}"#,
+            token, expires_on
+        )
+    }
+
+    fn now() -> u64 {
+        SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap()
+            .as_secs()
+    }
+
+    #[tokio::test]
+    async fn test_token_cached_per_resource() {
+        let expires_on = now() + 3600;
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path(TOKEN_PATH))
+            .and(header("Metadata", "true"))
+            .and(query_param("api-version", "2018-02-01"))
+            .and(query_param("resource", "https://management.azure.com/"))
+            .respond_with(
+                ResponseTemplate::new(200).set_body_string(token_response("eyJ0eXAi", expires_on)),
+            )
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let metadata = CloudMetadata::azure_with_base_url(&server.uri());
+        let provider = metadata.azure_token_provider().unwrap();
+
+        let token = provider
+            .token("https://management.azure.com/")
+            .await
+            .unwrap();
+        assert_eq!(token.token, "eyJ0eXAi");
+        assert_eq!(token.resource, "https://management.azure.com/");
+        assert_eq!(
+            token.expires_at,
+            SystemTime::UNIX_EPOCH + Duration::from_secs(expires_on)
+        );
+
+        let cached = provider
+            .token("https://management.azure.com/")
+            .await
+            .unwrap();
+        assert_eq!(cached, token);
+    }
+
+    #[tokio::test]
+    async fn test_token_refreshed_near_expiry() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path(TOKEN_PATH))
+            .respond_with(
+                ResponseTemplate::new(200).set_body_string(token_response("eyJ0eXAi", now() + 60)),
+            )
+            .expect(2)
+            .mount(&server)
+            .await;
+
+        let metadata = CloudMetadata::azure_with_base_url(&server.uri());
+        let provider = metadata.azure_token_provider().unwrap();
+
+        provider.token("https://vault.azure.net").await.unwrap();
+        provider.token("https://vault.azure.net").await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_user_assigned_identity() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path(TOKEN_PATH))
+            .and(query_param("client_id", "11111111-2222"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_string(token_response("user-token", now() + 3600)),
+            )
+            .mount(&server)
+            .await;
+
+        let metadata = CloudMetadata::azure_with_base_url(&server.uri());
+        let token = metadata
+            .azure_token_provider()
+            .unwrap()
+            .with_identity(AzureIdentity::ClientId("11111111-2222".to_string()))
+            .token("https://management.azure.com/")
+            .await
+            .unwrap();
+
+        assert_eq!(token.token, "user-token");
+    }
+
+    #[tokio::test]
+    async fn test_identity_not_found() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path(TOKEN_PATH))
+            .respond_with(ResponseTemplate::new(400).set_body_string(
+                r#"{"error": "invalid_request", "error_description": "Identity not found"}"#,
+            ))
+            .mount(&server)
+            .await;
+
+        let metadata = CloudMetadata::azure_with_base_url(&server.uri());
+        let result = metadata
+            .azure_token_provider()
+            .unwrap()
+            .token("https://management.azure.com/")
+            .await;
+
+        assert!(
+            matches!(result, Err(MetadataError::Credentials(msg)) if msg == "Identity not found")
+        );
+    }
+
+    #[test]
+    fn test_not_supported() {
+        let metadata = CloudMetadata::gcp_with_base_url("http://127.0.0.1:1");
+
+        assert!(matches!(
+            metadata.azure_token_provider(),
+            Err(MetadataError::NotSupported)
+        ));
+    }
+}
+
+// =============================================================================
+// Signed Identity Document Tests
+// =============================================================================
+
+mod signed_identity {
+    use super::*;
+
+    const AWS_DOCUMENT: &str = include_str!("fixtures/verify/aws-document.json");
+    const AWS_SIGNATURE: &str = include_str!("fixtures/verify/aws-rsa2048");
+
+    #[tokio::test]
+    async fn test_aws_identity_document() {
+        let server = MockServer::start().await;
+        setup_aws_token(&server).await;
+        Mock::given(method("GET"))
+            .and(path("/latest/dynamic/instance-identity/document"))
+            .and(header("X-aws-ec2-metadata-token", "mock-token"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(AWS_DOCUMENT))
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/latest/dynamic/instance-identity/rsa2048"))
+            .and(header("X-aws-ec2-metadata-token", "mock-token"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(AWS_SIGNATURE))
+            .mount(&server)
+            .await;
+
+        let metadata = CloudMetadata::aws_with_base_url(&server.uri());
+        let signed = metadata.aws_identity_document().await.unwrap();
+
+        assert_eq!(signed.raw, AWS_DOCUMENT);
+        assert_eq!(signed.signature, AWS_SIGNATURE);
+        assert_eq!(signed.document.instance_id, "i-1234567890abcdef0");
+        assert_eq!(signed.document.account_id, "123456789012");
+        assert_eq!(signed.document.private_ip.as_deref(), Some("10.0.0.1"));
+
+        #[cfg(feature = "verify")]
+        {
+            let cert = include_str!("fixtures/verify/aws-cert.pem");
+            let verified =
+                cloud_metadata::verify::aws_identity_document(&signed.signature, &[cert]).unwrap();
+            assert_eq!(verified, signed.document);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_azure_attested_document() {
+        let signature = include_str!("fixtures/verify/azure-attested-chain");
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/metadata/attested/document"))
+            .and(header("Metadata", "true"))
+            .and(query_param("nonce", "1234567890"))
+            .and(query_param("api-version", "2021-02-01"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(format!(
+                r#"{{"encoding": "pkcs7", "signature": "{}"}}"#,
+                signature
+            )))
+            .mount(&server)
+            .await;
+
+        let metadata =
+            CloudMetadata::azure_with_base_url(&server.uri()).with_azure_api_version("2021-02-01");
+        let document = metadata
+            .azure_attested_document("1234567890")
+            .await
+            .unwrap();
+
+        assert_eq!(document.encoding, "pkcs7");
+        assert_eq!(document.signature, signature);
+
+        #[cfg(feature = "verify")]
+        {
+            let root = include_str!("fixtures/verify/azure-root.pem");
+            let data = cloud_metadata::verify::azure_attested_document(
+                &document.signature,
+                "1234567890",
+                &[root],
+                &[],
+            )
+            .unwrap();
+            assert_eq!(data.vm_id, "d3e0e374-fda6-4649-bbc9-7f20dcd2f213");
+            assert_eq!(data.sku, "22_04-lts-gen2");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_gcp_identity_token() {
+        let token = include_str!("fixtures/verify/gcp-identity-token");
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path(
+                "/computeMetadata/v1/instance/service-accounts/default/identity",
+            ))
+            .and(query_param("audience", "https://svc.example.com"))
+            .and(query_param("format", "full"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(token))
+            .mount(&server)
+            .await;
+
+        let metadata = CloudMetadata::gcp_with_base_url(&server.uri());
+        let identity = metadata
+            .gcp_token_provider()
+            .unwrap()
+            .identity_token("https://svc.example.com")
+            .await
+            .unwrap();
+
+        assert_eq!(identity.token, token);
+
+        #[cfg(feature = "verify")]
+        {
+            let jwks = include_str!("fixtures/verify/gcp-jwks.json");
+            let claims = cloud_metadata::verify::gcp_identity_token(
+                &identity.token,
+                jwks,
+                "https://svc.example.com",
+            )
+            .unwrap();
+            let instance = claims.compute_engine.unwrap();
+            assert_eq!(instance.instance_id, "5678901234567890");
+            assert_eq!(instance.project_id, "my-project");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_aws_identity_document_not_supported() {
+        let metadata = CloudMetadata::gcp_with_base_url("http://127.0.0.1:1");
+        let result = metadata.aws_identity_document().await;
+
+        assert!(matches!(result, Err(MetadataError::NotSupported)));
+    }
+}
+
+// =============================================================================
+// Watch Tests
+// =============================================================================
+
+mod watch {
+    use super::*;
+
+    use std::time::Duration;
+
+    use wiremock::matchers::query_param_is_missing;
+
+    const ATTRIBUTE_PATH: &str = "/computeMetadata/v1/instance/attributes/config";
+
+    fn config(port: u16) -> String {
+        format!(r#"{{"db_host": "postgres.internal", "port": {}}}"#, port)
+    }
+
+    /// Serve `body` with `etag` for a GCP attribute request waiting on `last_etag`.
+    async fn mount_gcp_change(
+        server: &MockServer,
+        last_etag: Option<&str>,
+        body: &str,
+        etag: &str,
+    ) {
+        let mock = Mock::given(method("GET")).and(path(ATTRIBUTE_PATH));
+        let mock = match last_etag {
+            Some(last_etag) => mock
+                .and(query_param("wait_for_change", "true"))
+                .and(query_param("last_etag", last_etag)),
+            None => mock.and(query_param_is_missing("wait_for_change")),
+        };
+        mock.respond_with(
+            ResponseTemplate::new(200)
+                .insert_header("ETag", etag)
+                .set_body_string(body),
+        )
+        .mount(server)
+        .await;
+    }
+
+    /// Hold requests waiting on `etag` open, as when nothing changes.
+    async fn mount_gcp_unchanged(server: &MockServer, etag: &str) {
+        Mock::given(method("GET"))
+            .and(path(ATTRIBUTE_PATH))
+            .and(query_param("last_etag", etag))
+            .respond_with(ResponseTemplate::new(200).set_delay(Duration::from_secs(60)))
+            .mount(server)
+            .await;
+    }
+
+    async fn next_port(
+        rx: &mut tokio::sync::watch::Receiver<Result<TestConfig, MetadataError>>,
+    ) -> u16 {
+        tokio::time::timeout(Duration::from_secs(5), rx.changed())
+            .await
+            .unwrap()
+            .unwrap();
+        rx.borrow_and_update().as_ref().unwrap().port
+    }
+
+    #[tokio::test]
+    async fn test_gcp_watch() {
+        let server = MockServer::start().await;
+        mount_gcp_change(&server, None, &config(5432), "etag-1").await;
+        mount_gcp_change(&server, Some("etag-1"), &config(5433), "etag-2").await;
+        mount_gcp_unchanged(&server, "etag-2").await;
+
+        let metadata = CloudMetadata::gcp_with_base_url(&server.uri());
+        let mut rx = metadata.watch::<TestConfig>("config").await.unwrap();
+
+        assert_eq!(rx.borrow_and_update().as_ref().unwrap().port, 5432);
+        assert_eq!(next_port(&mut rx).await, 5433);
+    }
+
+    #[tokio::test]
+    async fn test_gcp_watch_decode_error() {
+        let server = MockServer::start().await;
+        mount_gcp_change(&server, None, "not json", "etag-1").await;
+        mount_gcp_change(&server, Some("etag-1"), &config(5432), "etag-2").await;
+        mount_gcp_unchanged(&server, "etag-2").await;
+
+        let metadata = CloudMetadata::gcp_with_base_url(&server.uri());
+        let mut rx = metadata.watch::<TestConfig>("config").await.unwrap();
+
+        assert!(matches!(
+            *rx.borrow_and_update(),
+            Err(MetadataError::Json(_))
+        ));
+        assert_eq!(next_port(&mut rx).await, 5432);
+    }
+
+    #[tokio::test]
+    async fn test_gcp_watch_missing_key() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path(ATTRIBUTE_PATH))
+            .respond_with(ResponseTemplate::new(404))
+            .up_to_n_times(1)
+            .mount(&server)
+            .await;
+        mount_gcp_change(&server, None, &config(5432), "etag-1").await;
+        mount_gcp_unchanged(&server, "etag-1").await;
+
+        let metadata = CloudMetadata::gcp_with_base_url(&server.uri())
+            .with_watch_interval(Duration::from_millis(10));
+        let mut rx = metadata.watch::<TestConfig>("config").await.unwrap();
+
+        assert!(matches!(
+            *rx.borrow_and_update(),
+            Err(MetadataError::NotFound)
+        ));
+        assert_eq!(next_port(&mut rx).await, 5432);
+    }
+
+    #[tokio::test]
+    async fn test_aws_watch_polls() {
+        let server = MockServer::start().await;
+        setup_aws_token(&server).await;
+        Mock::given(method("GET"))
+            .and(path("/latest/user-data"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(config(5432)))
+            .up_to_n_times(3)
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/latest/user-data"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(config(5433)))
+            .mount(&server)
+            .await;
+
+        let metadata = CloudMetadata::aws_with_base_url(&server.uri())
+            .with_watch_interval(Duration::from_millis(10));
+        let mut rx = metadata.watch::<TestConfig>("ignored").await.unwrap();
+
+        assert_eq!(rx.borrow_and_update().as_ref().unwrap().port, 5432);
+        assert_eq!(next_port(&mut rx).await, 5433);
+    }
+
+    #[tokio::test]
+    async fn test_aws_watch_request_error() {
+        let server = MockServer::start().await;
+        setup_aws_token(&server).await;
+        Mock::given(method("GET"))
+            .and(path("/latest/user-data"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(config(5432)))
+            .up_to_n_times(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/latest/user-data"))
+            .respond_with(ResponseTemplate::new(403))
+            .up_to_n_times(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/latest/user-data"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(config(5432)))
+            .mount(&server)
+            .await;
+
+        let metadata = CloudMetadata::aws_with_base_url(&server.uri())
+            .with_watch_interval(Duration::from_millis(10));
+        let mut rx = metadata.watch::<TestConfig>("ignored").await.unwrap();
+        assert_eq!(rx.borrow_and_update().as_ref().unwrap().port, 5432);
+
+        tokio::time::timeout(Duration::from_secs(5), rx.changed())
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(matches!(
+            *rx.borrow_and_update(),
+            Err(MetadataError::Http(403))
+        ));
+        // The unchanged value is delivered again once the request succeeds
+        assert_eq!(next_port(&mut rx).await, 5432);
+    }
+
+    #[tokio::test]
+    async fn test_watch_initial_request_fails() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path(ATTRIBUTE_PATH))
+            .respond_with(ResponseTemplate::new(500))
+            .mount(&server)
+            .await;
+
+        let metadata = CloudMetadata::gcp_with_base_url(&server.uri());
+        let result = metadata.watch::<TestConfig>("config").await;
+
+        assert!(matches!(result, Err(MetadataError::Http(500))));
+    }
+}
+
+// =============================================================================
+// Lifecycle Event Tests
+// =============================================================================
+
+mod lifecycle_events {
+    use super::*;
+
+    use std::time::{Duration, SystemTime};
+
+    use cloud_metadata::{LifecycleEvent, LifecycleEventKind};
+    use tokio::sync::mpsc;
+    use wiremock::matchers::{body_json, query_param_is_missing};
+
+    async fn next_event(
+        rx: &mut mpsc::Receiver<Result<LifecycleEvent, MetadataError>>,
+    ) -> Result<LifecycleEvent, MetadataError> {
+        tokio::time::timeout(Duration::from_secs(5), rx.recv())
+            .await
+            .unwrap()
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_aws_spot_interruption() {
+        let server = MockServer::start().await;
+        setup_aws_token(&server).await;
+        Mock::given(method("GET"))
+            .and(path("/latest/meta-data/spot/instance-action"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_string(r#"{"action": "terminate", "time": "2017-09-18T08:22:00Z"}"#),
+            )
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/latest/meta-data/autoscaling/target-lifecycle-state"))
+            .respond_with(ResponseTemplate::new(200).set_body_string("InService"))
+            .mount(&server)
+            .await;
+
+        let metadata = CloudMetadata::aws_with_base_url(&server.uri())
+            .with_lifecycle_interval(Duration::from_millis(10));
+        let mut rx = metadata.lifecycle_events().unwrap();
+
+        let event = next_event(&mut rx).await.unwrap();
+        assert_eq!(event.kind, LifecycleEventKind::Preemption);
+        assert_eq!(
+            event.deadline,
+            Some(SystemTime::UNIX_EPOCH + Duration::from_secs(1_505_722_920))
+        );
+        assert!(event.raw.contains("terminate"));
+
+        // Later polls return the same notice, which is not delivered again
+        let next = tokio::time::timeout(Duration::from_millis(100), rx.recv()).await;
+        assert!(next.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_aws_failed_source_keeps_others() {
+        let server = MockServer::start().await;
+        setup_aws_token(&server).await;
+        Mock::given(method("GET"))
+            .and(path("/latest/meta-data/spot/instance-action"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_string(r#"{"action": "stop", "time": "2017-09-18T08:22:00Z"}"#),
+            )
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/latest/meta-data/events/maintenance/scheduled"))
+            .respond_with(ResponseTemplate::new(200).set_body_string("not json"))
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/latest/meta-data/autoscaling/target-lifecycle-state"))
+            .respond_with(ResponseTemplate::new(500))
+            .mount(&server)
+            .await;
+
+        let metadata = CloudMetadata::aws_with_base_url(&server.uri())
+            .with_lifecycle_interval(Duration::from_secs(60));
+        let mut rx = metadata.lifecycle_events().unwrap();
+
+        let event = next_event(&mut rx).await.unwrap();
+        assert_eq!(event.kind, LifecycleEventKind::Preemption);
+        assert!(matches!(
+            next_event(&mut rx).await,
+            Err(MetadataError::Json(_))
+        ));
+        assert!(matches!(
+            next_event(&mut rx).await,
+            Err(MetadataError::Http(500))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_gcp_preemption() {
+        let server = MockServer::start().await;
+        let respond = |body: &str, etag: &str| {
+            ResponseTemplate::new(200)
+                .insert_header("ETag", etag)
+                .set_body_string(body)
+        };
+        let preempted = "/computeMetadata/v1/instance/preempted";
+        let maintenance = "/computeMetadata/v1/instance/maintenance-event";
+        Mock::given(method("GET"))
+            .and(path(preempted))
+            .and(query_param_is_missing("last_etag"))
+            .respond_with(respond("FALSE", "etag-1"))
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path(preempted))
+            .and(query_param("wait_for_change", "true"))
+            .and(query_param("last_etag", "etag-1"))
+            .respond_with(respond("TRUE", "etag-2"))
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path(maintenance))
+            .and(query_param_is_missing("last_etag"))
+            .respond_with(respond("NONE", "etag-a"))
+            .mount(&server)
+            .await;
+        // Nothing changes after that
+        Mock::given(method("GET"))
+            .and(query_param("wait_for_change", "true"))
+            .respond_with(ResponseTemplate::new(200).set_delay(Duration::from_secs(60)))
+            .mount(&server)
+            .await;
+
+        let metadata = CloudMetadata::gcp_with_base_url(&server.uri());
+        let mut rx = metadata.lifecycle_events().unwrap();
+
+        let event = next_event(&mut rx).await.unwrap();
+        assert_eq!(event.kind, LifecycleEventKind::Preemption);
+        assert_eq!(event.raw, "TRUE");
+        assert_eq!(event.deadline, None);
+    }
+
+    #[tokio::test]
+    async fn test_azure_scheduled_event_ack() {
+        let server = MockServer::start().await;
+        let event_id = "602d9444-d2cd-49c7-8624-8643e7171297";
+        let document = serde_json::json!({
+            "DocumentIncarnation": 1,
+            "Events": [{
+                "EventId": event_id,
+                "EventStatus": "Scheduled",
+                "EventType": "Reboot",
+                "ResourceType": "VirtualMachine",
+                "Resources": ["vm-1"],
+                "NotBefore": "Mon, 19 Sep 2016 18:29:47 GMT",
+                "Description": "",
+                "EventSource": "Platform",
+                "DurationInSeconds": -1
+            }]
+        });
+        Mock::given(method("GET"))
+            .and(path("/metadata/scheduledevents"))
+            .and(query_param("api-version", "2020-07-01"))
+            .and(header("Metadata", "true"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(document))
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/metadata/scheduledevents"))
+            .and(query_param("api-version", "2020-07-01"))
+            .and(header("Metadata", "true"))
+            .and(body_json(serde_json::json!({
+                "StartRequests": [{"EventId": event_id}]
+            })))
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let metadata = CloudMetadata::azure_with_base_url(&server.uri());
+        let mut rx = metadata.lifecycle_events().unwrap();
+
+        let event = next_event(&mut rx).await.unwrap();
+        assert_eq!(event.kind, LifecycleEventKind::Reboot);
+        assert_eq!(event.id.as_deref(), Some(event_id));
+        assert_eq!(
+            event.deadline,
+            Some(SystemTime::UNIX_EPOCH + Duration::from_secs(1_474_309_787))
+        );
+
+        metadata.azure_ack_event(&event).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_azure_event_delivered_again_after_it_was_gone() {
+        let server = MockServer::start().await;
+        let pending = serde_json::json!({
+            "DocumentIncarnation": 1,
+            "Events": [{"EventId": "event-1", "EventType": "Reboot", "NotBefore": ""}]
+        });
+        let empty = serde_json::json!({"DocumentIncarnation": 2, "Events": []});
+        for document in [&pending, &empty] {
+            Mock::given(method("GET"))
+                .and(path("/metadata/scheduledevents"))
+                .respond_with(ResponseTemplate::new(200).set_body_json(document))
+                .up_to_n_times(1)
+                .mount(&server)
+                .await;
+        }
+        Mock::given(method("GET"))
+            .and(path("/metadata/scheduledevents"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(&pending))
+            .mount(&server)
+            .await;
+
+        let metadata = CloudMetadata::azure_with_base_url(&server.uri())
+            .with_lifecycle_interval(Duration::from_millis(10));
+        let mut rx = metadata.lifecycle_events().unwrap();
+
+        for _ in 0..2 {
+            let event = next_event(&mut rx).await.unwrap();
+            assert_eq!(event.id.as_deref(), Some("event-1"));
+        }
+        // Still pending, so not delivered a third time
+        let next = tokio::time::timeout(Duration::from_millis(100), rx.recv()).await;
+        assert!(next.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_azure_scheduled_events_outlast_client_timeout() {
+        let server = MockServer::start().await;
+        let document = serde_json::json!({
+            "DocumentIncarnation": 1,
+            "Events": [{
+                "EventId": "event-1",
+                "EventType": "Freeze",
+                "NotBefore": ""
+            }]
+        });
+        // Longer than the 5 s client timeout, as when the service is enabled
+        Mock::given(method("GET"))
+            .and(path("/metadata/scheduledevents"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_json(document)
+                    .set_delay(Duration::from_secs(6)),
+            )
+            .mount(&server)
+            .await;
+
+        let metadata = CloudMetadata::azure_with_base_url(&server.uri());
+        let mut rx = metadata.lifecycle_events().unwrap();
+
+        let event = tokio::time::timeout(Duration::from_secs(10), rx.recv())
+            .await
+            .unwrap()
+            .unwrap()
+            .unwrap();
+        assert_eq!(event.kind, LifecycleEventKind::Maintenance);
+    }
+
+    #[tokio::test]
+    async fn test_ack_not_supported() {
+        let metadata = CloudMetadata::aws_with_base_url("http://localhost:1234");
+        let event = LifecycleEvent {
+            kind: LifecycleEventKind::Reboot,
+            id: Some("instance-event-1".to_string()),
+            deadline: None,
+            raw: String::new(),
+        };
+
+        let result = metadata.azure_ack_event(&event).await;
+        assert!(matches!(result, Err(MetadataError::NotSupported)));
+    }
+
+    #[tokio::test]
+    async fn test_lifecycle_events_not_supported() {
+        let metadata = CloudMetadata::nocloud_with_path("/nonexistent");
+        let result = metadata.lifecycle_events();
+        assert!(matches!(result, Err(MetadataError::NotSupported)));
+    }
+}
+
+// =============================================================================
+// GCP Guest Attribute Tests
+// =============================================================================
+
+mod gcp_guest_attributes {
+    use super::*;
+
+    use wiremock::matchers::body_string;
+
+    const GUEST_ATTRIBUTES_PATH: &str = "/computeMetadata/v1/instance/guest-attributes";
+
+    #[tokio::test]
+    async fn test_set_guest_attribute() {
+        let server = MockServer::start().await;
+        Mock::given(method("PUT"))
+            .and(path(format!("{}/bootstrap/status", GUEST_ATTRIBUTES_PATH)))
+            .and(header("Metadata-Flavor", "Google"))
+            .and(body_string("ready"))
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let metadata =
+            CloudMetadata::gcp_with_base_url(&server.uri()).with_gcp_guest_attributes(true);
+        let attributes = metadata.gcp_guest_attributes().unwrap();
+
+        attributes
+            .set("bootstrap", "status", "ready")
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_get_and_list_guest_attributes() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path(format!("{}/bootstrap/status", GUEST_ATTRIBUTES_PATH)))
+            .and(header("Metadata-Flavor", "Google"))
+            .respond_with(ResponseTemplate::new(200).set_body_string("ready"))
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path(format!("{}/bootstrap/", GUEST_ATTRIBUTES_PATH)))
+            .and(header("Metadata-Flavor", "Google"))
+            .respond_with(ResponseTemplate::new(200).set_body_string("status\nversion\n"))
+            .mount(&server)
+            .await;
+
+        let metadata =
+            CloudMetadata::gcp_with_base_url(&server.uri()).with_gcp_guest_attributes(true);
+        let attributes = metadata.gcp_guest_attributes().unwrap();
+
+        assert_eq!(
+            attributes.get("bootstrap", "status").await.unwrap(),
+            "ready"
+        );
+        assert_eq!(
+            attributes.list("bootstrap").await.unwrap(),
+            vec!["status", "version"]
+        );
+        assert!(matches!(
+            attributes.get("bootstrap", "missing").await,
+            Err(MetadataError::NotFound)
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_set_guest_attribute_disabled() {
+        let server = MockServer::start().await;
+        Mock::given(method("PUT"))
+            .respond_with(ResponseTemplate::new(403))
+            .mount(&server)
+            .await;
+
+        let metadata =
+            CloudMetadata::gcp_with_base_url(&server.uri()).with_gcp_guest_attributes(true);
+        let result = metadata
+            .gcp_guest_attributes()
+            .unwrap()
+            .set("bootstrap", "status", "ready")
+            .await;
+
+        assert!(matches!(result, Err(MetadataError::Http(403))));
+    }
+
+    #[tokio::test]
+    async fn test_guest_attributes_invalid_names() {
+        let server = MockServer::start().await;
+        Mock::given(method("PUT"))
+            .respond_with(ResponseTemplate::new(200))
+            .expect(0)
+            .mount(&server)
+            .await;
+
+        let metadata =
+            CloudMetadata::gcp_with_base_url(&server.uri()).with_gcp_guest_attributes(true);
+        let attributes = metadata.gcp_guest_attributes().unwrap();
+
+        assert!(matches!(
+            attributes.set("../project", "status", "ready").await,
+            Err(MetadataError::InvalidKey(_))
+        ));
+        assert!(matches!(
+            attributes.set("bootstrap", "status?x=1", "ready").await,
+            Err(MetadataError::InvalidKey(_))
+        ));
+        assert!(matches!(
+            attributes.list("").await,
+            Err(MetadataError::InvalidKey(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_get_guest_attribute_max_size() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path(format!("{}/bootstrap/status", GUEST_ATTRIBUTES_PATH)))
+            .respond_with(ResponseTemplate::new(200).set_body_string("x".repeat(100)))
+            .mount(&server)
+            .await;
+
+        let metadata = CloudMetadata::gcp_with_base_url(&server.uri())
+            .with_gcp_guest_attributes(true)
+            .with_max_size(10);
+        let result = metadata
+            .gcp_guest_attributes()
+            .unwrap()
+            .get("bootstrap", "status")
+            .await;
+
+        assert!(matches!(result, Err(MetadataError::TooLarge(_, 10))));
+    }
+
+    #[tokio::test]
+    async fn test_guest_attributes_not_enabled() {
+        let metadata = CloudMetadata::gcp_with_base_url("http://localhost:1234");
+        assert!(matches!(
+            metadata.gcp_guest_attributes(),
+            Err(MetadataError::NotSupported)
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_guest_attributes_not_supported() {
+        let metadata = CloudMetadata::aws_with_base_url("http://localhost:1234")
+            .with_gcp_guest_attributes(true);
+        assert!(matches!(
+            metadata.gcp_guest_attributes(),
+            Err(MetadataError::NotSupported)
+        ));
+    }
+}
+
+// =============================================================================
+// GCP Tree Tests
+// =============================================================================
+
+mod gcp_tree {
+    use super::*;
+
+    use std::collections::BTreeMap;
+
+    async fn setup_tree_mock(server: &MockServer) {
+        let instance = serde_json::json!({
+            "id": 5678901234567890_u64,
+            "name": "worker-1",
+            "zone": "projects/123456789012/zones/us-central1-a",
+            "attributes": {
+                "config": "{\"db_host\": \"postgres.internal\", \"port\": 5432}",
+                "role": "worker"
+            },
+            "tags": ["http-server"]
+        });
+        Mock::given(method("GET"))
+            .and(path("/computeMetadata/v1/instance/"))
+            .and(query_param("recursive", "true"))
+            .and(query_param("alt", "json"))
+            .and(header("Metadata-Flavor", "Google"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(instance))
+            .expect(1)
+            .mount(server)
+            .await;
+    }
+
+    #[tokio::test]
+    async fn test_tree_value() {
+        let server = MockServer::start().await;
+        setup_tree_mock(&server).await;
+
+        let metadata = CloudMetadata::gcp_with_base_url(&server.uri());
+        let tree: serde_json::Value = metadata.tree("instance").await.unwrap();
+
+        assert_eq!(tree["name"], "worker-1");
+        assert_eq!(tree["attributes"]["role"], "worker");
+    }
+
+    #[tokio::test]
+    async fn test_tree_typed() {
+        #[derive(Debug, Deserialize)]
+        struct Instance {
+            id: u64,
+            name: String,
+            attributes: BTreeMap<String, String>,
+        }
+
+        let server = MockServer::start().await;
+        setup_tree_mock(&server).await;
+
+        let metadata = CloudMetadata::gcp_with_base_url(&server.uri());
+        let instance: Instance = metadata.tree("/instance/").await.unwrap();
+
+        assert_eq!(instance.id, 5678901234567890);
+        assert_eq!(instance.name, "worker-1");
+        assert_eq!(instance.attributes.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_tree_not_supported() {
+        let metadata = CloudMetadata::aws_with_base_url("http://localhost:1234");
+        let result = metadata.tree::<serde_json::Value>("instance").await;
+        assert!(matches!(result, Err(MetadataError::NotSupported)));
+    }
+}
+
+// =============================================================================
+// User-Data Decoding Tests
+// =============================================================================
+
+mod user_data_decoding {
+    use super::*;
+
+    use std::io::Write;
+
+    use cloud_metadata::UserDataKind;
+    use flate2::write::GzEncoder;
+    use flate2::Compression;
+
+    fn gzip(data: &[u8]) -> Vec<u8> {
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(data).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    async fn setup_gcp_attribute(server: &MockServer, value: String) {
+        Mock::given(method("GET"))
+            .and(path("/computeMetadata/v1/instance/attributes/user-data"))
+            .and(header("Metadata-Flavor", "Google"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(value))
+            .mount(server)
+            .await;
+    }
+
+    #[tokio::test]
+    async fn test_gzip_json() {
+        let server = MockServer::start().await;
+        let config = br#"{"db_host": "postgres.internal", "port": 5432}"#;
+        setup_aws_user_data(&server, gzip(config)).await;
+
+        let metadata = CloudMetadata::aws_with_base_url(&server.uri());
+        assert!(metadata.custom_json::<TestConfig>("").await.is_err());
+
+        let metadata = metadata.with_user_data_decoding(true);
+        let config: TestConfig = metadata.custom_json("").await.unwrap();
+        assert_eq!(config.db_host, "postgres.internal");
+        assert_eq!(
+            metadata.custom_data_kind("").await.unwrap(),
+            UserDataKind::Json
+        );
+    }
+
+    #[tokio::test]
+    async fn test_base64_gzip_cloud_config() {
+        let server = MockServer::start().await;
+        let cloud_config = b"#cloud-config\npackages:\n  - jq\n";
+        setup_gcp_attribute(&server, STANDARD.encode(gzip(cloud_config))).await;
+
+        let metadata =
+            CloudMetadata::gcp_with_base_url(&server.uri()).with_user_data_decoding(true);
+
+        let text = metadata.custom_text("user-data").await.unwrap();
+        assert_eq!(text.as_bytes(), cloud_config);
+        assert_eq!(
+            metadata.custom_data_kind("user-data").await.unwrap(),
+            UserDataKind::CloudConfig
+        );
+    }
+
+    #[tokio::test]
+    async fn test_max_size_limits_decompressed_size() {
+        let server = MockServer::start().await;
+        let bomb = gzip(&vec![b'{'; 1 << 20]);
+        setup_aws_user_data(&server, bomb).await;
+
+        let metadata = CloudMetadata::aws_with_base_url(&server.uri())
+            .with_user_data_decoding(true)
+            .with_max_size(16 * 1024);
+        let result = metadata.custom_data("").await;
+
+        assert!(matches!(result, Err(MetadataError::TooLarge(16385, 16384))));
+    }
+
+    #[tokio::test]
+    async fn test_plain_script_unchanged() {
+        let server = MockServer::start().await;
+        let script = b"#!/bin/sh\necho hello\n".to_vec();
+        setup_aws_user_data(&server, script.clone()).await;
+
+        let metadata =
+            CloudMetadata::aws_with_base_url(&server.uri()).with_user_data_decoding(true);
+
+        assert_eq!(metadata.custom_data("").await.unwrap(), script);
+        assert_eq!(
+            metadata.custom_data_kind("").await.unwrap(),
+            UserDataKind::ShellScript
+        );
+    }
+}
+
+// =============================================================================
+// User-Data Multipart Tests
+// =============================================================================
+
+mod user_data_parts {
+    use super::*;
+
+    use cloud_metadata::UserDataPart;
+
+    const USER_DATA: &str = "Content-Type: multipart/mixed; boundary=\"BOUNDARY\"\n\
+MIME-Version: 1.0\n\
+\n\
+--BOUNDARY\n\
+Content-Type: text/cloud-config; charset=\"us-ascii\"\n\
+Content-Disposition: attachment; filename=\"cloud-config.txt\"\n\
+\n\
+#cloud-config\n\
+packages: [jq]\n\
+\n\
+--BOUNDARY\n\
+Content-Type: application/json\n\
+Content-Transfer-Encoding: base64\n\
+Content-Disposition: attachment; filename=\"app-config.json\"\n\
+\n\
+eyJkYl9ob3N0IjogInBvc3RncmVzLmludGVybmFsIiwgInBvcnQiOiA1NDMyfQ==\n\
+--BOUNDARY--\n";
+
+    #[tokio::test]
+    async fn test_select_part_by_filename() {
+        let server = MockServer::start().await;
+        setup_aws_user_data(&server, USER_DATA).await;
+
+        let metadata = CloudMetadata::aws_with_base_url(&server.uri());
+        let config: TestConfig = metadata.custom_json("app-config.json").await.unwrap();
+
+        assert_eq!(
+            config,
+            TestConfig {
+                db_host: "postgres.internal".to_string(),
+                port: 5432,
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn test_select_part_by_content_type() {
+        let server = MockServer::start().await;
+        setup_aws_user_data(&server, USER_DATA).await;
+
+        let metadata = CloudMetadata::aws_with_base_url(&server.uri());
+        let text = metadata.custom_text("text/cloud-config").await.unwrap();
+
+        assert_eq!(text, "#cloud-config\npackages: [jq]\n");
+    }
+
+    #[tokio::test]
+    async fn test_unknown_key_returns_whole_user_data() {
+        let server = MockServer::start().await;
+        setup_aws_user_data(&server, USER_DATA).await;
+
+        let metadata = CloudMetadata::aws_with_base_url(&server.uri());
+        let text = metadata.custom_text("missing").await.unwrap();
+
+        assert_eq!(text, USER_DATA);
+    }
+
+    #[tokio::test]
+    async fn test_parts() {
+        let server = MockServer::start().await;
+        setup_aws_user_data(&server, USER_DATA).await;
+
+        let metadata = CloudMetadata::aws_with_base_url(&server.uri());
+        let parts = metadata.parts("").await.unwrap();
+
+        let names: Vec<_> = parts
+            .iter()
+            .map(|part| (part.filename.as_deref(), part.content_type.as_str()))
+            .collect();
+        assert_eq!(
+            names,
+            vec![
+                (Some("cloud-config.txt"), "text/cloud-config"),
+                (Some("app-config.json"), "application/json"),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_parts_not_multipart() {
+        let server = MockServer::start().await;
+        setup_aws_user_data(&server, "#!/bin/sh\necho hello\n").await;
+
+        let metadata = CloudMetadata::aws_with_base_url(&server.uri());
+        let parts = metadata.parts("").await.unwrap();
+
+        assert_eq!(
+            parts,
+            vec![UserDataPart {
+                filename: None,
+                content_type: "text/x-shellscript".to_string(),
+                data: b"#!/bin/sh\necho hello\n".to_vec(),
+            }]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_gcp_key_is_attribute_name() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/computeMetadata/v1/instance/attributes/user-data"))
+            .and(header("Metadata-Flavor", "Google"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(USER_DATA))
+            .mount(&server)
+            .await;
+
+        let metadata = CloudMetadata::gcp_with_base_url(&server.uri());
+
+        let text = metadata.custom_text("user-data").await.unwrap();
+        assert_eq!(text, USER_DATA);
+        let parts = metadata.parts("user-data").await.unwrap();
+        assert_eq!(parts.len(), 2);
+    }
+}
+
+// =============================================================================
+// Detection Tests
+// =============================================================================
+
+mod detection {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_detect_aws() {
+        let server = MockServer::start().await;
+
+        // AWS probe endpoint (token endpoint with TTL=1)
+        Mock::given(method("PUT"))
+            .and(path("/latest/api/token"))
+            .respond_with(ResponseTemplate::new(200).set_body_string("token"))
+            .mount(&server)
+            .await;
+
+        // Also need the fetch endpoints for after detection
+        Mock::given(method("PUT"))
+            .and(path("/latest/api/token"))
+            .and(header("X-aws-ec2-metadata-token-ttl-seconds", "21600"))
+            .respond_with(ResponseTemplate::new(200).set_body_string("mock-token"))
+            .mount(&server)
+            .await;
+
+        let metadata = CloudMetadata::detect_with_base_url(&server.uri())
+            .await
+            .unwrap();
+        assert_eq!(metadata.provider(), CloudProvider::Aws);
+    }
+
+    #[tokio::test]
+    async fn test_detect_gcp() {
+        let server = MockServer::start().await;
+
+        // GCP probe endpoint
+        Mock::given(method("GET"))
+            .and(path("/computeMetadata/v1"))
+            .and(header("Metadata-Flavor", "Google"))
+            .respond_with(ResponseTemplate::new(200))
+            .mount(&server)
+            .await;
+
+        let metadata = CloudMetadata::detect_with_base_url(&server.uri())
+            .await
+            .unwrap();
+        assert_eq!(metadata.provider(), CloudProvider::Gcp);
+    }
+
+    #[tokio::test]
+    async fn test_detect_azure() {
+        let server = MockServer::start().await;
+
+        // Azure probe endpoint
+        Mock::given(method("GET"))
+            .and(path("/metadata"))
+            .and(query_param("api-version", "2021-02-01"))
+            .and(header("Metadata", "true"))
+            .respond_with(ResponseTemplate::new(200))
+            .mount(&server)
+            .await;
+
+        let metadata = CloudMetadata::detect_with_base_url(&server.uri())
+            .await
             .unwrap();
         assert_eq!(metadata.provider(), CloudProvider::Azure);
     }
@@ -488,6 +3442,26 @@ mod max_size {
         assert!(matches!(result, Err(MetadataError::TooLarge(36, 10))));
     }
 
+    #[tokio::test]
+    async fn test_max_size_rejects_larger_project_attribute() {
+        let server = MockServer::start().await;
+
+        Mock::given(method("GET"))
+            .and(path("/computeMetadata/v1/project/attributes/config"))
+            .respond_with(ResponseTemplate::new(200).set_body_string("a".repeat(36)))
+            .mount(&server)
+            .await;
+
+        let metadata = CloudMetadata::gcp_with_base_url(&server.uri()).with_max_size(10);
+
+        let result = metadata.project_attribute("config").await;
+        assert!(matches!(result, Err(MetadataError::TooLarge(36, 10))));
+
+        let metadata = metadata.with_gcp_attribute_scope(GcpAttributeScope::Project);
+        let result = metadata.custom_data("config").await;
+        assert!(matches!(result, Err(MetadataError::TooLarge(36, 10))));
+    }
+
     #[tokio::test]
     async fn test_max_size_error_message() {
         let err = MetadataError::TooLarge(1000, 100);